@@ -0,0 +1,309 @@
+//! Computer opponent logic
+
+use std::cmp;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{Board, Direction, PlayerID};
+use crate::board::PlayerToken;
+
+/// A whole turn, as (insert edge, insert guide, loose tile orientation, move destination)
+pub type Turn = (Direction, usize, Direction, (usize, usize));
+
+/// How well the computer plays
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiDifficulty {
+    /// Greedy, but with enough noise to miss things
+    Easy,
+    /// Greedy, looking only at its own turn
+    Medium,
+    /// Looks ahead to the leading opponent's best reply to each turn, to keep them away from
+    /// their target
+    Hard,
+}
+
+impl AiDifficulty {
+    /// Most extra distance randomly added to each move on easy
+    const EASY_NOISE: f64 = 4.0;
+    /// How much each step between the leader and their target after their best reply is worth on
+    /// hard, against a step toward the computer's own target
+    const LEADER_WEIGHT: f64 = 0.5;
+}
+
+/// Number of steps between two (row, col) positions, ignoring walls
+fn grid_distance((row_a, col_a): (usize, usize), (row_b, col_b): (usize, usize)) -> usize {
+    let rows = cmp::max(row_a, row_b) - cmp::min(row_a, row_b);
+    let cols = cmp::max(col_a, col_b) - cmp::min(col_a, col_b);
+    rows + cols
+}
+
+/// Scores how far the given position is from the player's target, lower being closer
+///
+/// A target the maze connects to is as far as the walk there. One it doesn't connect to yet is
+/// farther than any walk, and then the closer it is on the grid the better, since a later insert
+/// is more likely to open the way.
+fn target_distance(board: &Board, player_id: PlayerID, pos: (usize, usize)) -> f64 {
+    let tiles = (board.width() * board.height()) as f64;
+    let target = match board.target_pos(player_id) {
+        Some(target) => target,
+        // a target pushed off the board is as far away as anything gets
+        None => return tiles + (board.width() + board.height()) as f64,
+    };
+    match board.shortest_path(pos, target) {
+        Some(path) => (path.len() - 1) as f64,
+        None => tiles + grid_distance(pos, target) as f64,
+    }
+}
+
+/// Gets how close to their target the given player can get with their best turn
+fn best_reply_distance(
+    board: &Board,
+    player_id: PlayerID,
+    excluded: Option<(Direction, usize)>,
+) -> f64 {
+    let mut best = None;
+    for insert in board.insert_positions() {
+        if Some(insert) == excluded {
+            continue;
+        }
+        for orientation in board.loose_tile.shape.distinct_orientations() {
+            let hypothetical = board.after_insert(insert, orientation);
+            let moves = hypothetical.movable_coords(hypothetical.player_pos(player_id));
+            for move_to in moves {
+                let distance = target_distance(&hypothetical, player_id, move_to);
+                if distance == 0.0 {
+                    return 0.0;
+                }
+                best = match best {
+                    Some(best) if best <= distance => Some(best),
+                    _ => Some(distance),
+                };
+            }
+        }
+    }
+    // with no reply at all, the opponent stays where they are
+    match best {
+        Some(best) => best,
+        None => target_distance(board, player_id, board.player_pos(player_id)),
+    }
+}
+
+/// Gets the highest-scoring player other than the given one, if there is one
+fn leading_opponent(board: &Board, player_id: PlayerID) -> Option<PlayerID> {
+    board
+        .player_tokens
+        .values()
+        .filter(|token| token.player_id != player_id)
+        .fold(None, |leader: Option<&PlayerToken>, token| match leader {
+            Some(leader) if leader.score >= token.score => Some(leader),
+            _ => Some(token),
+        })
+        .map(|token| token.player_id)
+}
+
+/// Picks a turn for the given player, if any insert is allowed
+///
+/// Every insert position (except `excluded`, such as the one undoing the last insert) and loose
+/// tile orientation is tried on a copy of the board, and (except on easy, where noise can hide it)
+/// the first one that lets the player reach their target wins outright; otherwise the move that
+/// ends up closest to the target along the maze is chosen. On hard, each insert is also weighed by
+/// how close the leading opponent could get to their target with their best reply to it. The
+/// random number generator is only used on easy, so seeding it makes easy turns reproducible.
+pub fn best_turn<R: Rng + ?Sized>(
+    board: &Board,
+    player_id: PlayerID,
+    difficulty: AiDifficulty,
+    excluded: Option<(Direction, usize)>,
+    rng: &mut R,
+) -> Option<Turn> {
+    let leader = match difficulty {
+        AiDifficulty::Hard => leading_opponent(board, player_id),
+        _ => None,
+    };
+    let mut best: Option<(f64, Turn)> = None;
+    let inserts = board
+        .insert_positions()
+        .into_iter()
+        .filter(|&insert| Some(insert) != excluded);
+    for (dir, idx) in inserts {
+        for orientation in board.loose_tile.shape.distinct_orientations() {
+            let hypothetical = board.after_insert((dir, idx), orientation);
+            // the reply can't undo this insert, and moving the token doesn't change the maze
+            let penalty = match leader {
+                Some(leader) => {
+                    let reply_excluded = Some((dir.opposite(), idx));
+                    let reply = best_reply_distance(&hypothetical, leader, reply_excluded);
+                    -AiDifficulty::LEADER_WEIGHT * reply
+                }
+                None => 0.0,
+            };
+            // sorted so ties always break the same way
+            let mut moves = hypothetical
+                .movable_coords(hypothetical.player_pos(player_id))
+                .into_iter()
+                .collect::<Vec<_>>();
+            moves.sort();
+            for move_to in moves {
+                let turn = (dir, idx, orientation, move_to);
+                let distance = target_distance(&hypothetical, player_id, move_to);
+                if distance == 0.0 && difficulty != AiDifficulty::Easy {
+                    return Some(turn);
+                }
+                let mut score = distance + penalty;
+                if difficulty == AiDifficulty::Easy {
+                    score += rng.gen_range(0.0, AiDifficulty::EASY_NOISE);
+                }
+                let is_better = match best {
+                    Some((best_score, _)) => score < best_score,
+                    None => true,
+                };
+                if is_better {
+                    best = Some((score, turn));
+                }
+            }
+        }
+    }
+    best.map(|(_, turn)| turn)
+}
+
+/// Picks where the given player moves on the board as it stands, such as once their insert has
+/// gone in
+///
+/// Moves are weighed like the moves of `best_turn`, so on medium and hard this is the move
+/// `best_turn` picked for the insert that made the board.
+pub fn best_move<R: Rng + ?Sized>(
+    board: &Board,
+    player_id: PlayerID,
+    difficulty: AiDifficulty,
+    rng: &mut R,
+) -> (usize, usize) {
+    let from = board.player_pos(player_id);
+    let mut moves = board.movable_coords(from).into_iter().collect::<Vec<_>>();
+    moves.sort();
+    let mut best: Option<(f64, (usize, usize))> = None;
+    for move_to in moves {
+        let mut score = target_distance(board, player_id, move_to);
+        if difficulty == AiDifficulty::Easy {
+            score += rng.gen_range(0.0, AiDifficulty::EASY_NOISE);
+        } else if score == 0.0 {
+            return move_to;
+        }
+        let is_better = match best {
+            Some((best_score, _)) => score < best_score,
+            None => true,
+        };
+        if is_better {
+            best = Some((score, move_to));
+        }
+    }
+    best.map_or(from, |(_, move_to)| move_to)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::convert::TryInto;
+
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::Player;
+    use crate::colors::Color;
+
+    /// Builds a board from a tile spec with each player's token at the given (row, col)
+    fn board(spec: &str, tokens: &[(PlayerID, (usize, usize))]) -> Board {
+        let cells = Board::parse_board(spec);
+        let players = tokens
+            .iter()
+            .map(|&(id, _)| (id, Player::new(format!("Player {}", id), Color(0.5, 0.5, 0.5), id)))
+            .collect::<BTreeMap<_, _>>();
+        let starts = tokens.iter().cloned().collect();
+        let (width, height) = (cells[0].len(), cells.len());
+        let mut board = Board::with_layout(width, height, &players, starts, BTreeMap::new())
+            .unwrap();
+        board.cells = cells;
+        board
+    }
+
+    #[test]
+    fn finds_one_move_win() {
+        let mut board = board("─│─ │││ │││", &[(1, (0, 0))]);
+        board.cells[0][2].whose_target = Some(1);
+        board.loose_tile = '│'.try_into().unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let winning = (Direction::North, 0, Direction::East, (0, 2));
+        for &difficulty in &[AiDifficulty::Medium, AiDifficulty::Hard] {
+            assert_eq!(best_turn(&board, 1, difficulty, None, &mut rng), Some(winning));
+        }
+        let excluded = Some((Direction::North, 0));
+        let turn = best_turn(&board, 1, AiDifficulty::Medium, excluded, &mut rng).unwrap();
+        assert_ne!((turn.0, turn.1), (Direction::North, 0));
+    }
+
+    #[test]
+    fn easy_can_miss_a_win_hard_never_does() {
+        let mut maze = board("─│─ │││ │││", &[(1, (0, 0))]);
+        maze.cells[0][2].whose_target = Some(1);
+        maze.loose_tile = '│'.try_into().unwrap();
+        let winning = (Direction::North, 0, Direction::East, (0, 2));
+        let turn = |difficulty, seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            best_turn(&maze, 1, difficulty, None, &mut rng).unwrap()
+        };
+        // the noise on easy can outweigh the win, so this seed leaves the target where it is
+        let (_, _, _, easy_move) = turn(AiDifficulty::Easy, 3);
+        assert_ne!(easy_move, (0, 2));
+        for seed in 0..20 {
+            assert_eq!(turn(AiDifficulty::Hard, seed), winning);
+        }
+    }
+
+    #[test]
+    fn hard_keeps_leader_from_winning_reply() {
+        let mut board = board("┌┴┤ └┤┤ ─┐┬", &[(1, (0, 0)), (2, (2, 2))]);
+        board.cells[2][0].whose_target = Some(1);
+        board.cells[0][2].whose_target = Some(2);
+        board.player_tokens.get_mut(&2).unwrap().score = 3;
+        board.loose_tile = '│'.try_into().unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let reply_after = |(dir, idx, orientation, _): Turn| {
+            let hypothetical = board.after_insert((dir, idx), orientation);
+            best_reply_distance(&hypothetical, 2, Some((dir.opposite(), idx)))
+        };
+        let medium = best_turn(&board, 1, AiDifficulty::Medium, None, &mut rng).unwrap();
+        assert_eq!(reply_after(medium), 0.0);
+        let hard = best_turn(&board, 1, AiDifficulty::Hard, None, &mut rng).unwrap();
+        assert!(reply_after(hard) > 0.0);
+    }
+
+    #[test]
+    fn easy_is_reproducible_with_seed() {
+        let mut board = board("┌┴┤ └┤┤ ─┐┬", &[(1, (0, 0))]);
+        board.cells[2][0].whose_target = Some(1);
+        board.loose_tile = '│'.try_into().unwrap();
+        let turn = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            best_turn(&board, 1, AiDifficulty::Easy, None, &mut rng)
+        };
+        assert_eq!(turn(7), turn(7));
+    }
+
+    #[test]
+    fn best_move_matches_the_move_of_the_best_turn() {
+        let mut maze = board("┌┴┤ └┤┤ ─┐┬", &[(1, (0, 0))]);
+        maze.cells[2][0].whose_target = Some(1);
+        maze.loose_tile = '│'.try_into().unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for &difficulty in &[AiDifficulty::Medium, AiDifficulty::Hard] {
+            let (dir, idx, orientation, move_to) =
+                best_turn(&maze, 1, difficulty, None, &mut rng).unwrap();
+            let inserted = maze.after_insert((dir, idx), orientation);
+            assert_eq!(best_move(&inserted, 1, difficulty, &mut rng), move_to);
+        }
+        // a token with nowhere to go stays put
+        let boxed_in = board("│││ │─│ │││", &[(1, (1, 1))]);
+        assert_eq!(best_move(&boxed_in, 1, AiDifficulty::Easy, &mut rng), (1, 1));
+    }
+}
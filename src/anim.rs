@@ -1,10 +1,10 @@
-use std::collections::VecDeque;
-use std::f64::consts::FRAC_PI_2;
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::{FRAC_PI_2, PI};
 use std::sync::{Arc, Mutex, RwLock};
 
 use serde::{Deserialize, Serialize};
 
-use crate::Direction;
+use crate::{Direction, PlayerID};
 use crate::net::{Message, MetaMessage};
 
 /// Tracks state of the target stripe animation
@@ -49,11 +49,16 @@ impl LooseRotateState {
         LooseRotateState { angle: 0.0 }
     }
 
-    fn reset(&mut self, dir: RotateDir) {
-        self.angle += match dir {
-            RotateDir::CW => -FRAC_PI_2,
-            RotateDir::CCW => FRAC_PI_2,
-        };
+    /// Starts drawing the tile back at its old orientation, on top of any rotation still playing
+    fn reset(&mut self, from: Direction, to: Direction) {
+        // take the short way around
+        let mut delta = from.rad() - to.rad();
+        if delta > PI {
+            delta -= 2.0 * PI;
+        } else if delta <= -PI {
+            delta += 2.0 * PI;
+        }
+        self.angle += delta;
     }
 
     fn advance_by(&mut self, ticks: f64) {
@@ -70,6 +75,29 @@ impl LooseRotateState {
     }
 }
 
+/// Tracks state of the loose tile gently bobbing while it waits to be inserted
+pub struct LooseBobState {
+    elapsed: f64,
+}
+
+impl LooseBobState {
+    fn new() -> LooseBobState {
+        LooseBobState { elapsed: 0.0 }
+    }
+
+    fn advance_by(&mut self, ticks: f64) {
+        self.elapsed += ticks;
+    }
+
+    /// Gets how far down to draw the loose tile, swinging by `amplitude` every `period` seconds
+    pub fn offset(&self, amplitude: f64, period: f64) -> f64 {
+        if period <= 0.0 {
+            return 0.0;
+        }
+        amplitude * (2.0 * PI * self.elapsed / period).sin()
+    }
+}
+
 /// Tracks state of loose tile insert animation
 pub struct LooseInsertState {
     /// Direction in which the tiles are currently offset
@@ -79,6 +107,8 @@ pub struct LooseInsertState {
     pub distance_left: f64,
     /// Row/column of the offset tiles
     coordinate: usize,
+    /// Whether the slide is playing ahead of the board actually shifting
+    pending: bool,
 }
 
 impl LooseInsertState {
@@ -89,13 +119,28 @@ impl LooseInsertState {
             offset_dir: Direction::North,
             distance_left: 0.0,
             coordinate: 0,
+            pending: false,
         }
     }
 
     fn reset(&mut self, dir: Direction, coord: usize) {
+        // if this insert was already slid into place, the board has just caught up
+        if self.pending && self.offset_dir == dir && self.coordinate == coord {
+            self.pending = false;
+            self.distance_left = 0.0;
+            return;
+        }
+        self.offset_dir = dir;
+        self.distance_left = 1.0;
+        self.coordinate = coord;
+        self.pending = false;
+    }
+
+    fn reset_pending(&mut self, dir: Direction, coord: usize) {
         self.offset_dir = dir;
         self.distance_left = 1.0;
         self.coordinate = coord;
+        self.pending = true;
     }
 
     fn advance_by(&mut self, ticks: f64) {
@@ -105,8 +150,36 @@ impl LooseInsertState {
         self.distance_left = (self.distance_left - ticks / Self::LENGTH).max(0.0);
     }
 
+    /// Checks if the slide has finished playing
+    pub fn is_settled(&self) -> bool {
+        self.distance_left == 0.0
+    }
+
+    /// Fraction of a tile by which the affected tiles are offset along `offset_dir`
+    ///
+    /// Settling inserts start a tile behind and slide forward, pending inserts start in place and
+    /// slide a tile forward before the board shifts underneath them.
+    pub fn offset(&self) -> f64 {
+        if self.pending {
+            self.distance_left - 1.0
+        } else {
+            self.distance_left
+        }
+    }
+
+    /// Scale along the shift axis, squashing as the slide starts and stretching as it settles
+    ///
+    /// `intensity` is the largest fraction by which tiles deform, and zero turns the effect off.
+    pub fn squash(&self, intensity: f64) -> f64 {
+        if self.is_settled() {
+            return 1.0;
+        }
+        let progress = 1.0 - self.distance_left;
+        1.0 - intensity * (progress * 2.0 * PI).sin()
+    }
+
     pub fn applies_to_pos(&self, (row, col): (usize, usize)) -> bool {
-        if self.distance_left == 0.0 {
+        if self.offset() == 0.0 {
             return false;
         }
         let should_be_coord = match self.offset_dir {
@@ -116,40 +189,303 @@ impl LooseInsertState {
         should_be_coord == self.coordinate
     }
 
-    pub fn applies_to_loose(&self, (dir, guide_idx): (Direction, usize)) -> bool {
-        if self.distance_left == 0.0 {
+    /// Checks if the loose tile at the guide on the given edge shifting the given line is sliding
+    pub fn applies_to_loose(&self, dir: Direction, line: usize) -> bool {
+        if self.offset() == 0.0 {
             return false;
         }
-        if dir == self.offset_dir || dir == self.offset_dir * Direction::South {
-            self.coordinate == 2 * guide_idx + 1
+        if dir == self.offset_dir || dir == self.offset_dir.opposite() {
+            self.coordinate == line
         } else {
             false
         }
     }
 }
 
+/// Tracks state of the screen edge flash when the local player's turn starts
+pub struct TurnFlashState {
+    left: f64,
+}
+
+impl TurnFlashState {
+    const LENGTH: f64 = 0.75;
+
+    fn new() -> TurnFlashState {
+        TurnFlashState { left: 0.0 }
+    }
+
+    /// Starts the flash over from full strength
+    pub fn trigger(&mut self) {
+        self.left = Self::LENGTH;
+    }
+
+    fn advance_by(&mut self, ticks: f64) {
+        self.left = (self.left - ticks).max(0.0);
+    }
+
+    /// Gets how strongly to draw the flash, fading from 1 to 0
+    pub fn alpha(&self) -> f64 {
+        self.left / Self::LENGTH
+    }
+}
+
+/// Tracks state of the marker fading out at the most recent insert
+pub struct LastInsertState {
+    left: f64,
+}
+
+impl LastInsertState {
+    const LENGTH: f64 = 2.0;
+
+    fn new() -> LastInsertState {
+        LastInsertState { left: 0.0 }
+    }
+
+    fn trigger(&mut self) {
+        self.left = Self::LENGTH;
+    }
+
+    fn advance_by(&mut self, ticks: f64) {
+        self.left = (self.left - ticks).max(0.0);
+    }
+
+    /// Gets how strongly to draw the marker, fading from 1 to 0
+    pub fn alpha(&self) -> f64 {
+        self.left / Self::LENGTH
+    }
+}
+
+/// Tracks state of the dashed ring turning around the token about to move
+pub struct FocusRingState {
+    elapsed: f64,
+}
+
+impl FocusRingState {
+    const PERIOD: f64 = 1.5;
+
+    fn new() -> FocusRingState {
+        FocusRingState { elapsed: 0.0 }
+    }
+
+    fn advance_by(&mut self, ticks: f64) {
+        self.elapsed = (self.elapsed + ticks) % Self::PERIOD;
+    }
+
+    /// Gets how far around the ring the dashes have turned, from 0 to 1
+    pub fn pct_offset(&self) -> f64 {
+        self.elapsed / Self::PERIOD
+    }
+}
+
+/// Tracks state of the board fading in tile by tile when a game starts
+pub struct BoardIntroState {
+    elapsed: f64,
+    duration: f64,
+}
+
+impl BoardIntroState {
+    fn new() -> BoardIntroState {
+        BoardIntroState {
+            elapsed: 0.0,
+            duration: 0.0,
+        }
+    }
+
+    /// Starts fading the board in over the given number of seconds
+    pub fn start(&mut self, duration: f64) {
+        self.elapsed = 0.0;
+        self.duration = duration;
+    }
+
+    fn advance_by(&mut self, ticks: f64) {
+        self.elapsed = (self.elapsed + ticks).min(self.duration);
+    }
+
+    /// Checks if every tile is fully visible
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Gets the opacity of the tile at (row, col) on a board of (width, height)
+    ///
+    /// Each tile fades in over half the duration, starting later the further it is from the
+    /// northwest corner, so the far corner finishes right at the end.
+    pub fn tile_alpha(&self, (row, col): (usize, usize), (width, height): (usize, usize)) -> f64 {
+        if self.is_done() {
+            return 1.0;
+        }
+        let fade = self.duration / 2.0;
+        let furthest = (width + height).saturating_sub(2).max(1);
+        let delay = (row + col) as f64 / furthest as f64 * (self.duration - fade);
+        let alpha = ((self.elapsed - delay) / fade).max(0.0);
+        alpha.min(1.0)
+    }
+}
+
+/// Trail from where one token moved from to where it ended up, both (row, col)
+struct MoveTrail {
+    from: (usize, usize),
+    to: (usize, usize),
+    left: f64,
+}
+
+/// Tracks state of the trails left behind by tokens that just moved
+pub struct MoveTrailState {
+    trails: HashMap<PlayerID, MoveTrail>,
+}
+
+impl MoveTrailState {
+    const LENGTH: f64 = 1.5;
+
+    fn new() -> MoveTrailState {
+        MoveTrailState {
+            trails: HashMap::new(),
+        }
+    }
+
+    /// Notes that the given player's token moved between (row, col) positions, starting a trail if that's new
+    pub fn observe(&mut self, player_id: PlayerID, from: (usize, usize), to: (usize, usize)) {
+        let is_new = match self.trails.get(&player_id) {
+            Some(trail) => trail.from != from || trail.to != to,
+            None => true,
+        };
+        if is_new {
+            let left = Self::LENGTH;
+            self.trails.insert(player_id, MoveTrail { from, to, left });
+        }
+    }
+
+    fn advance_by(&mut self, ticks: f64) {
+        for trail in self.trails.values_mut() {
+            trail.left = (trail.left - ticks).max(0.0);
+        }
+    }
+
+    /// Gets how strongly to draw the given player's trail, fading from 1 to 0
+    pub fn alpha(&self, player_id: PlayerID) -> f64 {
+        match self.trails.get(&player_id) {
+            Some(trail) => trail.left / Self::LENGTH,
+            None => 0.0,
+        }
+    }
+}
+
+/// One player's score as drawn, counting toward their real score
+struct ScoreCount {
+    displayed: f64,
+    target: f64,
+    rate: f64,
+}
+
+/// Tracks state of the scores in the player list counting up
+pub struct ScoreCountState {
+    counts: HashMap<PlayerID, ScoreCount>,
+}
+
+impl ScoreCountState {
+    const LENGTH: f64 = 0.5;
+
+    fn new() -> ScoreCountState {
+        ScoreCountState {
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Sets the score the given player's count should reach, starting it counting if it changed
+    ///
+    /// A player seen for the first time starts at their score rather than counting up from zero.
+    pub fn set_target(&mut self, player_id: PlayerID, score: u8) {
+        let target = f64::from(score);
+        let count = self.counts.entry(player_id).or_insert(ScoreCount {
+            displayed: target,
+            target,
+            rate: 0.0,
+        });
+        if count.target != target {
+            count.target = target;
+            count.rate = (target - count.displayed).abs() / Self::LENGTH;
+        }
+    }
+
+    fn advance_by(&mut self, ticks: f64) {
+        for count in self.counts.values_mut() {
+            let step = count.rate * ticks;
+            if count.displayed < count.target {
+                count.displayed = (count.displayed + step).min(count.target);
+            } else {
+                count.displayed = (count.displayed - step).max(count.target);
+            }
+        }
+    }
+
+    /// Gets the score to draw for the given player, rounded, or their real score if it isn't tracked yet
+    pub fn displayed(&self, player_id: PlayerID, score: u8) -> u8 {
+        match self.counts.get(&player_id) {
+            Some(count) => count.displayed.round() as u8,
+            None => score,
+        }
+    }
+}
+
 /// Tracks state of all currently running animations
 pub struct AnimGlobalState {
     pub target_stripe: TargetStripeState,
     pub loose_rotate: LooseRotateState,
+    pub loose_bob: LooseBobState,
     pub loose_insert: LooseInsertState,
+    pub turn_flash: TurnFlashState,
+    pub last_insert: LastInsertState,
+    pub focus_ring: FocusRingState,
+    pub board_intro: BoardIntroState,
+    pub score_count: ScoreCountState,
+    pub move_trail: MoveTrailState,
+    /// How many times faster than normal every animation plays
+    pub speed_multiplier: f64,
+    /// Whether animations only advance through `step_once`, for debugging
+    pub paused: bool,
     net_queue: Option<Arc<Mutex<VecDeque<MetaMessage>>>>,
 }
 
 impl AnimGlobalState {
-    fn new() -> AnimGlobalState {
+    /// Creates a state with no animations running, for drawing without the global state
+    pub fn new() -> AnimGlobalState {
         AnimGlobalState {
             target_stripe: TargetStripeState::new(),
             loose_rotate: LooseRotateState::new(),
+            loose_bob: LooseBobState::new(),
             loose_insert: LooseInsertState::new(),
+            turn_flash: TurnFlashState::new(),
+            last_insert: LastInsertState::new(),
+            focus_ring: FocusRingState::new(),
+            board_intro: BoardIntroState::new(),
+            score_count: ScoreCountState::new(),
+            move_trail: MoveTrailState::new(),
+            speed_multiplier: 1.0,
+            paused: false,
             net_queue: None,
         }
     }
 
     pub fn advance_by(&mut self, ticks: f64) {
+        if self.paused {
+            return;
+        }
+        self.step(ticks);
+    }
+
+    /// Advances every animation, even while paused
+    fn step(&mut self, ticks: f64) {
+        let ticks = ticks * self.speed_multiplier;
         self.target_stripe.advance_by(ticks);
         self.loose_rotate.advance_by(ticks);
+        self.loose_bob.advance_by(ticks);
         self.loose_insert.advance_by(ticks);
+        self.turn_flash.advance_by(ticks);
+        self.last_insert.advance_by(ticks);
+        self.focus_ring.advance_by(ticks);
+        self.board_intro.advance_by(ticks);
+        self.score_count.advance_by(ticks);
+        self.move_trail.advance_by(ticks);
     }
 
     pub fn set_send(&mut self, send: Arc<Mutex<VecDeque<MetaMessage>>>) {
@@ -158,8 +494,13 @@ impl AnimGlobalState {
 
     pub fn apply(&mut self, msg: AnimSync) {
         match msg {
-            AnimSync::Rotate(dir) => self.loose_rotate.reset(dir),
-            AnimSync::Insert(dir, x) => self.loose_insert.reset(dir, x),
+            AnimSync::Rotate(from, to) => self.loose_rotate.reset(from, to),
+            AnimSync::Insert(dir, x) => {
+                // every client sees this once per insert, so it's when to mark the insert
+                self.loose_insert.reset(dir, x);
+                self.last_insert.trigger();
+            }
+            AnimSync::Slide(dir, x) => self.loose_insert.reset_pending(dir, x),
         }
     }
 
@@ -174,10 +515,168 @@ impl AnimGlobalState {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum AnimSync {
-    Rotate(RotateDir),
+    Rotate(Direction, Direction),
     Insert(Direction, usize),
+    Slide(Direction, usize),
+}
+
+/// Animates the loose tile turning from one orientation to another
+///
+/// The tile's real orientation should already be updated; only the drawn angle catches up.
+pub fn start_loose_rotation(from: Direction, to: Direction) {
+    STATE.write().unwrap().apply_send(AnimSync::Rotate(from, to));
+}
+
+/// Scales the speed of every animation, so 2.0 plays them in half the time
+pub fn set_speed_multiplier(multiplier: f64) {
+    STATE.write().unwrap().speed_multiplier = multiplier;
+}
+
+/// Freezes or unfreezes every animation, for debugging
+pub fn set_paused(paused: bool) {
+    STATE.write().unwrap().paused = paused;
+}
+
+/// Checks if animations are frozen
+pub fn is_paused() -> bool {
+    STATE.read().unwrap().paused
+}
+
+/// Advances every animation by the given number of seconds, even while paused
+pub fn step_once(dt: f64) {
+    STATE.write().unwrap().step(dt);
 }
 
 lazy_static! {
     pub static ref STATE: RwLock<AnimGlobalState> = { RwLock::new(AnimGlobalState::new()) };
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+
+    lazy_static! {
+        static ref STATE_LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    /// Keeps other tests from touching `STATE` until the guard is dropped, since they all share it
+    pub fn lock_state() -> MutexGuard<'static, ()> {
+        STATE_LOCK.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    #[test]
+    fn rotation_starts_at_old_angle_and_ends_at_new() {
+        let mut rotate = LooseRotateState::new();
+        rotate.reset(Direction::North, Direction::East);
+        assert_eq!(rotate.angle, -FRAC_PI_2);
+        rotate.advance_by(LooseRotateState::LENGTH / 2.0);
+        assert!(rotate.angle < 0.0 && rotate.angle > -FRAC_PI_2);
+        rotate.advance_by(LooseRotateState::LENGTH);
+        assert_eq!(rotate.angle, 0.0);
+
+        // three quarters one way is a quarter the other
+        rotate.reset(Direction::North, Direction::West);
+        assert!((rotate.angle - FRAC_PI_2).abs() < 1e-9);
+        rotate.advance_by(LooseRotateState::LENGTH);
+        assert_eq!(rotate.angle, 0.0);
+    }
+
+    #[test]
+    fn speed_multiplier_scales_progress() {
+        let step = LooseInsertState::LENGTH / 4.0;
+        let mut normal = AnimGlobalState::new();
+        normal.apply(AnimSync::Insert(Direction::North, 1));
+        normal.advance_by(step);
+        assert!((normal.loose_insert.distance_left - 0.75).abs() < 1e-9);
+
+        let mut fast = AnimGlobalState::new();
+        fast.speed_multiplier = 2.0;
+        fast.apply(AnimSync::Insert(Direction::North, 1));
+        fast.advance_by(step);
+        assert!((fast.loose_insert.distance_left - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn turn_flash_fades_out() {
+        let mut state = AnimGlobalState::new();
+        assert_eq!(state.turn_flash.alpha(), 0.0);
+        state.turn_flash.trigger();
+        assert_eq!(state.turn_flash.alpha(), 1.0);
+        state.advance_by(0.25);
+        assert!(state.turn_flash.alpha() > 0.0 && state.turn_flash.alpha() < 1.0);
+        state.advance_by(1.0);
+        assert_eq!(state.turn_flash.alpha(), 0.0);
+    }
+
+    #[test]
+    fn board_intro_fades_tiles_in_from_the_corner() {
+        let dims = (7, 7);
+        let mut intro = BoardIntroState::new();
+        assert_eq!(intro.tile_alpha((6, 6), dims), 1.0);
+
+        intro.start(2.0);
+        assert_eq!(intro.tile_alpha((0, 0), dims), 0.0);
+        intro.advance_by(0.5);
+        assert!((intro.tile_alpha((0, 0), dims) - 0.5).abs() < 1e-9);
+        assert_eq!(intro.tile_alpha((6, 6), dims), 0.0);
+        assert!(intro.tile_alpha((3, 3), dims) < intro.tile_alpha((1, 2), dims));
+        intro.advance_by(1.0);
+        assert_eq!(intro.tile_alpha((0, 0), dims), 1.0);
+        assert!((intro.tile_alpha((6, 6), dims) - 0.5).abs() < 1e-9);
+        intro.advance_by(0.5);
+        assert!(intro.is_done());
+        assert_eq!(intro.tile_alpha((6, 6), dims), 1.0);
+    }
+
+    #[test]
+    fn score_count_ticks_up_to_the_new_score() {
+        let mut count = ScoreCountState::new();
+        assert_eq!(count.displayed(1, 2), 2);
+        count.set_target(1, 2);
+        assert_eq!(count.displayed(1, 2), 2);
+
+        count.set_target(1, 6);
+        assert_eq!(count.displayed(1, 6), 2);
+        count.advance_by(ScoreCountState::LENGTH / 2.0);
+        assert_eq!(count.displayed(1, 6), 4);
+        count.advance_by(ScoreCountState::LENGTH);
+        assert_eq!(count.displayed(1, 6), 6);
+        count.advance_by(ScoreCountState::LENGTH);
+        assert_eq!(count.displayed(1, 6), 6);
+    }
+
+    #[test]
+    fn stepping_while_paused_advances_insert() {
+        let _lock = lock_state();
+        STATE.write().unwrap().apply(AnimSync::Insert(Direction::East, 3));
+        set_paused(true);
+        assert!(is_paused());
+        STATE.write().unwrap().advance_by(1.0);
+        assert_eq!(STATE.read().unwrap().loose_insert.distance_left, 1.0);
+        step_once(LooseInsertState::LENGTH / 4.0);
+        let distance_left = STATE.read().unwrap().loose_insert.distance_left;
+        assert!((distance_left - 0.75).abs() < 1e-9);
+
+        set_paused(false);
+        assert!(!is_paused());
+        step_once(1.0);
+        assert!(STATE.read().unwrap().loose_insert.is_settled());
+    }
+
+    #[test]
+    fn squash_settles_back_to_full_size() {
+        let mut insert = LooseInsertState::new();
+        assert_eq!(insert.squash(0.2), 1.0);
+        insert.reset(Direction::South, 1);
+        insert.advance_by(LooseInsertState::LENGTH / 4.0);
+        assert!(insert.squash(0.2) < 1.0);
+        assert_eq!(insert.squash(0.0), 1.0);
+        insert.advance_by(LooseInsertState::LENGTH / 2.0);
+        assert!(insert.squash(0.2) > 1.0);
+        insert.advance_by(LooseInsertState::LENGTH);
+        assert!(insert.is_settled());
+        assert_eq!(insert.squash(0.2), 1.0);
+    }
+}
@@ -1,24 +1,30 @@
 //! Board logic
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::{Direction, Player, PlayerID, Shape, Tile};
 use crate::anim;
-use crate::demo;
 use crate::tutorial;
 
 /// Information about a player's token on the board
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct PlayerToken {
     /// ID of player the token is for
     pub player_id: PlayerID,
     /// Position of token (row, col)
     pub position: (usize, usize),
+    /// Home tile of token (row, col), where it started unless a layout says otherwise
+    pub home: (usize, usize),
     /// Number of targets reached
     pub score: u8,
+    /// Position (row, col) the token last moved from, until the board next shifts
+    pub last_position: Option<(usize, usize)>,
+    /// Turn number on which the token reached the score limit, if it has
+    pub finished_turn: Option<u32>,
 }
 
 impl PlayerToken {
@@ -27,7 +33,10 @@ impl PlayerToken {
         PlayerToken {
             player_id: player.id,
             position,
+            home: position,
             score: 0,
+            last_position: None,
+            finished_turn: None,
         }
     }
 
@@ -37,6 +46,43 @@ impl PlayerToken {
     }
 }
 
+/// Which lines of the board the loose tile can be inserted along
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertRule {
+    /// Only odd rows and columns shift, so tiles where two even lines cross never move
+    OddLinesOnly,
+    /// Every row and column without a fixed tile on it shifts, and `Board::set_insert_rule`
+    /// clears the fixed tiles so every line moves
+    AnyLine,
+}
+
+impl InsertRule {
+    /// Gets how many insert guides an edge with the given number of lines has
+    pub fn guide_count(self, lines: usize) -> usize {
+        match self {
+            InsertRule::OddLinesOnly => lines / 2,
+            InsertRule::AnyLine => lines,
+        }
+    }
+
+    /// Gets the row or column the given insert guide shifts
+    pub fn guide_line(self, guide_idx: usize) -> usize {
+        match self {
+            InsertRule::OddLinesOnly => 2 * guide_idx + 1,
+            InsertRule::AnyLine => guide_idx,
+        }
+    }
+}
+
+/// How the edges of the board connect for token movement
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// Paths stop at the edges of the board
+    Bounded,
+    /// Paths off one edge come back in at the opposite edge, as on a torus
+    Toroidal,
+}
+
 /// Information about board state
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Board {
@@ -44,14 +90,142 @@ pub struct Board {
     pub cells: Vec<Vec<Tile>>,
     /// Loose tile
     pub loose_tile: Tile,
-    /// Loose tile position
-    pub loose_tile_position: (Direction, usize),
+    /// Loose tile position, if it has been moved to an insert guide yet
+    pub loose_tile_position: Option<(Direction, usize)>,
     /// Player tokens
     pub player_tokens: BTreeMap<PlayerID, PlayerToken>,
+    /// Positions (row, col) of treasures any player can claim
+    pub treasures: HashSet<(usize, usize)>,
+    /// Which lines the loose tile can be inserted along
+    pub insert_rule: InsertRule,
+    /// Positions (row, col) of tiles that never move, which block inserts along their lines
+    pub fixed_tiles: HashSet<(usize, usize)>,
+    /// Which sides of the board the loose tile can be inserted from
+    pub allowed_insert_directions: HashSet<Direction>,
+    /// Most steps a token can take in one move, if limited
+    pub max_move_tiles: Option<u32>,
+    /// How the edges of the board connect for token movement
+    pub topology: Topology,
+    /// Positions (row, col) of the cells the last insert shifted, to redraw or animate only those
+    pub last_changed_cells: Vec<(usize, usize)>,
     /// Step in tutorial, if any
     pub tutorial_step: Option<tutorial::TutorialStep>,
 }
 
+/// Changes between two snapshots of the same board, to sync without sending the whole board
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BoardDiff {
+    /// Tiles that changed, with their (row, col) position
+    pub cells: Vec<((usize, usize), Tile)>,
+    /// New loose tile, if it changed
+    pub loose_tile: Option<Tile>,
+    /// New loose tile position, if it changed
+    pub loose_tile_position: Option<Option<(Direction, usize)>>,
+    /// Tokens that moved or scored
+    pub player_tokens: Vec<PlayerToken>,
+    /// New set of treasures, if it changed
+    pub treasures: Option<HashSet<(usize, usize)>>,
+}
+
+/// How evenly a board treats each player's start, where lower variances are fairer
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairnessReport {
+    /// Number of tiles each player can reach from their start
+    pub reachable: BTreeMap<PlayerID, usize>,
+    /// Steps from each player's start to the center region, if they can get there
+    pub center_distance: BTreeMap<PlayerID, Option<usize>>,
+    /// Variance of `reachable` across players
+    pub reachable_variance: f64,
+    /// Variance of `center_distance` across players, counting unreachable as the board's width plus height
+    pub distance_variance: f64,
+}
+
+/// Gets the population variance of the given values
+fn variance(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    values.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / count
+}
+
+/// Version byte at the start of every encoded board
+const ENCODING_VERSION: u8 = 7;
+
+/// Reasons a custom board layout was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The given player has no start tile
+    MissingStart(PlayerID),
+    /// The given player's start or home tile is off the board
+    OutOfBounds(PlayerID),
+    /// The given player starts on the same tile as another player
+    DuplicateStart(PlayerID),
+}
+
+/// Reasons an encoded board could not be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The leading version byte was not one this build understands
+    Version(u8),
+    /// The data ended before the board was complete
+    Truncated,
+    /// Some byte held a value that doesn't mean anything
+    Invalid,
+    /// More data followed the end of the board
+    TrailingData,
+}
+
+/// Narrows a size, position, or count to the single byte `Board::encode` stores it in
+fn encode_byte(value: usize) -> u8 {
+    assert!(value <= 0xFF, "{} is too big to encode in a byte", value);
+    value as u8
+}
+
+fn encode_direction(dir: Direction) -> u8 {
+    match dir {
+        Direction::North => 0,
+        Direction::East => 1,
+        Direction::South => 2,
+        Direction::West => 3,
+    }
+}
+
+fn decode_direction(bits: u8) -> Result<Direction, DecodeError> {
+    Direction::all()
+        .get(bits as usize)
+        .cloned()
+        .ok_or(DecodeError::Invalid)
+}
+
+/// Packs shape into bits 0-1, orientation into bits 2-3, and whether it is a target into bit 4
+fn encode_tile(tile: &Tile) -> u8 {
+    let shape = match tile.shape {
+        Shape::L => 0,
+        Shape::I => 1,
+        Shape::T => 2,
+    };
+    let target = if tile.whose_target.is_some() { 1 } else { 0 };
+    shape | encode_direction(tile.orientation) << 2 | target << 4
+}
+
+/// Unpacks a tile, returning whether it is someone's target so the owner can be filled in later
+fn decode_tile(byte: u8) -> Result<(Tile, bool), DecodeError> {
+    let shape = match byte & 0b11 {
+        0 => Shape::L,
+        1 => Shape::I,
+        2 => Shape::T,
+        _ => return Err(DecodeError::Invalid),
+    };
+    let tile = Tile {
+        shape,
+        orientation: decode_direction(byte >> 2 & 0b11)?,
+        whose_target: None,
+    };
+    Ok((tile, byte & 0b1_0000 != 0))
+}
+
 fn avoid_path(tile: &mut Tile, target: Direction) {
     while tile.paths().contains(&target) {
         *tile = random();
@@ -68,12 +242,107 @@ fn valid_move(ind: (usize, usize), dir: Direction, (width, height): (usize, usiz
     }
 }
 
+/// Gets the position one step from the given one, coming back in at the far edge if it goes off
+fn wrapped_move(pos: (usize, usize), dir: Direction, dimensions: (usize, usize)) -> (usize, usize) {
+    let (width, height) = dimensions;
+    let (row, col) = pos;
+    let (new_row, new_col) = (row + height, col + width) + dir;
+    (new_row % height, new_col % width)
+}
+
+/// Gets where a position ends up when the given row or column shifts, wrapping around the far edge
+fn shifted_pos(
+    pos: (usize, usize),
+    target_idx: usize,
+    move_dir: Direction,
+    dimensions: (usize, usize),
+) -> (usize, usize) {
+    let (width, height) = dimensions;
+    let (old_row, old_col) = pos;
+    let should_be_target_idx = match move_dir {
+        Direction::North | Direction::South => old_col,
+        Direction::East | Direction::West => old_row,
+    };
+    if should_be_target_idx != target_idx {
+        return pos;
+    }
+    if valid_move(pos, move_dir, dimensions) {
+        pos + move_dir
+    } else {
+        let (new_row, new_col) = match move_dir {
+            Direction::East | Direction::West => (old_row, (old_col + width)) + move_dir,
+            Direction::North | Direction::South => ((old_row + height), old_col) + move_dir,
+        };
+        (new_row % height, new_col % width)
+    }
+}
+
 impl Board {
     /// Creates a new board
     pub fn new(width: usize, height: usize, players: &BTreeMap<PlayerID, Player>) -> Board {
-        if demo::is_demo() {
-            return demo::new_board(players);
+        let starts = Self::default_starts(width, height, players);
+        Self::generate(width, height, players, &starts, &BTreeMap::new())
+    }
+
+    /// Creates a new board with the given start tiles and, optionally, home tiles
+    ///
+    /// Every player needs a distinct start on the board. A player without a home gets their start.
+    pub fn with_layout(
+        width: usize,
+        height: usize,
+        players: &BTreeMap<PlayerID, Player>,
+        starts: BTreeMap<PlayerID, (usize, usize)>,
+        homes: BTreeMap<PlayerID, (usize, usize)>,
+    ) -> Result<Board, LayoutError> {
+        let in_bounds = |&(row, col): &(usize, usize)| row < height && col < width;
+        let mut used = HashSet::new();
+        for &id in players.keys() {
+            let start = starts.get(&id).ok_or(LayoutError::MissingStart(id))?;
+            let home_in_bounds = match homes.get(&id) {
+                Some(home) => in_bounds(home),
+                None => true,
+            };
+            if !in_bounds(start) || !home_in_bounds {
+                return Err(LayoutError::OutOfBounds(id));
+            }
+            if !used.insert(*start) {
+                return Err(LayoutError::DuplicateStart(id));
+            }
         }
+        Ok(Self::generate(width, height, players, &starts, &homes))
+    }
+
+    /// Gets the usual start tiles, in the corners for the first four players
+    fn default_starts(
+        width: usize,
+        height: usize,
+        players: &BTreeMap<PlayerID, Player>,
+    ) -> BTreeMap<PlayerID, (usize, usize)> {
+        let mut rng = thread_rng();
+        players
+            .keys()
+            .enumerate()
+            .map(|(i, &id)| {
+                let position = match i {
+                    0 => (0, 0),
+                    1 => (height - 1, width - 1),
+                    2 => (0, width - 1),
+                    3 => (height - 1, 0),
+                    _ => (rng.gen_range(0, height), rng.gen_range(0, width)),
+                };
+                (id, position)
+            })
+            .collect()
+    }
+
+    /// Builds a random board with tokens on the given starts, trusting that they are valid
+    fn generate(
+        width: usize,
+        height: usize,
+        players: &BTreeMap<PlayerID, Player>,
+        starts: &BTreeMap<PlayerID, (usize, usize)>,
+        homes: &BTreeMap<PlayerID, (usize, usize)>,
+    ) -> Board {
         let mut rng = rand::thread_rng();
         // build tiles
         let loose_tile: Tile = rng.gen();
@@ -85,27 +354,6 @@ impl Board {
             }
             cells.push(row);
         }
-        // set corners
-        cells[0][0] = Tile {
-            shape: Shape::L,
-            orientation: Direction::East,
-            whose_target: None,
-        };
-        cells[0][width - 1] = Tile {
-            shape: Shape::L,
-            orientation: Direction::South,
-            whose_target: None,
-        };
-        cells[height - 1][0] = Tile {
-            shape: Shape::L,
-            orientation: Direction::North,
-            whose_target: None,
-        };
-        cells[height - 1][width - 1] = Tile {
-            shape: Shape::L,
-            orientation: Direction::West,
-            whose_target: None,
-        };
         // ensure top/bottom fixed tiles point inwards
         for i in 0..width {
             if i % 2 == 0 {
@@ -124,32 +372,37 @@ impl Board {
         // create tokens
         let player_tokens = players
             .values()
-            .enumerate()
-            .map(move |(i, player)| {
-                let mut rng = thread_rng();
-                let position = match i {
-                    0 => (0, 0),
-                    1 => (height - 1, width - 1),
-                    2 => (0, width - 1),
-                    3 => (height - 1, 0),
-                    _ => (rng.gen_range(0, height), rng.gen_range(0, width)),
-                };
-                (player.id, PlayerToken::new(player, position))
+            .map(|player| {
+                let mut token = PlayerToken::new(player, starts[&player.id]);
+                if let Some(&home) = homes.get(&player.id) {
+                    token.home = home;
+                }
+                (player.id, token)
             })
             .collect();
-        let loose_tile_edge = rng.gen();
-        let loose_tile_spot = match loose_tile_edge {
-            Direction::North | Direction::South => rng.gen_range(0, height / 2),
-            Direction::East | Direction::West => rng.gen_range(0, width / 2),
-        };
-        // assign next locations
+        let fixed_tiles = (0..height)
+            .step_by(2)
+            .flat_map(|row| (0..width).step_by(2).map(move |col| (row, col)))
+            .collect();
         let mut result = Board {
             cells,
             loose_tile,
-            loose_tile_position: (loose_tile_edge, loose_tile_spot),
+            loose_tile_position: None,
             player_tokens,
+            treasures: HashSet::new(),
+            insert_rule: InsertRule::OddLinesOnly,
+            fixed_tiles,
+            allowed_insert_directions: Direction::all().iter().cloned().collect(),
+            max_move_tiles: None,
+            topology: Topology::Bounded,
+            last_changed_cells: vec![],
             tutorial_step: None,
         };
+        // give the corners and every start a way into the board
+        let corners = [(0, 0), (0, width - 1), (height - 1, 0), (height - 1, width - 1)];
+        let exits = corners.iter().chain(starts.values()).cloned().collect::<Vec<_>>();
+        result.ensure_start_exits(&exits);
+        // assign next locations
         let player_ids = result.player_tokens.keys().cloned().collect::<Vec<_>>();
         for player in &player_ids {
             result.assign_next_target(*player);
@@ -157,6 +410,317 @@ impl Board {
         result
     }
 
+    /// Packs the board into a compact binary form, roughly a byte per tile
+    ///
+    /// After the version byte come the width and height, then one byte per tile (row by row, with
+    /// bit 5 set on fixed tiles) and one for the loose tile, then the loose tile position, then
+    /// each token as its 8-byte little-endian ID, row, column, home row, home column, and score.
+    /// Each target tile's owner follows as an index into the token list, then the treasures, the
+    /// tutorial step, the insert rule, a bit for each side inserts are allowed from, the move limit
+    /// as a flag byte followed by its 4-byte little-endian value, and the topology.
+    ///
+    /// Panics if the board is more than 255 tiles wide or tall, or has more than 255 tokens or
+    /// treasures, since those don't fit.
+    pub fn encode(&self) -> Vec<u8> {
+        let (width, height) = (encode_byte(self.width()), encode_byte(self.height()));
+        let mut result = vec![ENCODING_VERSION, width, height];
+        for (pos, tile) in self.iter_tiles() {
+            let fixed = if self.is_fixed(pos) { 1 } else { 0 };
+            result.push(encode_tile(tile) | fixed << 5);
+        }
+        result.push(encode_tile(&self.loose_tile));
+        let tiles = self.cells.iter().flatten().chain(Some(&self.loose_tile));
+        match self.loose_tile_position {
+            Some((dir, guide_idx)) => {
+                result.extend(&[encode_direction(dir), encode_byte(guide_idx)]);
+            }
+            None => result.extend(&[0xFF, 0]),
+        }
+        let token_ids = self.player_tokens.keys().cloned().collect::<Vec<_>>();
+        result.push(encode_byte(token_ids.len()));
+        for token in self.player_tokens.values() {
+            result.extend(&token.player_id.to_le_bytes());
+            let (row, col) = token.position;
+            let (home_row, home_col) = token.home;
+            result.extend(&[row as u8, col as u8, home_row as u8, home_col as u8, token.score]);
+        }
+        for owner in tiles.filter_map(|tile| tile.whose_target) {
+            let idx = token_ids.iter().position(|&id| id == owner);
+            result.push(idx.unwrap_or(0xFF) as u8);
+        }
+        let mut treasures = self.treasures.iter().cloned().collect::<Vec<_>>();
+        treasures.sort();
+        result.push(encode_byte(treasures.len()));
+        for (row, col) in treasures {
+            result.extend(&[row as u8, col as u8]);
+        }
+        result.push(match self.tutorial_step {
+            None => 0,
+            Some(tutorial::TutorialStep::First) => 1,
+            Some(tutorial::TutorialStep::Second) => 2,
+            Some(tutorial::TutorialStep::Third) => 3,
+        });
+        result.push(match self.insert_rule {
+            InsertRule::OddLinesOnly => 0,
+            InsertRule::AnyLine => 1,
+        });
+        let directions = self.allowed_insert_directions.iter();
+        result.push(directions.fold(0, |bits, &dir| bits | 1 << encode_direction(dir)));
+        match self.max_move_tiles {
+            Some(limit) => {
+                result.push(1);
+                result.extend(&limit.to_le_bytes());
+            }
+            None => result.extend(&[0; 5]),
+        }
+        result.push(match self.topology {
+            Topology::Bounded => 0,
+            Topology::Toroidal => 1,
+        });
+        result
+    }
+
+    /// Unpacks a board packed by `encode`
+    pub fn decode(data: &[u8]) -> Result<Board, DecodeError> {
+        let mut bytes = data.iter().cloned();
+        let mut next = || bytes.next().ok_or(DecodeError::Truncated);
+        let version = next()?;
+        if version != ENCODING_VERSION {
+            return Err(DecodeError::Version(version));
+        }
+        let width = next()? as usize;
+        let height = next()? as usize;
+        if width == 0 || height == 0 {
+            return Err(DecodeError::Invalid);
+        }
+        let on_board = |(row, col): (usize, usize)| row < height && col < width;
+        let mut cells = vec![];
+        let mut targets = vec![];
+        let mut fixed_tiles = HashSet::new();
+        for j in 0..height {
+            let mut row = vec![];
+            for i in 0..width {
+                let byte = next()?;
+                let (tile, is_target) = decode_tile(byte)?;
+                if is_target {
+                    targets.push(Some((j, i)));
+                }
+                if byte & 0b10_0000 != 0 {
+                    fixed_tiles.insert((j, i));
+                }
+                row.push(tile);
+            }
+            cells.push(row);
+        }
+        let (mut loose_tile, is_target) = decode_tile(next()?)?;
+        if is_target {
+            targets.push(None);
+        }
+        let loose_dir = next()?;
+        let guide_idx = next()? as usize;
+        let loose_tile_position = match loose_dir {
+            0xFF => None,
+            bits => Some((decode_direction(bits)?, guide_idx)),
+        };
+        let mut token_ids = vec![];
+        let mut player_tokens = BTreeMap::new();
+        for _ in 0..next()? {
+            let mut id = [0; 8];
+            for byte in &mut id {
+                *byte = next()?;
+            }
+            let player_id = PlayerID::from_le_bytes(id);
+            let position = (next()? as usize, next()? as usize);
+            let home = (next()? as usize, next()? as usize);
+            if !on_board(position) || !on_board(home) {
+                return Err(DecodeError::Invalid);
+            }
+            let score = next()?;
+            token_ids.push(player_id);
+            player_tokens.insert(
+                player_id,
+                PlayerToken {
+                    player_id,
+                    position,
+                    home,
+                    score,
+                    last_position: None,
+                    finished_turn: None,
+                },
+            );
+        }
+        for target in targets {
+            let owner = *token_ids
+                .get(next()? as usize)
+                .ok_or(DecodeError::Invalid)?;
+            let tile = match target {
+                Some((j, i)) => &mut cells[j][i],
+                None => &mut loose_tile,
+            };
+            tile.whose_target = Some(owner);
+        }
+        let mut treasures = HashSet::new();
+        for _ in 0..next()? {
+            let treasure = (next()? as usize, next()? as usize);
+            if !on_board(treasure) {
+                return Err(DecodeError::Invalid);
+            }
+            treasures.insert(treasure);
+        }
+        let tutorial_step = match next()? {
+            0 => None,
+            1 => Some(tutorial::TutorialStep::First),
+            2 => Some(tutorial::TutorialStep::Second),
+            3 => Some(tutorial::TutorialStep::Third),
+            _ => return Err(DecodeError::Invalid),
+        };
+        let insert_rule = match next()? {
+            0 => InsertRule::OddLinesOnly,
+            1 => InsertRule::AnyLine,
+            _ => return Err(DecodeError::Invalid),
+        };
+        if let Some((dir, guide_idx)) = loose_tile_position {
+            let lines = match dir {
+                Direction::North | Direction::South => width,
+                Direction::East | Direction::West => height,
+            };
+            if guide_idx >= insert_rule.guide_count(lines) {
+                return Err(DecodeError::Invalid);
+            }
+        }
+        // a board nobody can insert into would leave every turn stuck
+        let direction_bits = next()?;
+        if direction_bits == 0 || direction_bits > 0b1111 {
+            return Err(DecodeError::Invalid);
+        }
+        let allowed_insert_directions = Direction::all()
+            .iter()
+            .cloned()
+            .filter(|&dir| direction_bits & 1 << encode_direction(dir) != 0)
+            .collect();
+        let has_limit = next()?;
+        let mut limit = [0; 4];
+        for byte in &mut limit {
+            *byte = next()?;
+        }
+        let max_move_tiles = match has_limit {
+            0 => None,
+            1 => Some(u32::from_le_bytes(limit)),
+            _ => return Err(DecodeError::Invalid),
+        };
+        let topology = match next()? {
+            0 => Topology::Bounded,
+            1 => Topology::Toroidal,
+            _ => return Err(DecodeError::Invalid),
+        };
+        if bytes.next().is_some() {
+            return Err(DecodeError::TrailingData);
+        }
+        Ok(Board {
+            cells,
+            loose_tile,
+            loose_tile_position,
+            player_tokens,
+            treasures,
+            insert_rule,
+            fixed_tiles,
+            allowed_insert_directions,
+            max_move_tiles,
+            topology,
+            last_changed_cells: vec![],
+            tutorial_step,
+        })
+    }
+
+    /// Gets what changed between this board and the given later one
+    ///
+    /// Both boards must have the same size and players. The tutorial step isn't included.
+    pub fn diff(&self, other: &Board) -> BoardDiff {
+        let mut cells = vec![];
+        for (j, (row, other_row)) in self.cells.iter().zip(&other.cells).enumerate() {
+            for (i, (tile, other_tile)) in row.iter().zip(other_row).enumerate() {
+                if tile != other_tile {
+                    cells.push(((j, i), other_tile.clone()));
+                }
+            }
+        }
+        let loose_tile = if self.loose_tile != other.loose_tile {
+            Some(other.loose_tile.clone())
+        } else {
+            None
+        };
+        let loose_tile_position = if self.loose_tile_position != other.loose_tile_position {
+            Some(other.loose_tile_position)
+        } else {
+            None
+        };
+        let player_tokens = other
+            .player_tokens
+            .values()
+            .filter(|token| self.player_tokens.get(&token.player_id) != Some(token))
+            .cloned()
+            .collect();
+        let treasures = if self.treasures != other.treasures {
+            Some(other.treasures.clone())
+        } else {
+            None
+        };
+        BoardDiff {
+            cells,
+            loose_tile,
+            loose_tile_position,
+            player_tokens,
+            treasures,
+        }
+    }
+
+    /// Applies changes from `diff`, so a board the diff was taken from ends up matching the later board
+    pub fn apply_diff(&mut self, diff: &BoardDiff) {
+        for &((j, i), ref tile) in &diff.cells {
+            self.cells[j][i] = tile.clone();
+        }
+        if let Some(ref loose_tile) = diff.loose_tile {
+            self.loose_tile = loose_tile.clone();
+        }
+        if let Some(loose_tile_position) = diff.loose_tile_position {
+            self.loose_tile_position = loose_tile_position;
+        }
+        for token in &diff.player_tokens {
+            self.player_tokens.insert(token.player_id, token.clone());
+        }
+        if let Some(ref treasures) = diff.treasures {
+            self.treasures = treasures.clone();
+        }
+    }
+
+    /// Reshapes the tiles at the given (row, col) positions so every open path leads into the board
+    ///
+    /// Corners become L tiles and other edge tiles become T tiles facing away from the edge. Tiles
+    /// away from the edges already can't lead off the board, so they are left alone.
+    pub fn ensure_start_exits(&mut self, positions: &[(usize, usize)]) {
+        let dimensions = (self.width(), self.height());
+        for &(row, col) in positions {
+            let outward = Direction::all()
+                .iter()
+                .cloned()
+                .filter(|&dir| !valid_move((row, col), dir, dimensions))
+                .collect::<Vec<_>>();
+            let shape = match outward.len() {
+                0 => continue,
+                1 => Shape::T,
+                _ => Shape::L,
+            };
+            let tile = &mut self.cells[row][col];
+            tile.shape = shape;
+            for &orientation in Direction::all() {
+                tile.orientation = orientation;
+                if tile.paths().iter().all(|path| !outward.contains(path)) {
+                    break;
+                }
+            }
+        }
+    }
+
     /// Parses a board specified with `│─└┌┐┘├┬┤┴` into an actual matrix of tiles
     pub fn parse_board(spec: &str) -> Vec<Vec<Tile>> {
         use std::convert::TryFrom;
@@ -177,11 +741,51 @@ impl Board {
             .collect()
     }
 
+    /// Gets the tile waiting to be inserted
+    pub fn loose_tile(&self) -> &Tile {
+        &self.loose_tile
+    }
+
+    /// Replaces the tile waiting to be inserted, such as when setting up a scenario or replay
+    ///
+    /// The orientation is normalized like any other loose tile's, and a target for a player who
+    /// isn't on the board is dropped.
+    pub fn set_loose_tile(&mut self, tile: Tile) {
+        let mut tile = tile;
+        tile.normalize_orientation();
+        if let Some(id) = tile.whose_target {
+            if !self.player_tokens.contains_key(&id) {
+                tile.whose_target = None;
+            }
+        }
+        self.loose_tile = tile;
+    }
+
     /// Gets a cell from the board
     pub fn get(&self, ind: [usize; 2]) -> &Tile {
         &self.cells[ind[1]][ind[0]]
     }
 
+    /// Gets every tile with its (row, col) position, a row at a time
+    pub fn iter_tiles(&self) -> impl Iterator<Item = ((usize, usize), &Tile)> {
+        self.cells.iter().enumerate().flat_map(|(row, tiles)| {
+            tiles
+                .iter()
+                .enumerate()
+                .map(move |(col, tile)| ((row, col), tile))
+        })
+    }
+
+    /// Gets every tile mutably with its (row, col) position, a row at a time
+    pub fn iter_tiles_mut(&mut self) -> impl Iterator<Item = ((usize, usize), &mut Tile)> {
+        self.cells.iter_mut().enumerate().flat_map(|(row, tiles)| {
+            tiles
+                .iter_mut()
+                .enumerate()
+                .map(move |(col, tile)| ((row, col), tile))
+        })
+    }
+
     /// Gets the width of the board
     pub fn width(&self) -> usize {
         self.cells[0].len()
@@ -192,55 +796,206 @@ impl Board {
         self.cells.len()
     }
 
-    /// Inserts the loose tile at its current position
+    /// Gets every (edge, guide) position the loose tile can be inserted from
+    pub fn insert_positions(&self) -> Vec<(Direction, usize)> {
+        let guides_x = self.insert_rule.guide_count(self.width());
+        let guides_y = self.insert_rule.guide_count(self.height());
+        Direction::all()
+            .iter()
+            .filter(|dir| self.allowed_insert_directions.contains(dir))
+            .flat_map(|&dir| {
+                let count = match dir {
+                    Direction::North | Direction::South => guides_x,
+                    Direction::East | Direction::West => guides_y,
+                };
+                (0..count).map(move |idx| (dir, idx))
+            })
+            .filter(|&(dir, idx)| !self.is_line_blocked(dir, idx))
+            .collect()
+    }
+
+    /// Inserts the loose tile at its current position, if it has one
     pub fn insert_loose_tile(&mut self) {
-        let (dir, guide_idx) = self.loose_tile_position;
+        if let Some((dir, guide_idx)) = self.loose_tile_position {
+            let line = self.insert_rule.guide_line(guide_idx);
+            let sync = anim::AnimSync::Insert(dir.opposite(), line);
+            anim::STATE.write().unwrap().apply_send(sync);
+        }
+        self.shift_loose_tile();
+    }
+
+    /// Gets the (row, col) of the tile that inserting at the given guide pushes off the far side
+    pub fn ejected_pos(&self, dir: Direction, guide_idx: usize) -> (usize, usize) {
+        let (width, height) = (self.width(), self.height());
+        let line = self.insert_rule.guide_line(guide_idx);
+        match dir {
+            Direction::North => (height - 1, line),
+            Direction::South => (0, line),
+            Direction::West => (line, width - 1),
+            Direction::East => (line, 0),
+        }
+    }
+
+    /// Gets where the tile at the given (row, col) after an insert at the given guide sits before
+    /// that insert, or `None` for the loose tile that the insert pushes onto the board
+    pub fn unshifted_pos(
+        &self,
+        pos: (usize, usize),
+        (dir, guide_idx): (Direction, usize),
+    ) -> Option<(usize, usize)> {
+        if pos == self.ejected_pos(dir.opposite(), guide_idx) {
+            return None;
+        }
+        let line = self.insert_rule.guide_line(guide_idx);
+        Some(shifted_pos(pos, line, dir, (self.width(), self.height())))
+    }
+
+    /// Inserts the loose tile at its current position without animating, for hypothetical boards
+    pub fn shift_loose_tile(&mut self) {
+        let (dir, guide_idx) = match self.loose_tile_position {
+            Some(position) => position,
+            None => return,
+        };
         let dimensions = (self.width(), self.height());
-        let (width, height) = dimensions;
-        let target_idx = 2 * guide_idx + 1;
-        let sync = anim::AnimSync::Insert(dir * Direction::South, target_idx);
-        anim::STATE.write().unwrap().apply_send(sync);
+        let target_idx = self.insert_rule.guide_line(guide_idx);
         // general process: copy into the current position, so start opposite correct margin
-        let (mut j, mut i) = match dir {
-            Direction::North => (height - 1, target_idx),
-            Direction::South => (0, target_idx),
-            Direction::West => (target_idx, width - 1),
-            Direction::East => (target_idx, 0),
-        };
+        let (mut j, mut i) = self.ejected_pos(dir, guide_idx);
         let next_loose_tile = self.cells[j][i].clone();
+        self.last_changed_cells = vec![(j, i)];
         while valid_move((j, i), dir, dimensions) {
             let (next_j, next_i) = (j, i) + dir;
             self.cells[j][i] = self.cells[next_j][next_i].clone();
             j = next_j;
             i = next_i;
+            self.last_changed_cells.push((j, i));
         }
         self.cells[j][i] = self.loose_tile.clone();
         self.loose_tile = next_loose_tile;
-        self.loose_tile_position.0 *= Direction::South;
+        self.loose_tile_position = Some((dir.opposite(), guide_idx));
         // move all tokens
-        let move_dir = dir * Direction::South;
+        let move_dir = dir.opposite();
         for token in self.player_tokens.values_mut() {
-            let (old_row, old_col) = token.position;
-            let should_be_target_idx = match move_dir {
-                Direction::North | Direction::South => old_col,
-                Direction::East | Direction::West => old_row,
-            };
-            if should_be_target_idx != target_idx {
+            token.position = shifted_pos(token.position, target_idx, move_dir, dimensions);
+            // where a token came from means nothing once the board has moved under it
+            token.last_position = None;
+        }
+        // move all treasures the same way, so one pushed off lands on the inserted tile
+        self.treasures = self
+            .treasures
+            .iter()
+            .map(|&pos| shifted_pos(pos, target_idx, move_dir, dimensions))
+            .collect();
+    }
+
+    /// Scatters up to `count` treasures on shifting tiles that no token starts on
+    pub fn place_treasures<R: Rng + ?Sized>(&mut self, count: usize, rng: &mut R) {
+        let starts = self
+            .player_tokens
+            .values()
+            .map(|token| token.position)
+            .collect::<HashSet<_>>();
+        let candidates = self
+            .iter_tiles()
+            .map(|(pos, _)| pos)
+            .filter(|&pos| !self.is_fixed(pos))
+            .filter(|pos| !starts.contains(pos) && !self.treasures.contains(pos))
+            .collect::<Vec<_>>();
+        self.treasures
+            .extend(candidates.choose_multiple(rng, count).cloned());
+    }
+
+    /// Re-randomizes every tile that isn't fixed and has no token on it, for a chaotic mid-game
+    /// reshuffle
+    ///
+    /// Targets stay where they are and the loose tile is untouched. Fixed tiles keep their shape
+    /// and orientation, so the edge ones still face inward, and reshuffled corners and home tiles
+    /// still can't lead off the board afterwards.
+    pub fn shuffle_tiles<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let occupied: HashSet<(usize, usize)> =
+            self.player_tokens.values().map(|token| token.position).collect();
+        let shuffled = self
+            .iter_tiles()
+            .map(|(pos, _)| pos)
+            .filter(|&pos| !occupied.contains(&pos) && !self.is_fixed(pos))
+            .collect::<HashSet<_>>();
+        for (pos, tile) in self.iter_tiles_mut() {
+            if !shuffled.contains(&pos) {
                 continue;
             }
-            token.position = if valid_move(token.position, move_dir, dimensions) {
-                token.position + (dir * Direction::South)
-            } else {
-                let (old_row, old_col) = token.position;
-                let (new_row, new_col) = match move_dir {
-                    Direction::East | Direction::West => (old_row, (old_col + width)) + move_dir,
-                    Direction::North | Direction::South => ((old_row + height), old_col) + move_dir,
-                };
-                (new_row % height, new_col % width)
-            };
+            let whose_target = tile.whose_target;
+            *tile = rng.gen();
+            tile.whose_target = whose_target;
+        }
+        let (width, height) = (self.width(), self.height());
+        let corners = [(0, 0), (0, width - 1), (height - 1, 0), (height - 1, width - 1)];
+        let homes = self.player_tokens.values().map(|token| token.home);
+        let exits = corners
+            .iter()
+            .cloned()
+            .chain(homes)
+            .filter(|pos| shuffled.contains(pos))
+            .collect::<Vec<_>>();
+        self.ensure_start_exits(&exits);
+    }
+
+    /// Switches to the given insert rule, dropping the usual fixed tiles for `AnyLine` since they
+    /// sit on every even line and would block each line it adds
+    pub fn set_insert_rule(&mut self, rule: InsertRule) {
+        self.insert_rule = rule;
+        if rule == InsertRule::AnyLine {
+            self.fixed_tiles.clear();
+        }
+    }
+
+    /// Checks if the tile at the given (row, col) never moves, because no insert shifts its lines
+    pub fn is_fixed(&self, pos: (usize, usize)) -> bool {
+        self.fixed_tiles.contains(&pos)
+    }
+
+    /// Checks if a fixed tile sits on the line the given guide shifts, so nothing can be inserted
+    /// there
+    pub fn is_line_blocked(&self, dir: Direction, guide_idx: usize) -> bool {
+        let line = self.insert_rule.guide_line(guide_idx);
+        self.fixed_tiles.iter().any(|&(row, col)| match dir {
+            Direction::North | Direction::South => col == line,
+            Direction::East | Direction::West => row == line,
+        })
+    }
+
+    /// Claims the treasure at the given position for the given player, if there is one there
+    pub fn claim_treasure(&mut self, player_id: PlayerID, pos: (usize, usize)) -> bool {
+        if !self.treasures.remove(&pos) {
+            return false;
+        }
+        if let Some(token) = self.player_tokens.get_mut(&player_id) {
+            token.score += 1;
+        }
+        true
+    }
+
+    /// Notes the turn on which the given player reached the score limit, returns whether they just
+    /// reached it
+    ///
+    /// Call this after every change to a player's score, whether from a target or a treasure.
+    pub fn mark_finished(&mut self, player_id: PlayerID, score_limit: u8, turn: u32) -> bool {
+        match self.player_tokens.get_mut(&player_id) {
+            Some(token) if token.score >= score_limit && token.finished_turn.is_none() => {
+                token.finished_turn = Some(turn);
+                true
+            }
+            _ => false,
         }
     }
 
+    /// Copies the board and inserts the loose tile with the given orientation at the given position
+    pub fn after_insert(&self, insert: (Direction, usize), orientation: Direction) -> Board {
+        let mut hypothetical = self.clone();
+        hypothetical.loose_tile.orientation = orientation;
+        hypothetical.loose_tile_position = Some(insert);
+        hypothetical.shift_loose_tile();
+        hypothetical
+    }
+
     /// Gets the (row, col) position of the given player
     pub fn player_pos(&self, id: PlayerID) -> (usize, usize) {
         self.player_tokens
@@ -249,39 +1004,110 @@ impl Board {
             .position
     }
 
+    /// Gets the (row, col) position of the given player's target, if it is on the board
+    pub fn target_pos(&self, id: PlayerID) -> Option<(usize, usize)> {
+        self.iter_tiles()
+            .find(|(_, tile)| tile.whose_target == Some(id))
+            .map(|(pos, _)| pos)
+    }
+
+    /// Plays out a whole turn for the given player without animating, returns whether the move was legal
+    ///
+    /// The loose tile is inserted even if the move turns out not to be legal. Reaching
+    /// `score_limit` marks the player as finished on the given turn.
+    pub fn apply_turn(
+        &mut self,
+        id: PlayerID,
+        insert: (Direction, usize),
+        orientation: Direction,
+        move_to: (usize, usize),
+        score_limit: u8,
+        turn: u32,
+    ) -> bool {
+        self.loose_tile.orientation = orientation;
+        self.loose_tile_position = Some(insert);
+        self.shift_loose_tile();
+        if !self.movable_coords(self.player_pos(id)).contains(&move_to) {
+            return false;
+        }
+        self.move_player(id, move_to);
+        if self.claim_treasure(id, move_to) {
+            self.mark_finished(id, score_limit, turn);
+        }
+        let (row, col) = move_to;
+        if self.cells[row][col].whose_target == Some(id) {
+            self.player_reached_target(id);
+            self.mark_finished(id, score_limit, turn);
+        }
+        true
+    }
+
     /// Moves the given player to the given (row, col)
     pub fn move_player(&mut self, id: PlayerID, pos: (usize, usize)) {
-        self.player_tokens
+        let token = self
+            .player_tokens
             .get_mut(&id)
-            .expect("No token for player with given ID")
-            .position = pos;
+            .expect("No token for player with given ID");
+        token.last_position = if token.position != pos {
+            Some(token.position)
+        } else {
+            None
+        };
+        token.position = pos;
     }
 
-    fn add_reachable_coords(&self, from: (usize, usize), result: &mut HashSet<(usize, usize)>) {
+    /// Gets the position one step from the given (row, col), if that doesn't fall off the board
+    fn neighbor(&self, pos: (usize, usize), dir: Direction) -> Option<(usize, usize)> {
         let dimensions = (self.width(), self.height());
-        // result contains everything seen, frontier contains only things not yet scanned
+        match self.topology {
+            Topology::Bounded if valid_move(pos, dir, dimensions) => Some(pos + dir),
+            Topology::Bounded => None,
+            Topology::Toroidal => Some(wrapped_move(pos, dir, dimensions)),
+        }
+    }
+
+    /// Gets the (row, col) positions a token could step to directly from the given one
+    fn open_neighbors(&self, (row, col): (usize, usize)) -> Vec<(usize, usize)> {
+        self.cells[row][col]
+            .paths()
+            .into_iter()
+            // if it doesn't fall off the board and the connecting tile connects up as well...
+            .filter_map(|dir| {
+                let (next_row, next_col) = self.neighbor((row, col), dir)?;
+                if self.cells[next_row][next_col].paths().contains(&dir.opposite()) {
+                    Some((next_row, next_col))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn add_reachable_coords(
+        &self,
+        from: (usize, usize),
+        max_steps: Option<u32>,
+        result: &mut HashSet<(usize, usize)>,
+    ) {
+        // result contains everything seen, frontier contains only things not yet scanned, nearest first
         result.insert(from);
-        let mut frontier = vec![from];
+        let mut frontier = VecDeque::new();
+        frontier.push_back((from, 0));
         // while frontier is nonempty...
-        while let Some((curr_row, curr_col)) = frontier.pop() {
-            // for each reachable direction...
-            for dir in self.cells[curr_row][curr_col].paths() {
-                // if it doesn't fall off the board...
-                if valid_move((curr_row, curr_col), dir, dimensions) {
-                    // find the connecting tile
-                    let (next_row, next_col) = (curr_row, curr_col) + dir;
-                    // if that tile connects up as well...
-                    if self.cells[next_row][next_col]
-                        .paths()
-                        .contains(&(dir * Direction::South))
-                    {
-                        // if we've never seen that location before...
-                        if !result.contains(&(next_row, next_col)) {
-                            // add it to frontier and result
-                            frontier.push((next_row, next_col));
-                            result.insert((next_row, next_col));
-                        }
-                    }
+        while let Some(((curr_row, curr_col), steps)) = frontier.pop_front() {
+            // if we can't go any further from here, don't look at the neighbors
+            if let Some(max_steps) = max_steps {
+                if steps >= max_steps {
+                    continue;
+                }
+            }
+            // for each connected neighbor...
+            for next in self.open_neighbors((curr_row, curr_col)) {
+                // if we've never seen that location before...
+                if !result.contains(&next) {
+                    // add it to frontier and result
+                    frontier.push_back((next, steps + 1));
+                    result.insert(next);
                 }
             }
         }
@@ -290,29 +1116,125 @@ impl Board {
     /// Gets all the coordinates reachable from the given (row, col)
     pub fn reachable_coords(&self, from: (usize, usize)) -> HashSet<(usize, usize)> {
         let mut result = HashSet::new();
-        self.add_reachable_coords(from, &mut result);
+        self.add_reachable_coords(from, None, &mut result);
         result
     }
 
+    /// Gets all the coordinates reachable from the given (row, col) in at most `max_steps` steps
+    pub fn reachable_within(&self, from: (usize, usize), max_steps: u32) -> HashSet<(usize, usize)> {
+        let mut result = HashSet::new();
+        self.add_reachable_coords(from, Some(max_steps), &mut result);
+        result
+    }
+
+    /// Gets the (row, col) positions a token at the given position can move to in one turn,
+    /// within `max_move_tiles` steps if that is set
+    pub fn movable_coords(&self, from: (usize, usize)) -> HashSet<(usize, usize)> {
+        match self.max_move_tiles {
+            Some(max_steps) => self.reachable_within(from, max_steps),
+            None => self.reachable_coords(from),
+        }
+    }
+
+    /// Gets the positions along a shortest walk between two (row, col) positions, both ends included
+    pub fn shortest_path(
+        &self,
+        from: (usize, usize),
+        to: (usize, usize),
+    ) -> Option<Vec<(usize, usize)>> {
+        // remember how each position was first reached, so the path can be walked back
+        let mut came_from = HashMap::new();
+        came_from.insert(from, from);
+        let mut frontier = VecDeque::new();
+        frontier.push_back(from);
+        while let Some(curr) = frontier.pop_front() {
+            if curr == to {
+                let mut path = vec![curr];
+                let mut step = curr;
+                while step != from {
+                    step = came_from[&step];
+                    path.push(step);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for next in self.open_neighbors(curr) {
+                if let Entry::Vacant(entry) = came_from.entry(next) {
+                    entry.insert(curr);
+                    frontier.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Gets how many steps apart two players' tokens are along the maze's paths, if they connect
+    ///
+    /// Returns `None` as well if either player has no token on the board.
+    pub fn token_distance(&self, a: PlayerID, b: PlayerID) -> Option<u32> {
+        let from = self.player_tokens.get(&a)?.position;
+        let to = self.player_tokens.get(&b)?.position;
+        let path = self.shortest_path(from, to)?;
+        Some(path.len() as u32 - 1)
+    }
+
+    /// Measures how evenly the board treats each player's start, for picking fair generated boards
+    ///
+    /// Distances are measured to the nearest tile in the 3x3 block in the middle of the board,
+    /// which every start is equally far from on a symmetric board.
+    pub fn fairness_score(&self) -> FairnessReport {
+        let (center_row, center_col) = (self.height() / 2, self.width() / 2);
+        let region = (center_row.saturating_sub(1)..(center_row + 2).min(self.height()))
+            .flat_map(|row| {
+                (center_col.saturating_sub(1)..(center_col + 2).min(self.width()))
+                    .map(move |col| (row, col))
+            })
+            .collect::<Vec<_>>();
+        let unreachable_distance = (self.width() + self.height()) as f64;
+        let mut reachable = BTreeMap::new();
+        let mut center_distance = BTreeMap::new();
+        for token in self.player_tokens.values() {
+            let start = token.home;
+            reachable.insert(token.player_id, self.reachable_coords(start).len());
+            let distance = region
+                .iter()
+                .filter_map(|&target| self.shortest_path(start, target))
+                .map(|path| path.len() - 1)
+                .min();
+            center_distance.insert(token.player_id, distance);
+        }
+        let reachable_values = reachable.values().map(|&n| n as f64).collect::<Vec<_>>();
+        let distance_values = center_distance
+            .values()
+            .map(|distance| match *distance {
+                Some(distance) => distance as f64,
+                None => unreachable_distance,
+            })
+            .collect::<Vec<_>>();
+        FairnessReport {
+            reachable_variance: variance(&reachable_values),
+            distance_variance: variance(&distance_values),
+            reachable,
+            center_distance,
+        }
+    }
+
     /// Gets all the coordinates reachable from the given (row, col) or one tile nearby
     pub fn nearly_reachable_coords(&self, from: (usize, usize)) -> HashSet<(usize, usize)> {
-        let dimensions = (self.width(), self.height());
         let mut result = HashSet::new();
         // grab all the directly reachable coordinates
-        self.add_reachable_coords(from, &mut result);
+        self.add_reachable_coords(from, None, &mut result);
         let direct_result = result.clone();
         // for everything already found...
         for pos in direct_result {
             // for every direction...
             for dir in Direction::all() {
-                // if it doesn't fall off the board...
-                if valid_move(pos, *dir, dimensions) {
-                    // find the connecting tile
-                    let next_pos = pos + *dir;
+                // if it doesn't fall off the board, find the connecting tile
+                if let Some(next_pos) = self.neighbor(pos, *dir) {
                     // if we've never seen that location before...
                     if !result.contains(&next_pos) {
                         // run that search from there
-                        self.add_reachable_coords(next_pos, &mut result);
+                        self.add_reachable_coords(next_pos, None, &mut result);
                     }
                 }
             }
@@ -323,8 +1245,9 @@ impl Board {
     fn assign_next_target(&mut self, player_id: PlayerID) {
         let mut rng = rand::thread_rng();
         let (old_row, old_col) = self.player_tokens[&player_id].position;
-        let all_targets = (0..self.height())
-            .flat_map(|row| (0..self.width()).map(move |col| (row, col)))
+        let all_targets = self
+            .iter_tiles()
+            .map(|(pos, _)| pos)
             .collect::<HashSet<_>>();
         let banned_targets = [(old_row, old_col)]
             .iter()
@@ -358,4 +1281,496 @@ impl Board {
             self.assign_next_target(player_id);
         }
     }
+
+    /// Draws the board as a grid of text for reading in logs, three characters square per tile
+    ///
+    /// Walls are `█`, tokens are capital letters (or a count where several share a tile), targets
+    /// are the matching lowercase letter, and treasures are `$`. A legend follows the grid.
+    pub fn render_ascii(&self) -> String {
+        let letters: HashMap<PlayerID, char> = self
+            .player_tokens
+            .keys()
+            .enumerate()
+            .map(|(i, &id)| (id, (b'A' + (i % 26) as u8) as char))
+            .collect();
+        let mut occupants: HashMap<(usize, usize), Vec<PlayerID>> = HashMap::new();
+        for token in self.player_tokens.values() {
+            occupants.entry(token.position).or_default().push(token.player_id);
+        }
+        let wall = |open: bool| if open { ' ' } else { '█' };
+
+        let mut result = String::new();
+        for (row, tiles) in self.cells.iter().enumerate() {
+            let mut lines = [String::new(), String::new(), String::new()];
+            for (col, tile) in tiles.iter().enumerate() {
+                let paths = tile.paths();
+                let center = match occupants.get(&(row, col)) {
+                    Some(ids) if ids.len() == 1 => letters[&ids[0]],
+                    Some(ids) => std::char::from_digit(ids.len().min(9) as u32, 10).unwrap(),
+                    None => match tile.whose_target {
+                        Some(id) => letters.get(&id).map_or('?', |c| c.to_ascii_lowercase()),
+                        None if self.treasures.contains(&(row, col)) => '$',
+                        None => ' ',
+                    },
+                };
+                lines[0].push('█');
+                lines[0].push(wall(paths.contains(&Direction::North)));
+                lines[0].push('█');
+                lines[1].push(wall(paths.contains(&Direction::West)));
+                lines[1].push(center);
+                lines[1].push(wall(paths.contains(&Direction::East)));
+                lines[2].push('█');
+                lines[2].push(wall(paths.contains(&Direction::South)));
+                lines[2].push('█');
+            }
+            for line in &lines {
+                result.push_str(line);
+                result.push('\n');
+            }
+        }
+
+        for token in self.player_tokens.values() {
+            result.push_str(&format!(
+                "{}: player {} at {:?}, score {}\n",
+                letters[&token.player_id], token.player_id, token.position, token.score
+            ));
+        }
+        result.push_str(&format!(
+            "loose tile: {:?} facing {:?}\n",
+            self.loose_tile.shape, self.loose_tile.orientation
+        ));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+    use crate::colors::Color;
+
+    fn players(ids: &[PlayerID]) -> BTreeMap<PlayerID, Player> {
+        ids.iter()
+            .map(|&id| (id, Player::new(format!("Player {}", id), Color(0.5, 0.5, 0.5), id)))
+            .collect()
+    }
+
+    /// Builds a board from a tile spec with each player's token at the given (row, col)
+    fn board(spec: &str, tokens: &[(PlayerID, (usize, usize))]) -> Board {
+        let cells = Board::parse_board(spec);
+        let ids = tokens.iter().map(|&(id, _)| id).collect::<Vec<_>>();
+        let starts = tokens.iter().cloned().collect();
+        let (width, height) = (cells[0].len(), cells.len());
+        let mut board =
+            Board::with_layout(width, height, &players(&ids), starts, BTreeMap::new()).unwrap();
+        board.cells = cells;
+        board
+    }
+
+    #[test]
+    fn insert_carries_treasures_along_the_line() {
+        let mut board = Board::new(7, 7, &players(&[1, 2]));
+        board.treasures = [(1, 2), (1, 6), (2, 2)].iter().cloned().collect();
+        board.loose_tile_position = Some((Direction::West, 0));
+        board.shift_loose_tile();
+        let expected = [(1, 3), (1, 0), (2, 2)].iter().cloned().collect::<HashSet<_>>();
+        assert_eq!(board.treasures, expected);
+    }
+
+    #[test]
+    fn claiming_treasure_scores_and_removes_it() {
+        let mut board = board("─── ─── ───", &[(1, (0, 0))]);
+        board.treasures.insert((0, 2));
+        board.loose_tile = '│'.try_into().unwrap();
+        assert!(board.apply_turn(1, (Direction::North, 0), Direction::East, (0, 2), 1, 4));
+        let token = &board.player_tokens[&1];
+        assert_eq!(token.score, 1);
+        assert_eq!(token.finished_turn, Some(4));
+        assert!(board.treasures.is_empty());
+        assert!(!board.claim_treasure(1, (0, 2)));
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let mut board = Board::new(7, 7, &players(&[1, 2]));
+        board.place_treasures(3, &mut thread_rng());
+        board.loose_tile_position = Some((Direction::East, 2));
+        board.player_tokens.get_mut(&2).unwrap().score = 4;
+        let decoded = Board::decode(&board.encode()).unwrap();
+        assert_eq!(decoded.cells, board.cells);
+        assert_eq!(decoded.loose_tile, board.loose_tile);
+        assert_eq!(decoded.loose_tile_position, board.loose_tile_position);
+        assert_eq!(decoded.player_tokens, board.player_tokens);
+        assert_eq!(decoded.treasures, board.treasures);
+        assert!(decoded.tutorial_step.is_none());
+    }
+
+    #[test]
+    fn encoding_takes_about_a_byte_per_tile() {
+        let overhead = |size: usize| {
+            let board = Board::new(size, size, &players(&[1, 2]));
+            board.encode().len() - size * size
+        };
+        assert_eq!(overhead(7), overhead(15));
+        assert!(overhead(7) < 100);
+    }
+
+    #[test]
+    fn decoding_rejects_bad_data() {
+        let encoded = Board::new(7, 7, &players(&[1, 2])).encode();
+        let mut wrong_version = encoded.clone();
+        wrong_version[0] = 0;
+        assert_eq!(Board::decode(&wrong_version).unwrap_err(), DecodeError::Version(0));
+        let truncated = &encoded[..encoded.len() - 1];
+        assert_eq!(Board::decode(truncated).unwrap_err(), DecodeError::Truncated);
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert_eq!(Board::decode(&trailing).unwrap_err(), DecodeError::TrailingData);
+    }
+
+    #[test]
+    fn decoding_rejects_what_is_off_the_board() {
+        let mut board = Board::new(7, 7, &players(&[1, 2]));
+        board.treasures = [(2, 3)].iter().cloned().collect();
+        board.loose_tile_position = Some((Direction::North, 2));
+        let encoded = board.encode();
+        let invalid = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut data = encoded.clone();
+            change(&mut data);
+            Board::decode(&data).unwrap_err() == DecodeError::Invalid
+        };
+        assert!(invalid(&|data| data[1] = 0));
+        assert!(invalid(&|data| data[2] = 0));
+        // the loose tile guide sits after the tiles and loose tile, with only 3 odd columns
+        let guide = 3 + 7 * 7 + 2;
+        assert_eq!(encoded[guide], 2);
+        assert!(invalid(&|data| data[guide] = 3));
+        // each token is its count, then an 8-byte ID, row, column, home row, home column
+        let token = guide + 2 + 8;
+        for offset in 0..4 {
+            assert!(invalid(&|data| data[token + offset] = 7));
+        }
+        // the treasure's column comes before the tutorial step, insert rule, side mask, move limit
+        // and topology
+        let treasure = encoded.len() - 10;
+        assert_eq!(&encoded[treasure - 2..=treasure], &[1, 2, 3]);
+        assert!(invalid(&|data| data[treasure] = 7));
+    }
+
+    #[test]
+    #[should_panic(expected = "too big to encode")]
+    fn encoding_refuses_boards_too_big_for_a_byte() {
+        Board::new(256, 1, &players(&[1])).encode();
+    }
+
+    #[test]
+    fn custom_layout_places_tokens_on_their_starts() {
+        let starts = [(1, (3, 3)), (2, (0, 4))].iter().cloned().collect();
+        let homes = [(2, (6, 6))].iter().cloned().collect();
+        let board = Board::with_layout(7, 7, &players(&[1, 2]), starts, homes).unwrap();
+        assert_eq!(board.player_pos(1), (3, 3));
+        assert_eq!(board.player_pos(2), (0, 4));
+        assert_eq!(board.player_tokens[&1].home, (3, 3));
+        assert_eq!(board.player_tokens[&2].home, (6, 6));
+    }
+
+    #[test]
+    fn custom_layout_rejects_bad_starts() {
+        let layout = |starts: &[(PlayerID, (usize, usize))]| {
+            let starts = starts.iter().cloned().collect();
+            Board::with_layout(7, 7, &players(&[1, 2]), starts, BTreeMap::new()).err()
+        };
+        assert_eq!(layout(&[(1, (2, 2)), (2, (2, 2))]), Some(LayoutError::DuplicateStart(2)));
+        assert_eq!(layout(&[(1, (2, 2)), (2, (7, 0))]), Some(LayoutError::OutOfBounds(2)));
+        assert_eq!(layout(&[(1, (2, 2))]), Some(LayoutError::MissingStart(2)));
+    }
+
+    #[test]
+    fn start_exits_lead_into_the_board() {
+        let mut board = board("─── ─── ───", &[(1, (0, 1))]);
+        board.ensure_start_exits(&[(0, 1), (2, 2), (1, 1)]);
+        let north_edge = board.cells[0][1].paths();
+        assert!(north_edge.contains(&Direction::South));
+        assert!(!north_edge.contains(&Direction::North));
+        let corner = board.cells[2][2].paths();
+        assert!(corner.contains(&Direction::North) && corner.contains(&Direction::West));
+        // the middle can't lead off the board, so it keeps its shape
+        assert_eq!(board.cells[1][1].paths().len(), 2);
+        assert!(board.cells[1][1].paths().contains(&Direction::East));
+    }
+
+    #[test]
+    fn reachable_within_stops_at_the_step_limit() {
+        let board = board("─┬─ │││ │││", &[(1, (0, 1))]);
+        let set = |cells: &[(usize, usize)]| cells.iter().cloned().collect::<HashSet<_>>();
+        assert_eq!(board.reachable_within((0, 1), 0), set(&[(0, 1)]));
+        let neighbors = set(&[(0, 1), (0, 0), (0, 2), (1, 1)]);
+        assert_eq!(board.reachable_within((0, 1), 1), neighbors);
+        let everywhere = set(&[(0, 1), (0, 0), (0, 2), (1, 1), (2, 1)]);
+        assert_eq!(board.reachable_within((0, 1), 2), everywhere);
+        assert_eq!(board.reachable_coords((0, 1)), everywhere);
+    }
+
+    #[test]
+    fn applying_a_diff_catches_up_to_the_other_board() {
+        let mut before = Board::new(7, 7, &players(&[1, 2]));
+        before.loose_tile_position = Some((Direction::West, 1));
+        let mut after = before.clone();
+        after.shift_loose_tile();
+        after.move_player(1, (0, 1));
+        after.treasures.insert((4, 4));
+
+        let diff = before.diff(&after);
+        assert!(diff.cells.len() <= 7);
+        assert!(diff.cells.iter().all(|&((row, _), _)| row == 3));
+        before.apply_diff(&diff);
+        assert_eq!(before.cells, after.cells);
+        assert_eq!(before.loose_tile, after.loose_tile);
+        assert_eq!(before.loose_tile_position, after.loose_tile_position);
+        assert_eq!(before.player_tokens, after.player_tokens);
+        assert_eq!(before.treasures, after.treasures);
+
+        let nothing = after.diff(&after);
+        assert!(nothing.cells.is_empty() && nothing.player_tokens.is_empty());
+        assert!(nothing.loose_tile.is_none() && nothing.treasures.is_none());
+    }
+
+    #[test]
+    fn moving_remembers_where_the_token_came_from() {
+        let mut board = board("─── ─── ───", &[(1, (1, 0))]);
+        board.move_player(1, (1, 2));
+        assert_eq!(board.player_tokens[&1].last_position, Some((1, 0)));
+        // staying put leaves no trail
+        board.move_player(1, (1, 2));
+        assert_eq!(board.player_tokens[&1].last_position, None);
+        board.move_player(1, (1, 1));
+        board.loose_tile_position = Some((Direction::North, 0));
+        board.shift_loose_tile();
+        assert_eq!(board.player_tokens[&1].last_position, None);
+    }
+
+    #[test]
+    fn lopsided_board_is_less_fair() {
+        let even = board("┌─┐ │││ └─┘", &[(1, (0, 0)), (2, (2, 2))]).fairness_score();
+        assert_eq!(even.reachable[&1], even.reachable[&2]);
+        assert_eq!(even.reachable_variance, 0.0);
+        assert_eq!(even.distance_variance, 0.0);
+
+        let lopsided = board("┌─┐ │││ ──│", &[(1, (0, 0)), (2, (2, 1))]).fairness_score();
+        assert_eq!(lopsided.reachable[&1], 6);
+        assert_eq!(lopsided.reachable[&2], 2);
+        assert!(lopsided.reachable_variance > even.reachable_variance);
+    }
+
+    #[test]
+    fn any_line_rule_inserts_on_even_lines() {
+        let mut board = Board::new(7, 7, &players(&[1, 2]));
+        assert_eq!(board.insert_positions().len(), 4 * 3);
+        let mut blocked = board.clone();
+        blocked.insert_rule = InsertRule::AnyLine;
+        // the usual fixed tiles sit on every even line
+        assert_eq!(blocked.insert_positions().len(), 4 * 3);
+        board.set_insert_rule(InsertRule::AnyLine);
+        assert!(board.fixed_tiles.is_empty());
+        assert_eq!(board.insert_positions().len(), 4 * 7);
+
+        let before = board.clone();
+        board.loose_tile_position = Some((Direction::North, 2));
+        board.shift_loose_tile();
+        assert_eq!(board.cells[0][2], before.loose_tile);
+        assert_eq!(board.cells[1][2], before.cells[0][2]);
+        assert_eq!(board.loose_tile, before.cells[6][2]);
+        assert_eq!(board.cells[0][1], before.cells[0][1]);
+
+        board.fixed_tiles.insert((4, 2));
+        assert!(board.is_line_blocked(Direction::North, 2));
+        assert!(board.is_line_blocked(Direction::East, 4));
+        assert!(!board.is_line_blocked(Direction::East, 2));
+        assert!(!board.insert_positions().contains(&(Direction::South, 2)));
+    }
+
+    #[test]
+    fn encoding_keeps_insert_rule_and_fixed_tiles() {
+        let mut board = Board::new(7, 7, &players(&[1, 2]));
+        let decoded = Board::decode(&board.encode()).unwrap();
+        assert_eq!(decoded.insert_rule, InsertRule::OddLinesOnly);
+        assert_eq!(decoded.fixed_tiles, board.fixed_tiles);
+
+        board.insert_rule = InsertRule::AnyLine;
+        board.fixed_tiles = [(3, 3), (0, 5)].iter().cloned().collect();
+        let decoded = Board::decode(&board.encode()).unwrap();
+        assert_eq!(decoded.insert_rule, InsertRule::AnyLine);
+        assert_eq!(decoded.fixed_tiles, board.fixed_tiles);
+        assert_eq!(decoded.cells, board.cells);
+    }
+
+    #[test]
+    fn iter_tiles_visits_every_cell_once() {
+        let mut board = board("─│┌ ┐└┘", &[(1, (0, 0))]);
+        let positions = board.iter_tiles().map(|(pos, _)| pos).collect::<Vec<_>>();
+        assert_eq!(positions, vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]);
+        for ((row, col), tile) in board.iter_tiles() {
+            assert_eq!(tile, board.get([col, row]));
+        }
+
+        for ((row, col), tile) in board.iter_tiles_mut() {
+            tile.whose_target = Some((row * 3 + col) as PlayerID);
+        }
+        assert_eq!(board.cells[1][2].whose_target, Some(5));
+        assert_eq!(board.cells[0][1].whose_target, Some(1));
+    }
+
+    #[test]
+    fn text_rendering_puts_tokens_in_their_cells() {
+        let mut board = board("─│ │─", &[(1, (0, 0)), (2, (1, 1))]);
+        board.treasures.insert((0, 1));
+        let center = |board: &Board, (row, col): (usize, usize)| {
+            let text = board.render_ascii();
+            let line = text.lines().nth(row * 3 + 1).unwrap().to_string();
+            line.chars().nth(col * 3 + 1).unwrap()
+        };
+        assert_eq!(center(&board, (0, 0)), 'A');
+        assert_eq!(center(&board, (1, 1)), 'B');
+        assert_eq!(center(&board, (0, 1)), '$');
+        assert_eq!(center(&board, (1, 0)), ' ');
+        let text = board.render_ascii();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(&lines[..3], &["████ █", " A █$█", "████ █"]);
+
+        board.move_player(2, (0, 0));
+        assert_eq!(center(&board, (0, 0)), '2');
+    }
+
+    #[test]
+    fn set_loose_tile_is_what_slides_in() {
+        let mut board = board("─── ─── ───", &[(1, (0, 0))]);
+        // facing south is the same straight tile as facing north, and nobody is player 9
+        board.set_loose_tile(Tile {
+            shape: Shape::I,
+            orientation: Direction::South,
+            whose_target: Some(9),
+        });
+        assert_eq!(*board.loose_tile(), '│'.try_into().unwrap());
+
+        board.loose_tile_position = Some((Direction::West, 0));
+        board.shift_loose_tile();
+        assert_eq!(board.cells, Board::parse_board("─── │── ───"));
+        assert_eq!(*board.loose_tile(), '─'.try_into().unwrap());
+    }
+
+    #[test]
+    fn shuffling_keeps_tokens_and_fixed_tiles() {
+        let mut board = Board::new(7, 7, &players(&[1, 2]));
+        // a token off the fixed grid keeps its tile too
+        board.move_player(2, (3, 4));
+        let before = board.clone();
+        board.shuffle_tiles(&mut StdRng::seed_from_u64(7));
+
+        assert_eq!(board.player_tokens, before.player_tokens);
+        assert_eq!(board.loose_tile, before.loose_tile);
+        assert_eq!(board.cells[3][4], before.cells[3][4]);
+        for &(row, col) in &board.fixed_tiles {
+            assert_eq!(board.cells[row][col], before.cells[row][col]);
+        }
+        let changed = board
+            .iter_tiles()
+            .filter(|&((row, col), tile)| *tile != before.cells[row][col])
+            .count();
+        assert!(changed > 0);
+        // targets stay put, whatever tile they are on now
+        for (pos, tile) in before.iter_tiles() {
+            assert_eq!(board.cells[pos.0][pos.1].whose_target, tile.whose_target);
+        }
+    }
+
+    #[test]
+    fn disallowed_sides_have_no_guides() {
+        let mut board = Board::new(7, 7, &players(&[1, 2]));
+        board.allowed_insert_directions =
+            [Direction::North, Direction::South].iter().cloned().collect();
+        let positions = board.insert_positions();
+        assert_eq!(positions.len(), 2 * 3);
+        assert!(positions
+            .iter()
+            .all(|&(dir, _)| dir == Direction::North || dir == Direction::South));
+
+        let mut data = board.encode();
+        let decoded = Board::decode(&data).unwrap();
+        assert_eq!(decoded.allowed_insert_directions, board.allowed_insert_directions);
+        // the mask sits before the move limit and topology, and can't be empty
+        let mask = data.len() - 7;
+        data[mask] = 0;
+        assert_eq!(Board::decode(&data).err(), Some(DecodeError::Invalid));
+    }
+
+    #[test]
+    fn torus_joins_the_east_and_west_edges() {
+        let mut straight = board("─── │││ ───", &[(1, (0, 0))]);
+        assert!(!straight.reachable_within((0, 0), 1).contains(&(0, 2)));
+        straight.topology = Topology::Toroidal;
+        // one step west across the seam, rather than two east
+        assert!(straight.reachable_within((0, 0), 1).contains(&(0, 2)));
+
+        let mut board = board("─│─ │││ ─│┘", &[(1, (0, 0))]);
+        assert_eq!(board.reachable_coords((0, 0)), [(0, 0)].iter().cloned().collect());
+        board.topology = Topology::Toroidal;
+        let expected = [(0, 0), (0, 2)].iter().cloned().collect();
+        assert_eq!(board.reachable_coords((0, 0)), expected);
+        // the seam is only open if both tiles open onto it
+        assert_eq!(board.reachable_coords((2, 0)), [(2, 0)].iter().cloned().collect());
+
+        let decoded = Board::decode(&board.encode()).unwrap();
+        assert_eq!(decoded.topology, Topology::Toroidal);
+    }
+
+    #[test]
+    fn token_distance_follows_the_paths() {
+        let mut board = board("┌│┐ │││ └─┘", &[(1, (0, 0)), (2, (0, 2))]);
+        // the top row is walled in the middle, so the way round is six steps rather than two
+        assert_eq!(board.token_distance(1, 2), Some(6));
+        assert_eq!(board.token_distance(2, 1), Some(6));
+        assert_eq!(board.token_distance(1, 1), Some(0));
+        assert_eq!(board.token_distance(1, 3), None);
+
+        board.cells[1][0] = '─'.try_into().unwrap();
+        board.cells[1][2] = '─'.try_into().unwrap();
+        assert_eq!(board.token_distance(1, 2), None);
+    }
+
+    #[test]
+    fn north_insert_ejects_the_bottom_of_its_column() {
+        let mut board = Board::new(7, 7, &players(&[1, 2]));
+        // the second guide is on the fourth column, since only odd lines move
+        assert_eq!(board.ejected_pos(Direction::North, 1), (6, 3));
+        assert_eq!(board.ejected_pos(Direction::South, 1), (0, 3));
+        assert_eq!(board.ejected_pos(Direction::West, 0), (1, 6));
+
+        let ejected = board.cells[6][3].clone();
+        board.loose_tile_position = Some((Direction::North, 1));
+        board.shift_loose_tile();
+        assert_eq!(board.loose_tile, ejected);
+
+        // every column has a guide when any line can move
+        board.insert_rule = InsertRule::AnyLine;
+        assert_eq!(board.ejected_pos(Direction::North, 1), (6, 1));
+    }
+
+    #[test]
+    fn insert_lists_exactly_the_shifted_line_as_changed() {
+        let _lock = anim::tests::lock_state();
+        let mut board = Board::new(7, 7, &players(&[1, 2]));
+        board.loose_tile_position = Some((Direction::North, 2));
+        board.insert_loose_tile();
+        let mut changed = board.last_changed_cells.clone();
+        changed.sort();
+        assert_eq!(changed, (0..7).map(|row| (row, 5)).collect::<Vec<_>>());
+
+        // the next insert forgets the last one's line
+        board.loose_tile_position = Some((Direction::West, 0));
+        board.insert_loose_tile();
+        let changed = board.last_changed_cells.iter().cloned().collect::<HashSet<_>>();
+        assert_eq!(changed, (0..7).map(|col| (1, col)).collect());
+        assert_eq!(board.last_changed_cells.len(), 7);
+    }
 }
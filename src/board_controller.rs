@@ -1,23 +1,60 @@
 //! Board controller
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 use web_sys::CanvasRenderingContext2d as Context;
 
-use crate::{Board, BoardView, Direction, Player, PlayerID};
+use crate::{AiDifficulty, Board, BoardView, Direction, InsertRule, Player, PlayerID};
+use crate::ai;
 use crate::anim::{self, AnimSync, RotateDir};
+use crate::colors::{self, PaletteKind};
 use crate::demo;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum TurnState {
     /// Insert tile
     InsertTile,
+    /// Wait for the insert to finish animating before the board shifts
+    Animating,
     /// Move token
     MoveToken,
 }
 
+/// Something that happened on the board, for sound, animation, and UI to react to
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoardEvent {
+    /// The given player's turn has started
+    TurnStarted(PlayerID),
+    /// The loose tile was inserted at the given guide
+    TileInserted {
+        /// Side of the board the tile was inserted from
+        dir: Direction,
+        /// Index of the insert guide along that side
+        idx: usize,
+    },
+    /// A player's token moved between (row, col) positions
+    TokenMoved {
+        /// Player whose token moved
+        player: PlayerID,
+        /// Position the token left
+        from: (usize, usize),
+        /// Position the token arrived at
+        to: (usize, usize),
+    },
+    /// The given player reached their target
+    TargetCollected(PlayerID),
+    /// The given player reached the score limit
+    GameWon(PlayerID),
+    /// The given player left the game
+    PlayerLeft(PlayerID),
+    /// The given player's turn has started, but no turn can get them to their target
+    NoProgress(PlayerID),
+    /// The given player went too many turns without getting closer to their target and is out
+    PlayerEliminated(PlayerID),
+}
+
 /// Controls session-level game settings
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BoardSettings {
@@ -27,6 +64,14 @@ pub struct BoardSettings {
     pub height: usize,
     /// Score required to win
     pub score_limit: u8,
+    /// Number of treasures scattered on the board for anyone to claim
+    pub treasure_count: usize,
+    /// Seconds each player has to take their turn, if limited
+    pub turn_time_limit: Option<f64>,
+    /// Which lines the loose tile can be inserted along
+    pub insert_rule: InsertRule,
+    /// Palette players are given colors from
+    pub palette: PaletteKind,
     /// Version (increases monotonically, for replicating edits in lobby)
     pub version: usize,
 }
@@ -37,11 +82,48 @@ impl Default for BoardSettings {
             width: 7,
             height: 7,
             score_limit: 10,
+            treasure_count: 0,
+            turn_time_limit: None,
+            insert_rule: InsertRule::OddLinesOnly,
+            palette: PaletteKind::Default,
             version: 0,
         }
     }
 }
 
+/// One way a player could play out their turn
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegalTurn {
+    /// Guide the loose tile is inserted at
+    pub insert: (Direction, usize),
+    /// Orientation of the loose tile when it is inserted
+    pub rotation: Direction,
+    /// (row, col) positions the player could then move to
+    pub moves: Vec<(usize, usize)>,
+}
+
+/// Outcome of a game played out by `BoardController::simulate_game`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameResult {
+    /// Player who reached the score limit, or `None` if the turn limit ran out first
+    pub winner: Option<PlayerID>,
+    /// Number of turns played
+    pub turns: u32,
+    /// Final score of each player
+    pub scores: BTreeMap<PlayerID, u8>,
+}
+
+/// Board and turn as they were before an insert or move, for undoing it
+#[derive(Debug, Clone)]
+struct UndoEntry {
+    board: Board,
+    turn_order: Vec<PlayerID>,
+    turn_state: TurnState,
+    highlighted_tile: (usize, usize),
+    last_insert: Option<(Direction, usize)>,
+    turns_completed: u32,
+}
+
 /// Handles events for DynaMaze game session
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BoardController {
@@ -53,42 +135,249 @@ pub struct BoardController {
     pub players: BTreeMap<PlayerID, Player>,
     /// Host
     pub host_id: PlayerID,
-    /// Turn order
+    /// Turn order, rotated so the active player is always first
     pub turn_order: Vec<PlayerID>,
+    /// Turn order as it was when the game started, less anyone who left
+    seats: Vec<PlayerID>,
     /// Current turn state
     pub turn_state: TurnState,
     /// Settings
     pub settings: BoardSettings,
+    /// Timestamp (in seconds) when the current turn runs out, once the turn's timer has started
+    pub turn_deadline: Option<f64>,
+    /// Guide the loose tile was last inserted at, if it has been inserted yet
+    pub last_insert: Option<(Direction, usize)>,
+    /// Number of turns completed so far
+    turns_completed: u32,
+    /// How long each completed turn took in seconds, with whose turn it was, oldest first
+    pub turn_durations: Vec<(PlayerID, f64)>,
+    /// Timestamp (in seconds) of the first tick of the current turn, local to this client
+    #[serde(skip)]
+    turn_started_at: Option<f64>,
+    /// Timestamp (in seconds) of the latest tick, local to this client
+    #[serde(skip)]
+    last_tick_at: Option<f64>,
+    /// Whether anyone may insert at any time and move any token, for practice
+    pub sandbox: bool,
+    /// Token picked up to be moved next in sandbox mode
+    pub sandbox_token: Option<PlayerID>,
+    /// Players the computer is playing for, such as those who disconnected mid-game
+    ai_players: BTreeMap<PlayerID, AiDifficulty>,
+    /// Timestamp (in seconds) each remote player was last heard from, local to the host
+    #[serde(skip)]
+    last_heard: BTreeMap<PlayerID, f64>,
+    /// Players the computer took over because they stopped being heard from, local to the host
+    #[serde(skip)]
+    disconnected: BTreeSet<PlayerID>,
+    /// Whether to check (slowly) at the start of each turn if the player can reach their target
+    pub enable_progress_warnings: bool,
+    /// Whether players who go `elimination_threshold` turns in a row without getting closer to
+    /// their target are knocked out
+    pub elimination_enabled: bool,
+    /// How many turns in a row a player can go without progress before being knocked out
+    pub elimination_threshold: u32,
+    /// How many turns in a row each player has gone without progress
+    stall_counts: BTreeMap<PlayerID, u32>,
+    /// Active player's score and walking distance to their target as their turn started, if known
+    turn_start_progress: Option<(u8, Option<u32>)>,
+    /// How many inserts and moves can be undone, oldest first to be forgotten past that
+    pub undo_depth: usize,
+    /// Snapshots from before recent inserts and moves, newest last, local to this client
+    #[serde(skip)]
+    undo_stack: VecDeque<UndoEntry>,
+    /// Snapshots undone since the last insert or move, newest last, local to this client
+    #[serde(skip)]
+    redo_stack: Vec<UndoEntry>,
+    /// Events not yet taken, local to this client and never sent over the network
+    #[serde(skip)]
+    events: Vec<BoardEvent>,
+    /// Cursor position while the loose tile is being dragged, local to this client
+    #[serde(skip)]
+    pub loose_drag: Option<[f64; 2]>,
 }
 
 impl BoardController {
+    /// Seconds without hearing from a remote player before the computer takes over for them
+    pub const DISCONNECT_TIMEOUT: f64 = 10.0;
+
     /// Creates a new board controller with a new board
     pub fn new(
         settings: BoardSettings,
         player_list: Vec<Player>,
         host_id: PlayerID,
+    ) -> BoardController {
+        Self::build(settings, player_list, host_id, false)
+    }
+
+    /// Creates a board controller on the demo board, with players taking turns in the order given
+    pub fn new_demo(
+        settings: BoardSettings,
+        player_list: Vec<Player>,
+        host_id: PlayerID,
+    ) -> BoardController {
+        Self::build(settings, player_list, host_id, true)
+    }
+
+    fn build(
+        settings: BoardSettings,
+        player_list: Vec<Player>,
+        host_id: PlayerID,
+        demo: bool,
     ) -> BoardController {
         let width = settings.width;
         let height = settings.height;
         let mut player_ids: Vec<PlayerID> = player_list.iter().map(|p| p.id).collect();
-        if !demo::is_demo() {
+        if !demo {
             player_ids.shuffle(&mut thread_rng());
         }
+        let mut player_list = player_list;
+        let collision = player_list.iter().enumerate().any(|(i, a)| {
+            player_list[i + 1..]
+                .iter()
+                .any(|b| colors::indistinguishable(a.color, b.color))
+        });
+        // if any two players can't be told apart, or their own colors may not be colorblind safe,
+        // recolor everyone (spreading hues out instead if the palette is too small for everyone)
+        if collision || settings.palette == PaletteKind::ColorblindSafe {
+            let count = player_list.len();
+            let distinct = colors::assign_from_palette(settings.palette, count)
+                .unwrap_or_else(|| colors::assign_distinct(count));
+            for (i, player) in player_list.iter_mut().enumerate() {
+                player.color = distinct[i % distinct.len()];
+            }
+        }
         let players = player_list.into_iter().map(|p| (p.id, p)).collect();
-        let board = Board::new(width, height, &players);
+        let mut board = if demo {
+            demo::new_board(&players)
+        } else {
+            Board::new(width, height, &players)
+        };
+        board.set_insert_rule(settings.insert_rule);
+        board.place_treasures(settings.treasure_count, &mut thread_rng());
         let highlighted_tile = board.player_pos(player_ids[0]);
-        BoardController {
+        let mut result = BoardController {
             board,
             highlighted_tile,
             players,
             host_id,
+            seats: player_ids.clone(),
             turn_order: player_ids,
             turn_state: TurnState::InsertTile,
             settings,
+            turn_deadline: None,
+            last_insert: None,
+            turns_completed: 0,
+            turn_durations: vec![],
+            turn_started_at: None,
+            last_tick_at: None,
+            sandbox: false,
+            sandbox_token: None,
+            ai_players: BTreeMap::new(),
+            last_heard: BTreeMap::new(),
+            disconnected: BTreeSet::new(),
+            enable_progress_warnings: false,
+            elimination_enabled: false,
+            elimination_threshold: 3,
+            stall_counts: BTreeMap::new(),
+            turn_start_progress: None,
+            undo_depth: 20,
+            undo_stack: VecDeque::new(),
+            redo_stack: vec![],
+            events: vec![],
+            loose_drag: None,
+        };
+        result.start_turn();
+        result
+    }
+
+    /// Starts a fresh game on a new board, keeping the host and other settings
+    pub fn reset(&mut self, width: usize, height: usize, player_list: Vec<Player>) {
+        let mut settings = self.settings.clone();
+        settings.width = width;
+        settings.height = height;
+        let sandbox = self.sandbox;
+        *self = BoardController::new(settings, player_list, self.host_id);
+        self.sandbox = sandbox;
+    }
+
+    /// Plays a whole game with the computer taking every turn, without drawing or animating
+    ///
+    /// Players take turns in the order given, so results can be compared by seat. The seed drives
+    /// treasure placement and easy players' choices, but the board itself is still random. Turns
+    /// skip the animated insert, but still can't undo the insert before them.
+    pub fn simulate_game(
+        settings: &BoardSettings,
+        players: Vec<Player>,
+        seed: u64,
+        max_turns: u32,
+        strategy: AiDifficulty,
+    ) -> GameResult {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let turn_order: Vec<PlayerID> = players.iter().map(|p| p.id).collect();
+        let players = players.into_iter().map(|p| (p.id, p)).collect();
+        let mut board = Board::new(settings.width, settings.height, &players);
+        board.set_insert_rule(settings.insert_rule);
+        board.place_treasures(settings.treasure_count, &mut rng);
+
+        let mut turns = 0;
+        let mut winner = None;
+        let mut last_insert: Option<(Direction, usize)> = None;
+        while !turn_order.is_empty() && winner.is_none() && turns < max_turns {
+            let id = turn_order[turns as usize % turn_order.len()];
+            let excluded = last_insert.map(|(dir, idx)| (dir.opposite(), idx));
+            let turn = ai::best_turn(&board, id, strategy, excluded, &mut rng);
+            let (dir, idx, orientation, move_to) = match turn {
+                Some(turn) => turn,
+                None => break,
+            };
+            board.apply_turn(id, (dir, idx), orientation, move_to, settings.score_limit, turns);
+            last_insert = Some((dir, idx));
+            turns += 1;
+            if board.player_tokens[&id].score >= settings.score_limit {
+                winner = Some(id);
+            }
+        }
+
+        GameResult {
+            winner,
+            turns,
+            scores: board
+                .player_tokens
+                .iter()
+                .map(|(&id, token)| (id, token.score))
+                .collect(),
+        }
+    }
+
+    /// Takes every event that has happened since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<BoardEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Declares that `child` lives with `parent` (shares their client, e.g. for hotseat play),
+    /// returns whether the relationship was recorded
+    ///
+    /// Children are only one level deep, so a parent may not itself be a child.
+    pub fn set_child(&mut self, parent: PlayerID, child: PlayerID) -> bool {
+        if parent == child || !self.players.contains_key(&parent) {
+            return false;
+        }
+        if self.players[&parent].parent.is_some() {
+            return false;
+        }
+        match self.players.get_mut(&child) {
+            Some(player) => {
+                player.parent = Some(parent);
+                true
+            }
+            None => false,
         }
     }
 
     /// Gets the effective local ID (the player living here who will be moving soonest)
+    ///
+    /// A client controls its own player and every child of that player, so the view should show
+    /// things from the perspective of whichever of them is next in the turn order.
     pub fn effective_local_id(&self, local_id: PlayerID) -> PlayerID {
         for id in &self.turn_order {
             let player = &self.players[id];
@@ -99,6 +388,135 @@ impl BoardController {
         local_id
     }
 
+    /// Hands the given player over to the computer, returns whether they are in the game
+    pub fn set_ai_control(&mut self, player_id: PlayerID, difficulty: AiDifficulty) -> bool {
+        if !self.players.contains_key(&player_id) {
+            return false;
+        }
+        self.ai_players.insert(player_id, difficulty);
+        true
+    }
+
+    /// Gives the given player back to a human, returns whether the computer was playing for them
+    pub fn clear_ai_control(&mut self, player_id: PlayerID) -> bool {
+        self.ai_players.remove(&player_id).is_some()
+    }
+
+    /// Notes that the client of the given player is still connected, returns whether that gave
+    /// any of its players back from the computer
+    ///
+    /// Only the host needs to track this. Players living with the given one are heard from too.
+    pub fn note_heard_from(&mut self, player_id: PlayerID, now: f64) -> bool {
+        let ids = self
+            .players
+            .values()
+            .filter(|player| player.lives_with(player_id))
+            .map(|player| player.id)
+            .collect::<Vec<_>>();
+        let mut changed = false;
+        for id in ids {
+            self.last_heard.insert(id, now);
+            if self.disconnected.remove(&id) {
+                changed |= self.clear_ai_control(id);
+            }
+        }
+        changed
+    }
+
+    /// Hands players who haven't been heard from in `DISCONNECT_TIMEOUT` over to the computer,
+    /// returns whether anyone was taken over
+    ///
+    /// Only the host should call this, once per frame. The host's own players are never taken
+    /// over, and everyone else counts as heard from the first time this runs.
+    pub fn take_over_disconnected(&mut self, now: f64) -> bool {
+        let host_id = self.host_id;
+        let remote = self
+            .players
+            .values()
+            .filter(|player| !player.lives_with(host_id))
+            .map(|player| player.id)
+            .collect::<Vec<_>>();
+        let mut changed = false;
+        for id in remote {
+            let last_heard = *self.last_heard.entry(id).or_insert(now);
+            let silent = now - last_heard > Self::DISCONNECT_TIMEOUT;
+            if silent && !self.ai_players.contains_key(&id) {
+                self.set_ai_control(id, AiDifficulty::Medium);
+                self.disconnected.insert(id);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Keeps what only the host tracks from the controller this one is replacing, such as when a
+    /// guest's state arrives at the given timestamp (in seconds)
+    ///
+    /// Guests never see who was heard from when, so without this the host would forget which
+    /// players it took over and never give them back. Guests don't time turns either, so a turn
+    /// a guest ended is timed here, up to when the news arrived, and one still going keeps the
+    /// time it started.
+    pub fn carry_host_state(&mut self, previous: &BoardController, now: f64) {
+        self.last_heard = previous.last_heard.clone();
+        self.disconnected = previous.disconnected.clone();
+        self.turn_durations = previous.turn_durations.clone();
+        self.last_tick_at = previous.last_tick_at;
+        if self.turns_completed == previous.turns_completed {
+            self.turn_started_at = previous.turn_started_at;
+        } else if self.turns_completed > previous.turns_completed {
+            if let Some(start) = previous.turn_started_at {
+                let player_id = previous.active_player_id();
+                self.turn_durations.push((player_id, now - start));
+            }
+        }
+    }
+
+    /// Gets how well the computer is playing for the given player, if it is
+    pub fn ai_difficulty(&self, player_id: PlayerID) -> Option<AiDifficulty> {
+        self.ai_players.get(&player_id).cloned()
+    }
+
+    /// Plays the next step of the active player's turn if the computer is playing for them,
+    /// returns the insert or move made
+    ///
+    /// Only the host should step the computer, once per frame. The computer inserts just like a
+    /// person would, so the slide plays out (and can be undone) before it moves. A turn picked up
+    /// after the insert, or one where no insert is allowed, moves as well as it can from there.
+    pub fn step_ai(&mut self) -> Option<BoardEvent> {
+        if self.sandbox || self.winner().is_some() {
+            return None;
+        }
+        let player_id = self.active_player_id();
+        let difficulty = self.ai_difficulty(player_id)?;
+        let mut rng = thread_rng();
+        match self.turn_state {
+            TurnState::InsertTile => {
+                let excluded = self.reversed_insert();
+                let turn = ai::best_turn(&self.board, player_id, difficulty, excluded, &mut rng);
+                if let Some((dir, idx, orientation, _)) = turn {
+                    self.board.loose_tile.orientation = orientation;
+                    if self.perform_insert(dir, idx) {
+                        return Some(BoardEvent::TileInserted { dir, idx });
+                    }
+                }
+            }
+            TurnState::Animating => {
+                if !self.finish_insert() {
+                    return None;
+                }
+            }
+            TurnState::MoveToken => {}
+        }
+        let from = self.board.player_pos(player_id);
+        let to = ai::best_move(&self.board, player_id, difficulty, &mut rng);
+        self.attempt_move(to);
+        Some(BoardEvent::TokenMoved {
+            player: player_id,
+            from,
+            to,
+        })
+    }
+
     /// Gets the ID of the player whose turn it is
     pub fn active_player_id(&self) -> PlayerID {
         self.turn_order[0]
@@ -111,22 +529,43 @@ impl BoardController {
 
     fn move_loose_tile(&mut self, new_loose_tile_position: (Direction, usize)) -> bool {
         let old_loose_tile_position = self.board.loose_tile_position;
-        self.board.loose_tile_position = new_loose_tile_position;
-        old_loose_tile_position != new_loose_tile_position
+        self.board.loose_tile_position = Some(new_loose_tile_position);
+        old_loose_tile_position != self.board.loose_tile_position
     }
 
     fn rotate_loose_tile(&mut self, dir: RotateDir) -> bool {
+        let old_orientation = self.board.loose_tile.orientation;
         self.board.loose_tile.rotate(match dir {
             RotateDir::CW => Direction::East,
             RotateDir::CCW => Direction::West,
         });
-        let sync = AnimSync::Rotate(dir);
-        anim::STATE.write().unwrap().apply_send(sync);
+        // animate toward the unnormalized orientation so the tile keeps turning the way it was told
+        anim::start_loose_rotation(old_orientation, self.board.loose_tile.orientation);
+        self.board.loose_tile.normalize_orientation();
         true
     }
 
+    /// Gets whether input should currently insert and whether it should move
+    fn input_phase(&self) -> (bool, bool) {
+        if self.sandbox {
+            return match self.turn_state {
+                TurnState::Animating => (false, false),
+                TurnState::InsertTile | TurnState::MoveToken => (true, true),
+            };
+        }
+        match self.turn_state {
+            TurnState::InsertTile => (true, false),
+            TurnState::Animating => (false, false),
+            TurnState::MoveToken => (false, true),
+        }
+    }
+
     /// Checks if the player whose turn it is lives with this player (equal to or child of)
     pub fn local_turn(&self, local_id: PlayerID) -> bool {
+        // in sandbox mode it's everybody's turn
+        if self.sandbox {
+            return true;
+        }
         let active_player = self.active_player();
         active_player.lives_with(local_id)
     }
@@ -144,10 +583,7 @@ impl BoardController {
             return false;
         }
 
-        let (should_insert, should_move) = match self.turn_state {
-            TurnState::InsertTile => (true, false),
-            TurnState::MoveToken => (false, true),
-        };
+        let (should_insert, should_move) = self.input_phase();
 
         let mut dirty = false;
 
@@ -161,13 +597,20 @@ impl BoardController {
                 // insert the tile
                 self.insert_loose_tile();
             } else {
-                // otherwise, rotate the loose tile
-                self.rotate_loose_tile(RotateDir::CW);
+                // otherwise, rotate the loose tile whichever way that side points
+                let dir = match view.in_loose_rotate_control(&pos, self, ctx) {
+                    Some(false) => RotateDir::CCW,
+                    _ => RotateDir::CW,
+                };
+                self.rotate_loose_tile(dir);
             }
             dirty = true;
         } else if let Some(pos) = view.in_tile(&pos, self, ctx) {
-            // if clicked inside a tile, if we should be moving...
-            if should_move {
+            if self.sandbox && self.sandbox_token.is_none() {
+                // in sandbox mode, the first click picks up whichever token is there
+                dirty = self.pick_up_sandbox_token(pos);
+            } else if should_move {
+                // if clicked inside a tile, if we should be moving...
                 dirty = dirty || self.attempt_move(pos);
             }
         }
@@ -183,6 +626,65 @@ impl BoardController {
         dirty
     }
 
+    /// Handles mousedown event, returns whether or not the state may have changed
+    pub fn on_mousedown(
+        &mut self,
+        event: &web_sys::MouseEvent,
+        local_id: PlayerID,
+        view: &BoardView,
+        ctx: &Context,
+    ) -> bool {
+        // never do anything if this player is not the active player
+        if !self.local_turn(local_id) {
+            return false;
+        }
+
+        let (should_insert, _) = self.input_phase();
+
+        let pos = [event.offset_x() as f64, event.offset_y() as f64];
+        // only pick up the loose tile with the primary button
+        if should_insert && event.button() == 0 && view.in_loose_tile(&pos, self, ctx) {
+            self.begin_loose_drag(pos);
+        }
+
+        false
+    }
+
+    /// Handles mouseup event, returns whether or not the state may have changed
+    pub fn on_mouseup(
+        &mut self,
+        event: &web_sys::MouseEvent,
+        view: &BoardView,
+        ctx: &Context,
+    ) -> bool {
+        let pos = [event.offset_x() as f64, event.offset_y() as f64];
+        self.end_loose_drag(pos, view, ctx)
+    }
+
+    /// Picks up the loose tile at the given cursor position
+    pub fn begin_loose_drag(&mut self, pos: [f64; 2]) {
+        self.loose_drag = Some(pos);
+    }
+
+    /// Moves the dragged loose tile to follow the cursor, if it is being dragged
+    pub fn update_loose_drag(&mut self, pos: [f64; 2]) {
+        if self.loose_drag.is_some() {
+            self.loose_drag = Some(pos);
+        }
+    }
+
+    /// Drops the dragged loose tile, inserting it if it landed on an insert guide, returns whether or not the state may have changed
+    pub fn end_loose_drag(&mut self, pos: [f64; 2], view: &BoardView, ctx: &Context) -> bool {
+        if self.loose_drag.take().is_none() {
+            return false;
+        }
+        match view.in_insert_guide(&pos, self, ctx) {
+            Some((dir, guide_idx)) => self.perform_insert(dir, guide_idx),
+            // dropped elsewhere, so the tile goes back where it was
+            None => false,
+        }
+    }
+
     /// Handles mousemove event, returns whether or not the state may have changed
     pub fn on_mousemove(
         &mut self,
@@ -196,14 +698,12 @@ impl BoardController {
             return false;
         }
 
-        let (should_insert, should_move) = match self.turn_state {
-            TurnState::InsertTile => (true, false),
-            TurnState::MoveToken => (false, true),
-        };
+        let (should_insert, should_move) = self.input_phase();
 
         let mut dirty = false;
 
         let pos = [event.offset_x() as f64, event.offset_y() as f64];
+        self.update_loose_drag(pos);
         if should_insert {
             if let Some(new_loose_tile_position) = view.in_insert_guide(&pos, self, ctx) {
                 dirty = dirty || self.move_loose_tile(new_loose_tile_position);
@@ -235,10 +735,7 @@ impl BoardController {
             return false;
         }
 
-        let (should_insert, should_move) = match self.turn_state {
-            TurnState::InsertTile => (true, false),
-            TurnState::MoveToken => (false, true),
-        };
+        let (should_insert, should_move) = self.input_phase();
 
         let mut dirty = false;
         let key = event.code();
@@ -281,41 +778,304 @@ impl BoardController {
         dirty
     }
 
+    /// Turns sandbox mode on or off, going back to enforcing turns when it is turned off
+    pub fn set_sandbox(&mut self, sandbox: bool) {
+        self.sandbox = sandbox;
+        self.sandbox_token = None;
+        if !sandbox {
+            if let TurnState::MoveToken = self.turn_state {
+                self.turn_state = TurnState::InsertTile;
+            }
+        }
+    }
+
+    /// Picks up the token on the given tile to be moved next in sandbox mode, returns whether there was one
+    fn pick_up_sandbox_token(&mut self, pos: (usize, usize)) -> bool {
+        let token = self
+            .board
+            .player_tokens
+            .values()
+            .find(|token| token.position == pos);
+        self.sandbox_token = token.map(|token| token.player_id);
+        self.highlighted_tile = pos;
+        self.sandbox_token.is_some()
+    }
+
     fn attempt_move(&mut self, pos: (usize, usize)) -> bool {
         let (row, col) = pos;
-        // if that tile is reachable from the active player's position...
-        let id = self.active_player_id();
+        // if that tile is reachable from the moving token's position...
+        let id = match self.sandbox_token.take() {
+            Some(id) if self.sandbox => id,
+            _ => self.active_player_id(),
+        };
         if self
             .board
-            .reachable_coords(self.board.player_pos(id))
+            .movable_coords(self.board.player_pos(id))
             .contains(&pos)
         {
+            self.record_undo();
             // move the active player to the given position
+            let from = self.board.player_pos(id);
             self.board.move_player(id, pos);
+            self.events.push(BoardEvent::TokenMoved {
+                player: id,
+                from,
+                to: pos,
+            });
+            if self.board.claim_treasure(id, pos) {
+                self.check_finished(id);
+            }
             // if the player has reached their target...
             if self.board.get([col, row]).whose_target == Some(id) {
                 // advance the player to the next target
                 self.board.player_reached_target(id);
+                self.events.push(BoardEvent::TargetCollected(id));
+                self.check_finished(id);
             }
-            // advance turn order
+            // advance turn order, unless nobody is taking turns
             self.turn_state = TurnState::InsertTile;
-            self.rotate_turn_order();
+            if !self.sandbox {
+                self.advance_turn();
+            }
             return true;
         }
         false
     }
 
+    /// Marks the given player as finished if their score just reached the limit, announcing it
+    fn check_finished(&mut self, player_id: PlayerID) {
+        let turn = self.turns_completed;
+        let score_limit = self.settings.score_limit;
+        if self.board.mark_finished(player_id, score_limit, turn) {
+            self.events.push(BoardEvent::GameWon(player_id));
+        }
+    }
+
     fn insert_loose_tile(&mut self) -> bool {
-        self.board.insert_loose_tile();
+        match self.board.loose_tile_position {
+            Some((dir, guide_idx)) => self.perform_insert(dir, guide_idx),
+            None => false,
+        }
+    }
+
+    /// Starts inserting the loose tile at the given guide, returns whether or not the state may have changed
+    ///
+    /// Inserting happens in two phases: this moves the loose tile to the guide, starts the slide
+    /// animation, and enters `TurnState::Animating`, leaving the board untouched, and
+    /// `finish_insert` shifts the board and advances to `TurnState::MoveToken` once the slide has
+    /// settled. Input is ignored in between.
+    pub fn perform_insert(&mut self, dir: Direction, guide_idx: usize) -> bool {
+        match self.turn_state {
+            TurnState::InsertTile => {}
+            TurnState::Animating | TurnState::MoveToken => return false,
+        }
+        if !self.can_insert_at(dir, guide_idx) {
+            return false;
+        }
+        // remember the turn before it leaves the insert phase, so undoing lands back in it
+        self.record_undo();
+        self.move_loose_tile((dir, guide_idx));
+        let sync = AnimSync::Slide(dir.opposite(), self.board.insert_rule.guide_line(guide_idx));
+        anim::STATE.write().unwrap().apply_send(sync);
         // advance turn state
-        self.turn_state = TurnState::MoveToken;
+        self.turn_state = TurnState::Animating;
+        true
+    }
+
+    /// Completes an animating insert if its slide has settled, returns whether or not the state may have changed
+    pub fn finish_insert(&mut self) -> bool {
+        match self.turn_state {
+            TurnState::Animating => {}
+            TurnState::InsertTile | TurnState::MoveToken => return false,
+        }
+        if !anim::STATE.read().unwrap().loose_insert.is_settled() {
+            return false;
+        }
+        if let Some((dir, idx)) = self.board.loose_tile_position {
+            self.events.push(BoardEvent::TileInserted { dir, idx });
+        }
+        self.last_insert = self.board.loose_tile_position;
+        self.board.insert_loose_tile();
+        // advance turn state, though in sandbox mode inserting never stops being allowed
+        self.turn_state = if self.sandbox {
+            TurnState::InsertTile
+        } else {
+            TurnState::MoveToken
+        };
         true
     }
 
+    fn snapshot(&self) -> UndoEntry {
+        UndoEntry {
+            board: self.board.clone(),
+            turn_order: self.turn_order.clone(),
+            turn_state: self.turn_state.clone(),
+            highlighted_tile: self.highlighted_tile,
+            last_insert: self.last_insert,
+            turns_completed: self.turns_completed,
+        }
+    }
+
+    fn restore(&mut self, entry: UndoEntry) {
+        self.board = entry.board;
+        self.turn_order = entry.turn_order;
+        self.turn_state = entry.turn_state;
+        self.highlighted_tile = entry.highlighted_tile;
+        self.last_insert = entry.last_insert;
+        self.turns_completed = entry.turns_completed;
+        self.loose_drag = None;
+        self.sandbox_token = None;
+        self.turn_deadline = None;
+    }
+
+    /// Remembers the state before an insert or move, forgetting the oldest past `undo_depth` and
+    /// anything that could have been redone
+    fn record_undo(&mut self) {
+        self.redo_stack.clear();
+        if self.undo_depth == 0 {
+            self.undo_stack.clear();
+            return;
+        }
+        while self.undo_stack.len() >= self.undo_depth {
+            self.undo_stack.pop_front();
+        }
+        let entry = self.snapshot();
+        self.undo_stack.push_back(entry);
+    }
+
+    /// Takes back the last insert or move, returns whether or not there was one to take back
+    pub fn undo(&mut self) -> bool {
+        match self.turn_state {
+            TurnState::InsertTile | TurnState::MoveToken => {}
+            TurnState::Animating => return false,
+        }
+        let entry = match self.undo_stack.pop_back() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let current = self.snapshot();
+        self.redo_stack.push(current);
+        self.restore(entry);
+        true
+    }
+
+    /// Redoes the last undone insert or move, returns whether or not there was one to redo
+    pub fn redo(&mut self) -> bool {
+        match self.turn_state {
+            TurnState::InsertTile | TurnState::MoveToken => {}
+            TurnState::Animating => return false,
+        }
+        let entry = match self.redo_stack.pop() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let current = self.snapshot();
+        self.undo_stack.push_back(current);
+        self.restore(entry);
+        true
+    }
+
+    /// Runs the turn timer, passing the turn if it has run out, returns whether or not the state may have changed
+    ///
+    /// The first tick of a turn starts its timer. Ticks also time each turn for `turn_durations`,
+    /// whether or not turns have a time limit. Only the host should tick, so every turn ends by
+    /// the same clock and differences between clocks only matter for the countdown others see.
+    pub fn tick(&mut self, now: f64) -> bool {
+        self.last_tick_at = Some(now);
+        if self.turn_started_at.is_none() {
+            self.turn_started_at = Some(now);
+        }
+        let limit = match self.settings.turn_time_limit {
+            Some(limit) if !self.sandbox => limit,
+            _ => return false,
+        };
+        let deadline = match self.turn_deadline {
+            Some(deadline) => deadline,
+            None => {
+                self.turn_deadline = Some(now + limit);
+                return true;
+            }
+        };
+        if now < deadline {
+            return false;
+        }
+        // let an insert that is already sliding land first
+        if let TurnState::Animating = self.turn_state {
+            return false;
+        }
+        self.advance_turn();
+        true
+    }
+
+    /// Gets the (row, col) positions a token at the given position could move to on the board as is
+    /// (within the move limit, if any), whoever's turn it is and whether any token is there
+    pub fn reachable_from(&self, pos: (usize, usize)) -> HashSet<(usize, usize)> {
+        self.board.movable_coords(pos)
+    }
+
+    /// Gets the (row, col) positions the given player could reach if the loose tile were inserted at the given guide with the given orientation
+    pub fn reachable_after_insert(
+        &self,
+        dir: Direction,
+        guide_idx: usize,
+        orientation: Direction,
+        player_id: PlayerID,
+    ) -> HashSet<(usize, usize)> {
+        let hypothetical = self.board.after_insert((dir, guide_idx), orientation);
+        hypothetical.movable_coords(hypothetical.player_pos(player_id))
+    }
+
+    /// Gets the guide that would undo the last insert, which can't be inserted at this turn
+    pub fn reversed_insert(&self) -> Option<(Direction, usize)> {
+        self.last_insert.map(|(dir, idx)| (dir.opposite(), idx))
+    }
+
+    /// Checks if the rules allow inserting at the given guide this turn
+    pub fn can_insert_at(&self, dir: Direction, guide_idx: usize) -> bool {
+        self.board.allowed_insert_directions.contains(&dir)
+            && !self.board.is_line_blocked(dir, guide_idx)
+            && self.reversed_insert() != Some((dir, guide_idx))
+    }
+
+    /// Gets every turn the given player could take from the current board
+    ///
+    /// Only the loose tile's distinct orientations are tried, so a straight tile only gets tried
+    /// two ways. Moves are sorted so the order is always the same.
+    pub fn legal_turns(&self, player_id: PlayerID) -> Vec<LegalTurn> {
+        let rotations = self.board.loose_tile.shape.distinct_orientations();
+        let mut result = vec![];
+        for insert in self.board.insert_positions() {
+            if !self.can_insert_at(insert.0, insert.1) {
+                continue;
+            }
+            for &rotation in &rotations {
+                let hypothetical = self.board.after_insert(insert, rotation);
+                let mut moves = hypothetical
+                    .movable_coords(hypothetical.player_pos(player_id))
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                moves.sort();
+                result.push(LegalTurn {
+                    insert,
+                    rotation,
+                    moves,
+                });
+            }
+        }
+        result
+    }
+
     fn handle_insert_key_direction(&mut self, move_dir: Direction) -> bool {
-        let old_loose_tile_position = self.board.loose_tile_position;
-        let guides_x = self.board.width() / 2;
-        let guides_y = self.board.height() / 2;
+        let old_loose_tile_position = match self.board.loose_tile_position {
+            Some(position) => position,
+            // start from the first guide on whichever side was picked, if that side is allowed
+            None if self.board.allowed_insert_directions.contains(&move_dir) => {
+                return self.move_loose_tile((move_dir, 0));
+            }
+            None => return false,
+        };
+        let guides_x = self.board.insert_rule.guide_count(self.board.width());
+        let guides_y = self.board.insert_rule.guide_count(self.board.height());
         let new_loose_tile_position = match (move_dir, old_loose_tile_position) {
             (Direction::West, (Direction::East, n)) => {
                 let count = guides_x - 1;
@@ -373,6 +1133,9 @@ impl BoardController {
             (Direction::North, (d, n)) => (d, n.saturating_sub(1)),
             _ => unreachable!("bad key"),
         };
+        if !self.board.allowed_insert_directions.contains(&new_loose_tile_position.0) {
+            return false;
+        }
         self.move_loose_tile(new_loose_tile_position)
     }
 
@@ -389,21 +1152,840 @@ impl BoardController {
         orig_highlight != new_highlight
     }
 
-    fn rotate_turn_order(&mut self) {
-        let mut rest = self.turn_order.split_off(1);
-        rest.append(&mut self.turn_order);
-        self.turn_order = rest;
+    /// Removes a player who left mid-game, passing the turn on if it was theirs, returns whether or not the state may have changed
+    ///
+    /// Their target goes back to being an ordinary tile. The last player left can't be removed.
+    pub fn remove_player(&mut self, player_id: PlayerID) -> bool {
+        let index = match self.turn_order.iter().position(|&id| id == player_id) {
+            Some(index) if self.turn_order.len() > 1 => index,
+            _ => return false,
+        };
+        self.turn_order.remove(index);
+        self.seats.retain(|&id| id != player_id);
+        self.players.remove(&player_id);
+        self.remove_token(player_id);
+        self.events.push(BoardEvent::PlayerLeft(player_id));
+        // if it was their turn, the next player is already up
+        if index == 0 {
+            self.loose_drag = None;
+            self.turn_state = TurnState::InsertTile;
+            self.highlighted_tile = self.board.player_pos(self.turn_order[0]);
+            self.turn_deadline = None;
+            self.turn_started_at = None;
+            self.start_turn();
+        }
+        true
+    }
+
+    /// Takes the given player's token and target off the board
+    fn remove_token(&mut self, player_id: PlayerID) {
+        self.board.player_tokens.remove(&player_id);
+        for (_, tile) in self.board.iter_tiles_mut() {
+            if tile.whose_target == Some(player_id) {
+                tile.whose_target = None;
+            }
+        }
+        if self.board.loose_tile.whose_target == Some(player_id) {
+            self.board.loose_tile.whose_target = None;
+        }
+        if self.sandbox_token == Some(player_id) {
+            self.sandbox_token = None;
+        }
+        self.ai_players.remove(&player_id);
+        self.stall_counts.remove(&player_id);
+    }
+
+    /// Gets the given player's score and walking distance to their target, if it can be reached
+    fn progress(&self, player_id: PlayerID) -> Option<(u8, Option<u32>)> {
+        let token = self.board.player_tokens.get(&player_id)?;
+        let distance = self
+            .board
+            .target_pos(player_id)
+            .and_then(|target| self.board.shortest_path(token.position, target))
+            .map(|path| path.len() as u32 - 1);
+        Some((token.score, distance))
+    }
+
+    /// Counts whether the active player got any closer to their target this turn, returns whether
+    /// they have now gone too long without doing so
+    fn record_stall(&mut self) -> bool {
+        let player_id = self.active_player_id();
+        let before = match self.turn_start_progress.take() {
+            Some(before) => before,
+            None => return false,
+        };
+        let after = match self.progress(player_id) {
+            Some(after) => after,
+            None => return false,
+        };
+        let progressed = match (before, after) {
+            ((old_score, _), (new_score, _)) if new_score > old_score => true,
+            ((_, Some(old_distance)), (_, Some(new_distance))) => new_distance < old_distance,
+            ((_, None), (_, Some(_))) => true,
+            (_, (_, None)) => false,
+        };
+        let stalls = self.stall_counts.entry(player_id).or_insert(0);
+        if progressed {
+            *stalls = 0;
+        } else {
+            *stalls += 1;
+        }
+        *stalls >= self.elimination_threshold
+    }
+
+    /// Knocks the given player out, leaving them in the player list but off the board
+    fn eliminate_player(&mut self, player_id: PlayerID) {
+        self.turn_order.retain(|&id| id != player_id);
+        self.remove_token(player_id);
+        self.events.push(BoardEvent::PlayerEliminated(player_id));
+    }
+
+    /// Gets how many turns have been completed, counting passed turns
+    pub fn turn_number(&self) -> u32 {
+        self.turns_completed
+    }
+
+    /// Gets how long the given player's timed turns took on average, in seconds, if they had any
+    pub fn average_turn_time(&self, player_id: PlayerID) -> Option<f64> {
+        let durations = self
+            .turn_durations
+            .iter()
+            .filter(|&&(id, _)| id == player_id)
+            .map(|&(_, duration)| duration)
+            .collect::<Vec<_>>();
+        if durations.is_empty() {
+            return None;
+        }
+        Some(durations.iter().sum::<f64>() / durations.len() as f64)
+    }
+
+    /// Checks if somebody has won
+    pub fn is_finished(&self) -> bool {
+        self.winner().is_some()
+    }
+
+    /// Gets the active player's seat, their place in the turn order as the game started
+    pub fn active_index(&self) -> usize {
+        let active = self.active_player_id();
+        self.seats.iter().position(|&id| id == active).unwrap_or(0)
+    }
+
+    /// Ends the active player's turn, whether or not they did anything, and starts the next
+    /// player's, skipping anyone who has already reached the score limit
+    pub fn advance_turn(&mut self) {
+        let stalled = if self.elimination_enabled && !self.sandbox && self.record_stall() {
+            Some(self.active_player_id())
+        } else {
+            None
+        };
+        // the turn ended no earlier than the latest tick, which is close enough for stats
+        if let (Some(start), Some(end)) = (self.turn_started_at.take(), self.last_tick_at) {
+            let player_id = self.active_player_id();
+            self.turn_durations.push((player_id, end - start));
+        }
+        self.loose_drag = None;
+        self.turn_state = TurnState::InsertTile;
+        self.turns_completed += 1;
+        let score_limit = self.settings.score_limit;
+        for _ in 0..self.turn_order.len() {
+            self.turn_order.rotate_left(1);
+            let finished = match self.board.player_tokens.get(&self.turn_order[0]) {
+                Some(token) => token.score >= score_limit,
+                None => true,
+            };
+            if !finished {
+                break;
+            }
+        }
+        // the last player standing is never knocked out
+        if let Some(player_id) = stalled {
+            if self.turn_order[0] != player_id {
+                self.eliminate_player(player_id);
+            }
+        }
         // reset the highlighted tile
         self.highlighted_tile = self.board.player_pos(self.turn_order[0]);
+        self.turn_deadline = None;
+        self.start_turn();
+    }
+
+    /// Announces the active player's turn, warning if it can't get them anywhere
+    fn start_turn(&mut self) {
+        let player_id = self.active_player_id();
+        self.events.push(BoardEvent::TurnStarted(player_id));
+        if self.elimination_enabled {
+            self.turn_start_progress = self.progress(player_id);
+        }
+        if self.enable_progress_warnings && !self.can_player_progress(player_id) {
+            self.events.push(BoardEvent::NoProgress(player_id));
+        }
+    }
+
+    /// Checks if any legal insert lets the given player reach their target this turn
+    pub fn can_player_progress(&self, player_id: PlayerID) -> bool {
+        let rotations = self.board.loose_tile.shape.distinct_orientations();
+        self.board.insert_positions().into_iter().any(|insert| {
+            if !self.can_insert_at(insert.0, insert.1) {
+                return false;
+            }
+            rotations.iter().any(|&rotation| {
+                let hypothetical = self.board.after_insert(insert, rotation);
+                match hypothetical.target_pos(player_id) {
+                    Some(target) => hypothetical
+                        .movable_coords(hypothetical.player_pos(player_id))
+                        .contains(&target),
+                    None => false,
+                }
+            })
+        })
     }
 
     /// Gets the player who has no targets remaining, if one exists
+    ///
+    /// If several players have, whoever got there on the earliest turn wins, then whoever sits
+    /// first in the turn order as the game started.
     pub fn winner(&self) -> Option<&Player> {
+        let seat_index = |id: PlayerID| self.seats.iter().position(|&x| x == id);
         self.board
             .player_tokens
+            .values()
+            .filter(|token| token.score >= self.settings.score_limit)
+            .min_by_key(|token| {
+                let finished_turn = token.finished_turn.unwrap_or(u32::MAX);
+                let index = seat_index(token.player_id).unwrap_or(usize::MAX);
+                (finished_turn, index)
+            })
+            .map(|token| &self.players[&token.player_id])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::Tile;
+    use crate::colors::Color;
+
+    fn player(id: PlayerID) -> Player {
+        Player::new(format!("Player {}", id), Color(0.5, 0.5, 0.5), id)
+    }
+
+    /// Starts a game on a random 7x7 board where the given players take turns in the order given
+    fn controller(ids: &[PlayerID]) -> BoardController {
+        let players = ids.iter().map(|&id| player(id)).collect();
+        let mut result = BoardController::new(BoardSettings::default(), players, ids[0]);
+        result.turn_order = ids.to_vec();
+        result.seats = ids.to_vec();
+        result.take_events();
+        result
+    }
+
+    /// Starts a game for player 1 alone, in the northwest corner of a board from a tile spec
+    fn tiny_controller(spec: &str) -> BoardController {
+        let cells = Board::parse_board(spec);
+        let starts = [(1, (0, 0))].iter().cloned().collect();
+        let players = [(1, player(1))].iter().cloned().collect();
+        let (width, height) = (cells[0].len(), cells.len());
+        let board = Board::with_layout(width, height, &players, starts, BTreeMap::new());
+        let mut result = controller(&[1]);
+        result.board = board.unwrap();
+        result.board.cells = cells;
+        result
+    }
+
+    /// Lets any insert slide that is playing finish
+    fn settle() {
+        anim::step_once(1.0);
+    }
+
+    #[test]
+    fn insert_shifts_board_once_slide_settles() {
+        let _lock = anim::tests::lock_state();
+        let mut controller = controller(&[1, 2]);
+        let before = controller.board.clone();
+        assert!(controller.perform_insert(Direction::North, 0));
+        assert!(matches!(controller.turn_state, TurnState::Animating));
+        assert_eq!(controller.board.cells, before.cells);
+        assert!(!controller.perform_insert(Direction::North, 1));
+
+        settle();
+        assert!(controller.finish_insert());
+        assert!(matches!(controller.turn_state, TurnState::MoveToken));
+        let mut expected = before.clone();
+        expected.loose_tile_position = Some((Direction::North, 0));
+        expected.shift_loose_tile();
+        assert_eq!(controller.board.cells, expected.cells);
+        assert_eq!(controller.last_insert, Some((Direction::North, 0)));
+        assert!(!controller.finish_insert());
+    }
+
+    #[test]
+    fn demo_keeps_its_board_and_turn_order() {
+        let players = vec![player(3), player(1), player(2)];
+        let controller = BoardController::new_demo(BoardSettings::default(), players, 3);
+        assert_eq!(controller.turn_order, vec![3, 1, 2]);
+        let players = controller.players.clone();
+        assert_eq!(controller.board.cells, demo::new_board(&players).cells);
+    }
+
+    #[test]
+    fn input_waits_while_insert_animates() {
+        let _lock = anim::tests::lock_state();
+        let mut controller = controller(&[1, 2]);
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+        assert_eq!(controller.input_phase(), (true, false));
+        assert!(controller.perform_insert(Direction::East, 2));
+        assert!(matches!(controller.turn_state, TurnState::Animating));
+        assert_eq!(controller.input_phase(), (false, false));
+        assert!(!controller.undo());
+
+        // the slide hasn't settled yet, so the board waits
+        assert!(!controller.finish_insert());
+        assert!(matches!(controller.turn_state, TurnState::Animating));
+        settle();
+        assert!(controller.finish_insert());
+        assert!(matches!(controller.turn_state, TurnState::MoveToken));
+        assert_eq!(controller.input_phase(), (false, true));
+    }
+
+    #[test]
+    fn full_turn_emits_events_in_order() {
+        let _lock = anim::tests::lock_state();
+        let mut controller = controller(&[1, 2]);
+        controller.board.loose_tile.whose_target = None;
+        controller.perform_insert(Direction::West, 1);
+        settle();
+        controller.finish_insert();
+        let pos = controller.board.player_pos(1);
+        assert!(controller.attempt_move(pos));
+        let expected = vec![
+            BoardEvent::TileInserted {
+                dir: Direction::West,
+                idx: 1,
+            },
+            BoardEvent::TokenMoved {
+                player: 1,
+                from: pos,
+                to: pos,
+            },
+            BoardEvent::TurnStarted(2),
+        ];
+        assert_eq!(controller.take_events(), expected);
+        assert!(controller.take_events().is_empty());
+    }
+
+    #[test]
+    fn claiming_last_point_as_treasure_wins() {
+        let mut controller = controller(&[1, 2]);
+        controller.settings.score_limit = 1;
+        controller.turn_state = TurnState::MoveToken;
+        let pos = controller.board.player_pos(1);
+        controller.board.treasures.insert(pos);
+        assert!(controller.attempt_move(pos));
+        let events = controller.take_events();
+        assert!(events.contains(&BoardEvent::GameWon(1)));
+        assert_eq!(controller.winner().map(|player| player.id), Some(1));
+        assert!(controller.board.treasures.is_empty());
+    }
+
+    #[test]
+    fn tick_past_deadline_passes_turn() {
+        let mut controller = controller(&[1, 2]);
+        controller.settings.turn_time_limit = Some(10.0);
+        assert!(controller.tick(100.0));
+        assert_eq!(controller.turn_deadline, Some(110.0));
+        assert!(!controller.tick(105.0));
+        assert_eq!(controller.active_player_id(), 1);
+        assert!(controller.tick(110.5));
+        assert_eq!(controller.active_player_id(), 2);
+        assert_eq!(controller.turn_deadline, None);
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+    }
+
+    #[test]
+    fn insert_preview_opens_new_paths() {
+        let mut controller = tiny_controller("─││ │││ │││");
+        controller.board.loose_tile = Tile::try_from('─').unwrap();
+        let before = controller.reachable_from((0, 0));
+        assert_eq!(before, [(0, 0)].iter().cloned().collect());
+
+        let after = controller.reachable_after_insert(Direction::North, 0, Direction::East, 1);
+        assert_eq!(after, [(0, 0), (0, 1)].iter().cloned().collect());
+        let upright = controller.reachable_after_insert(Direction::North, 0, Direction::North, 1);
+        assert_eq!(upright, before);
+        // the preview isn't the real thing
+        assert_eq!(controller.reachable_from((0, 0)), before);
+
+        // the tile that slides in has nowhere to be shaded before the shift
+        let guide = (Direction::North, 0);
+        assert_eq!(controller.board.unshifted_pos((0, 1), guide), None);
+        assert_eq!(controller.board.unshifted_pos((1, 1), guide), Some((0, 1)));
+        assert_eq!(controller.board.unshifted_pos((0, 0), guide), Some((0, 0)));
+    }
+
+    #[test]
+    fn legal_turns_cover_each_insert_and_distinct_rotation() {
+        let mut controller = tiny_controller("─││ │││ │││");
+        controller.board.loose_tile = Tile::try_from('│').unwrap();
+        // one guide on each side, and a straight tile only has two ways to face
+        let turns = controller.legal_turns(1);
+        assert_eq!(turns.len(), 4 * 2);
+        let opener = turns
             .iter()
-            .filter(|(_, token)| token.score >= self.settings.score_limit)
-            .nth(0)
-            .map(|(id, _)| &self.players[id])
+            .find(|turn| turn.insert == (Direction::North, 0) && turn.rotation == Direction::East)
+            .unwrap();
+        assert_eq!(opener.moves, vec![(0, 0), (0, 1)]);
+
+        controller.board.loose_tile = Tile::try_from('┬').unwrap();
+        assert_eq!(controller.legal_turns(1).len(), 4 * 4);
+
+        // undoing the last insert isn't a legal turn
+        controller.last_insert = Some((Direction::North, 0));
+        let turns = controller.legal_turns(1);
+        assert_eq!(turns.len(), 3 * 4);
+        assert!(turns.iter().all(|turn| turn.insert != (Direction::South, 0)));
+    }
+
+    #[test]
+    fn default_any_line_board_shifts_every_line() {
+        let settings = BoardSettings {
+            insert_rule: InsertRule::AnyLine,
+            ..BoardSettings::default()
+        };
+        let controller = BoardController::new(settings, vec![player(1)], 1);
+        assert_eq!(controller.board.insert_rule, InsertRule::AnyLine);
+        assert_eq!(controller.board.insert_positions().len(), 4 * 7);
+        assert!(!controller.board.is_line_blocked(Direction::North, 0));
+        assert!(!controller.board.is_line_blocked(Direction::West, 6));
+    }
+
+    #[test]
+    fn sandbox_moves_any_token() {
+        let mut controller = controller(&[1, 2]);
+        controller.board.cells = Board::parse_board(&["───────"; 7].join(" "));
+        let from = controller.board.player_pos(2);
+        let to = (from.0, from.1 - 1);
+        assert!(!controller.local_turn(2));
+
+        controller.set_sandbox(true);
+        assert!(controller.local_turn(2));
+        assert_eq!(controller.input_phase(), (true, true));
+        assert!(controller.pick_up_sandbox_token(from));
+        assert!(controller.attempt_move(to));
+        assert_eq!(controller.board.player_pos(2), to);
+        assert_eq!(controller.active_player_id(), 1);
+
+        controller.set_sandbox(false);
+        assert!(!controller.local_turn(2));
+        assert_eq!(controller.input_phase(), (true, false));
+    }
+
+    #[test]
+    fn reversing_guide_cant_be_inserted_at() {
+        let mut controller = controller(&[1, 2]);
+        assert!(controller.can_insert_at(Direction::South, 1));
+        controller.last_insert = Some((Direction::North, 1));
+        assert!(!controller.can_insert_at(Direction::South, 1));
+        assert!(controller.can_insert_at(Direction::North, 1));
+        assert!(!controller.perform_insert(Direction::South, 1));
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+    }
+
+    #[test]
+    fn removing_active_player_passes_the_turn() {
+        let mut controller = controller(&[1, 2, 3]);
+        assert!(controller.remove_player(1));
+        assert_eq!(controller.active_player_id(), 2);
+        assert_eq!(controller.turn_order, vec![2, 3]);
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+        // nothing of theirs is left to draw
+        assert!(!controller.board.player_tokens.contains_key(&1));
+        assert!(controller.board.iter_tiles().all(|(_, tile)| tile.whose_target != Some(1)));
+        let events = controller.take_events();
+        assert!(events.contains(&BoardEvent::PlayerLeft(1)));
+
+        // someone else leaving doesn't pass the turn
+        assert!(controller.remove_player(3));
+        assert_eq!(controller.active_player_id(), 2);
+        assert!(!controller.remove_player(2));
+    }
+
+    #[test]
+    fn full_round_advances_turn_number_by_player_count() {
+        let _lock = anim::tests::lock_state();
+        let mut controller = controller(&[1, 2, 3]);
+        assert_eq!(controller.turn_number(), 0);
+        for &id in &[1, 2, 3] {
+            assert_eq!(controller.active_player_id(), id);
+            assert!(controller.perform_insert(Direction::North, 0));
+            settle();
+            assert!(controller.finish_insert());
+            let pos = controller.board.player_pos(id);
+            assert!(controller.attempt_move(pos));
+        }
+        assert_eq!(controller.turn_number(), 3);
+        assert_eq!(controller.active_player_id(), 1);
+        assert!(!controller.is_finished());
+    }
+
+    #[test]
+    fn reset_starts_a_fresh_game() {
+        let mut controller = controller(&[1, 2]);
+        controller.board.cells = Board::parse_board(&["───────"; 7].join(" "));
+        controller.turn_state = TurnState::MoveToken;
+        assert!(controller.attempt_move((0, 1)));
+
+        controller.reset(9, 9, vec![player(4), player(5)]);
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+        assert_eq!((controller.board.width(), controller.board.height()), (9, 9));
+        let mut turn_order = controller.turn_order.clone();
+        turn_order.sort();
+        assert_eq!(turn_order, vec![4, 5]);
+        for token in controller.board.player_tokens.values() {
+            assert_eq!(token.position, token.home);
+            assert_eq!(token.score, 0);
+        }
+        assert_eq!(controller.board.player_pos(4), (0, 0));
+        assert_eq!(controller.board.player_pos(5), (8, 8));
+        assert!(!controller.undo());
+    }
+
+    #[test]
+    fn child_whose_turn_is_next_stands_in_for_parent() {
+        let mut controller = controller(&[1, 2, 3]);
+        assert!(controller.set_child(3, 2));
+        assert!(!controller.set_child(2, 1));
+        assert!(!controller.set_child(1, 1));
+        assert!(!controller.set_child(9, 1));
+        assert_eq!(controller.players[&2].parent, Some(3));
+
+        // player 1 is up, then the child, then the parent
+        assert_eq!(controller.effective_local_id(3), 2);
+        assert_eq!(controller.effective_local_id(1), 1);
+        assert!(controller.local_turn(1) && !controller.local_turn(3));
+        controller.advance_turn();
+        assert_eq!(controller.effective_local_id(3), 2);
+        assert!(controller.local_turn(3));
+        controller.advance_turn();
+        assert_eq!(controller.effective_local_id(3), 3);
+    }
+
+    #[test]
+    fn step_ai_takes_the_computer_players_turn() {
+        let _lock = anim::tests::lock_state();
+        let mut controller = controller(&[1, 2]);
+        assert_eq!(controller.step_ai(), None);
+        assert!(controller.set_ai_control(1, AiDifficulty::Easy));
+        let event = controller.step_ai();
+        assert!(matches!(event, Some(BoardEvent::TileInserted { .. })));
+        // the insert slides in like anyone else's
+        assert!(matches!(controller.turn_state, TurnState::Animating));
+        assert_eq!(controller.step_ai(), None);
+        settle();
+        let event = controller.step_ai();
+        assert!(matches!(event, Some(BoardEvent::TokenMoved { player: 1, .. })));
+        assert!(controller.last_insert.is_some());
+        assert_eq!(controller.active_player_id(), 2);
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+        // the human's turn is left to them
+        assert_eq!(controller.step_ai(), None);
+        assert!(controller.clear_ai_control(1));
+        assert!(!controller.clear_ai_control(1));
+
+        // and the computer's turn can be taken back
+        assert!(controller.undo());
+        assert_eq!(controller.active_player_id(), 1);
+        assert!(controller.undo());
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+        assert_eq!(controller.last_insert, None);
+    }
+
+    #[test]
+    fn silent_players_are_taken_over_until_heard_from() {
+        let mut controller = controller(&[1, 2]);
+        let timeout = BoardController::DISCONNECT_TIMEOUT;
+        assert!(!controller.take_over_disconnected(0.0));
+        assert!(!controller.take_over_disconnected(timeout / 2.0));
+        assert!(controller.take_over_disconnected(timeout + 1.0));
+        assert_eq!(controller.ai_difficulty(2), Some(AiDifficulty::Medium));
+        // the host is never taken over
+        assert_eq!(controller.ai_difficulty(1), None);
+
+        assert!(controller.note_heard_from(2, timeout + 2.0));
+        assert_eq!(controller.ai_difficulty(2), None);
+        assert!(!controller.take_over_disconnected(timeout + 3.0));
+    }
+
+    #[test]
+    fn taken_over_player_is_given_back_after_a_guest_state_arrives() {
+        let mut controller = controller(&[1, 2, 3]);
+        let timeout = BoardController::DISCONNECT_TIMEOUT;
+        controller.take_over_disconnected(0.0);
+        controller.note_heard_from(3, timeout);
+        assert!(controller.take_over_disconnected(timeout + 1.0));
+        assert_eq!(controller.ai_difficulty(2), Some(AiDifficulty::Medium));
+
+        // a guest's copy of the state knows who the computer plays for, but not why
+        let json = serde_json::to_string(&controller).unwrap();
+        let mut replaced: BoardController = serde_json::from_str(&json).unwrap();
+        replaced.carry_host_state(&controller, timeout + 2.0);
+        assert!(replaced.note_heard_from(2, timeout + 2.0));
+        assert_eq!(replaced.ai_difficulty(2), None);
+        // and the silence timers keep running rather than starting over
+        assert!(replaced.take_over_disconnected(2.0 * timeout + 0.5));
+        assert_eq!(replaced.ai_difficulty(3), Some(AiDifficulty::Medium));
+    }
+
+    #[test]
+    fn last_insert_follows_each_insert() {
+        let _lock = anim::tests::lock_state();
+        let mut controller = controller(&[1, 2]);
+        assert_eq!(controller.last_insert, None);
+        for &(id, guide) in &[(1, (Direction::West, 0)), (2, (Direction::South, 2))] {
+            assert!(controller.perform_insert(guide.0, guide.1));
+            settle();
+            assert!(controller.finish_insert());
+            assert_eq!(controller.last_insert, Some(guide));
+            // the marker starts over for every insert, then fades
+            assert_eq!(anim::STATE.read().unwrap().last_insert.alpha(), 1.0);
+            settle();
+            assert!(anim::STATE.read().unwrap().last_insert.alpha() < 1.0);
+            let pos = controller.board.player_pos(id);
+            assert!(controller.attempt_move(pos));
+        }
+    }
+
+    #[test]
+    fn earliest_finisher_wins() {
+        let mut controller = controller(&[1, 2, 3]);
+        let limit = controller.settings.score_limit;
+        let mut finish = |id, turn| {
+            let token = controller.board.player_tokens.get_mut(&id).unwrap();
+            token.score = limit;
+            token.finished_turn = Some(turn);
+        };
+        finish(1, 7);
+        finish(3, 4);
+        assert_eq!(controller.winner().map(|player| player.id), Some(3));
+        assert!(controller.is_finished());
+
+        // finishing on the same turn goes to whoever sat first
+        let token = controller.board.player_tokens.get_mut(&1).unwrap();
+        token.finished_turn = Some(4);
+        assert_eq!(controller.winner().map(|player| player.id), Some(1));
+    }
+
+    #[test]
+    fn reachable_from_matches_the_board() {
+        let mut controller = controller(&[1, 2]);
+        for pos in &[(0, 0), (3, 4), (6, 6)] {
+            assert_eq!(controller.reachable_from(*pos), controller.board.reachable_coords(*pos));
+        }
+        // with a move limit, it's where a token could actually go
+        controller.board.max_move_tiles = Some(1);
+        assert_eq!(controller.reachable_from((3, 3)), controller.board.reachable_within((3, 3), 1));
+    }
+
+    #[test]
+    fn trapped_player_is_warned_of_no_progress() {
+        let mut controller = tiny_controller("─││ │││ │││");
+        controller.board.loose_tile = Tile::try_from('─').unwrap();
+        // the west column never slides, and nothing can turn south off the top row
+        controller.board.cells[2][0].whose_target = Some(1);
+        assert!(!controller.can_player_progress(1));
+        controller.start_turn();
+        assert_eq!(controller.take_events(), vec![BoardEvent::TurnStarted(1)]);
+
+        controller.enable_progress_warnings = true;
+        controller.start_turn();
+        let events = controller.take_events();
+        assert_eq!(events, vec![BoardEvent::TurnStarted(1), BoardEvent::NoProgress(1)]);
+
+        controller.board.cells[2][0].whose_target = None;
+        controller.board.cells[0][0].whose_target = Some(1);
+        assert!(controller.can_player_progress(1));
+        controller.start_turn();
+        assert_eq!(controller.take_events(), vec![BoardEvent::TurnStarted(1)]);
+    }
+
+    #[test]
+    fn simulated_game_ends_within_the_turn_limit() {
+        let settings = BoardSettings::default();
+        for seed in 0..4 {
+            let players = vec![player(1), player(2), player(3)];
+            let strategy = AiDifficulty::Medium;
+            let result = BoardController::simulate_game(&settings, players, seed, 60, strategy);
+            assert!(result.turns <= 60);
+            assert_eq!(result.scores.keys().cloned().collect::<Vec<_>>(), vec![1, 2, 3]);
+            match result.winner {
+                Some(id) => assert_eq!(result.scores[&id], settings.score_limit),
+                // a draw means the limit ran out with nobody there yet
+                None => {
+                    assert_eq!(result.turns, 60);
+                    assert!(result.scores.values().all(|&score| score < settings.score_limit));
+                }
+            }
+        }
+        let players = vec![player(1), player(2)];
+        let result = BoardController::simulate_game(&settings, players, 0, 0, AiDifficulty::Easy);
+        assert_eq!((result.winner, result.turns), (None, 0));
+    }
+
+    #[test]
+    fn one_way_board_rejects_inserts_from_other_sides() {
+        let mut controller = controller(&[1, 2]);
+        controller.board.allowed_insert_directions =
+            [Direction::North, Direction::South].iter().cloned().collect();
+        for idx in 0..3 {
+            assert!(controller.can_insert_at(Direction::North, idx));
+            assert!(!controller.can_insert_at(Direction::East, idx));
+            assert!(!controller.can_insert_at(Direction::West, idx));
+        }
+        assert!(!controller.perform_insert(Direction::East, 1));
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+        assert!(controller.legal_turns(1).iter().all(|turn| turn.insert.0 != Direction::East));
+    }
+
+    #[test]
+    fn move_limit_rejects_reachable_tiles_too_far_away() {
+        let mut controller = tiny_controller("──── ──── ────");
+        controller.board.max_move_tiles = Some(2);
+        controller.turn_state = TurnState::MoveToken;
+        // the whole top row is reachable, but its far end is three steps along
+        assert!(controller.board.reachable_coords((0, 0)).contains(&(0, 3)));
+        assert!(!controller.attempt_move((0, 3)));
+        assert_eq!(controller.board.player_pos(1), (0, 0));
+        assert!(controller.attempt_move((0, 2)));
+        assert_eq!(controller.board.player_pos(1), (0, 2));
+
+        let decoded = Board::decode(&controller.board.encode()).unwrap();
+        assert_eq!(decoded.max_move_tiles, Some(2));
+        controller.board.max_move_tiles = None;
+        let decoded = Board::decode(&controller.board.encode()).unwrap();
+        assert_eq!(decoded.max_move_tiles, None);
+    }
+
+    #[test]
+    fn advancing_skips_finished_players() {
+        let mut controller = controller(&[1, 2, 3]);
+        let limit = controller.settings.score_limit;
+        controller.board.player_tokens.get_mut(&2).unwrap().score = limit;
+        controller.turn_state = TurnState::MoveToken;
+        assert_eq!(controller.active_index(), 0);
+        controller.advance_turn();
+        assert_eq!(controller.active_player_id(), 3);
+        assert_eq!(controller.active_index(), 2);
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+        // wrapping around still passes them by
+        controller.advance_turn();
+        assert_eq!(controller.active_player_id(), 1);
+        assert_eq!(controller.active_index(), 0);
+    }
+
+    #[test]
+    fn undo_forgets_past_its_depth_and_redo_past_a_new_move() {
+        let mut controller = tiny_controller("──────");
+        controller.set_sandbox(true);
+        controller.undo_depth = 2;
+        for col in 1..4 {
+            assert!(controller.attempt_move((0, col)));
+        }
+        assert!(controller.undo());
+        assert!(controller.undo());
+        assert_eq!(controller.board.player_pos(1), (0, 1));
+        // the first move was pushed out by the later two
+        assert!(!controller.undo());
+
+        assert!(controller.redo());
+        assert_eq!(controller.board.player_pos(1), (0, 2));
+        assert!(controller.attempt_move((0, 5)));
+        assert!(!controller.redo());
+        assert!(controller.undo());
+        assert_eq!(controller.board.player_pos(1), (0, 2));
+    }
+
+    #[test]
+    fn stalling_player_is_eliminated_at_the_threshold() {
+        let mut controller = controller(&[1, 2]);
+        controller.elimination_enabled = true;
+        controller.elimination_threshold = 2;
+        controller.start_turn();
+        controller.take_events();
+        // passing every turn never gets anyone closer
+        controller.advance_turn();
+        controller.advance_turn();
+        assert!(!controller.take_events().contains(&BoardEvent::PlayerEliminated(1)));
+        assert_eq!(controller.turn_order, vec![1, 2]);
+
+        controller.advance_turn();
+        assert!(controller.take_events().contains(&BoardEvent::PlayerEliminated(1)));
+        assert_eq!(controller.turn_order, vec![2]);
+        assert!(!controller.board.player_tokens.contains_key(&1));
+        assert_eq!(controller.active_player_id(), 2);
+
+        // the last player standing stays in, however long they stall
+        controller.advance_turn();
+        controller.advance_turn();
+        assert_eq!(controller.turn_order, vec![2]);
+        let events = controller.take_events();
+        assert!(!events.iter().any(|event| matches!(event, BoardEvent::PlayerEliminated(_))));
+    }
+
+    #[test]
+    fn each_timed_turn_records_its_duration() {
+        let mut controller = controller(&[1, 2]);
+        controller.settings.turn_time_limit = None;
+        controller.tick(10.0);
+        controller.tick(14.0);
+        controller.advance_turn();
+        // the next turn's timer only starts on the next tick
+        controller.tick(20.0);
+        controller.tick(27.5);
+        controller.advance_turn();
+        assert_eq!(controller.turn_durations, vec![(1, 4.0), (2, 7.5)]);
+
+        controller.tick(30.0);
+        controller.tick(32.0);
+        controller.advance_turn();
+        assert_eq!(controller.average_turn_time(1), Some(3.0));
+        assert_eq!(controller.average_turn_time(2), Some(7.5));
+        assert_eq!(controller.average_turn_time(3), None);
+    }
+
+    #[test]
+    fn host_times_turns_guests_end() {
+        let _lock = anim::tests::lock_state();
+        let mut host = controller(&[1, 2]);
+        host.settings.turn_time_limit = None;
+        let from_guest = |host: &BoardController, play: &dyn Fn(&mut BoardController)| {
+            let json = serde_json::to_string(host).unwrap();
+            let mut guest: BoardController = serde_json::from_str(&json).unwrap();
+            play(&mut guest);
+            let json = serde_json::to_string(&guest).unwrap();
+            serde_json::from_str::<BoardController>(&json).unwrap()
+        };
+        host.tick(10.0);
+        host.tick(14.0);
+        host.advance_turn();
+        host.tick(20.0);
+
+        // the guest's insert doesn't restart the clock on their turn
+        let mut replaced = from_guest(&host, &|guest| {
+            assert!(guest.perform_insert(Direction::North, 0));
+        });
+        replaced.carry_host_state(&host, 22.0);
+        host = replaced;
+        host.tick(25.0);
+        let mut replaced = from_guest(&host, &|guest| guest.advance_turn());
+        replaced.carry_host_state(&host, 27.0);
+        host = replaced;
+        assert_eq!(host.turn_durations, vec![(1, 4.0), (2, 7.0)]);
+
+        // the next turn is timed from the host's next tick
+        host.tick(30.0);
+        host.tick(31.0);
+        host.advance_turn();
+        assert_eq!(host.turn_durations, vec![(1, 4.0), (2, 7.0), (1, 1.0)]);
     }
 }
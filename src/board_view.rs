@@ -1,172 +1,172 @@
 //! Board view
 
-use std::cmp;
-use std::ops;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashSet};
+use std::f64::consts::{FRAC_PI_4, PI};
 
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 use web_sys::CanvasRenderingContext2d as Context;
 
 use crate::{
     BoardController,
-    colors::{self, Color}, Direction, PlayerID, Tile,
+    colors::{self, Color, PaletteKind}, Direction, PlayerID, Shape, Tile,
 };
-use crate::anim;
+use crate::anim::{self, AnimGlobalState};
 use crate::board_controller::TurnState;
-
-#[derive(Clone, Debug)]
-struct Diagonal {
-    ll: [f64; 2],
-    ur: [f64; 2],
+use crate::geometry::Extents;
+use crate::tile_renderer::{DefaultTileRenderer, TileRenderer};
+
+/// Fill pattern for tiles that are not highlighted as reachable
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum BackgroundStyle {
+    /// Background color everywhere
+    Flat,
+    /// Alternates between two colors, starting from the northwest corner
+    Checkerboard(Color, Color),
 }
 
-impl ops::Add<f64> for Diagonal {
-    type Output = Diagonal;
+impl BackgroundStyle {
+    /// Gets the fills the pattern uses, given the flat background color
+    fn fills(self, background: Color) -> Vec<Color> {
+        match self {
+            BackgroundStyle::Flat => vec![background],
+            BackgroundStyle::Checkerboard(even, odd) => vec![even, odd],
+        }
+    }
 
-    fn add(self, rhs: f64) -> Self::Output {
-        Diagonal {
-            ll: [self.ll[0] + rhs, self.ll[1] + rhs],
-            ur: [self.ur[0] + rhs, self.ur[1] + rhs],
+    /// Gets which of `fills` the tile at the given (row, col) gets
+    fn fill_index(self, (row, col): (usize, usize)) -> usize {
+        match self {
+            BackgroundStyle::Flat => 0,
+            BackgroundStyle::Checkerboard(_, _) => (row + col) % 2,
         }
     }
 }
 
-impl ops::Sub<f64> for Diagonal {
-    type Output = Diagonal;
+/// How tiles the local player can reach are marked
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReachableStyle {
+    /// Each reachable tile gets the reachable background color
+    Fill,
+    /// One outline runs around the edge of the whole reachable region
+    Outline,
+}
 
-    fn sub(self, rhs: f64) -> Self::Output {
-        self + (-rhs)
+/// Finds every side of a reachable tile that doesn't lead to another reachable tile
+fn reachable_boundary(reachable: &HashSet<(usize, usize)>) -> Vec<((usize, usize), Direction)> {
+    let mut result = vec![];
+    for &(row, col) in reachable {
+        for &dir in Direction::all() {
+            let (d_row, d_col) = dir.offset();
+            let next_row = row as isize + d_row;
+            let next_col = col as isize + d_col;
+            let inside = next_row >= 0
+                && next_col >= 0
+                && reachable.contains(&(next_row as usize, next_col as usize));
+            if !inside {
+                result.push(((row, col), dir));
+            }
+        }
     }
+    result
 }
 
-#[derive(Clone, Debug)]
-struct Extents {
-    north: f64,
-    south: f64,
-    east: f64,
-    west: f64,
+/// Gets the keyboard shortcuts that do something in the given turn phase, if any
+fn shortcut_hints(turn_state: &TurnState) -> Option<&'static str> {
+    match turn_state {
+        TurnState::InsertTile => Some("Arrows/WASD: choose guide, Shift: rotate, Space: insert"),
+        TurnState::Animating => None,
+        TurnState::MoveToken => Some("Arrows/WASD: choose tile, Space: move there"),
+    }
 }
 
-impl Extents {
-    #[allow(dead_code)]
-    fn center(&self) -> [f64; 2] {
-        [
-            (self.west + self.east) / 2.0,
-            (self.north + self.south) / 2.0,
-        ]
-    }
-
-    fn diagonal(&self) -> Diagonal {
-        Diagonal {
-            ll: [self.west, self.south],
-            ur: [self.east, self.north],
-        }
-    }
-
-    fn clamp_diagonal(&self, line: Diagonal) -> Diagonal {
-        // find equation of line as x + y = k (works for either point since slope assumed to be 1)
-        let ll = line.ll;
-        let k = ll[0] + ll[1];
-        // if k < west + north then too small so use northwest corner
-        let (ll, ur) = if k < self.west + self.north {
-            let point = [self.west, self.north];
-            (point, point)
-        } else if k > self.east + self.south {
-            // if k > east + south then too big so use southwest corner
-            let point = [self.east, self.south];
-            (point, point)
-        } else if k < self.north + self.east {
-            // if less than halfway, before main diagonal, so trust north and west already
-            let y_at_west = k - self.west;
-            let x_at_north = k - self.north;
-            ([self.west, y_at_west], [x_at_north, self.north])
+/// Splits text into lines no wider than the given width by the given measure, breaking between
+/// words (a single word too wide for any line gets a line to itself)
+fn wrap_words<F: Fn(&str) -> f64>(text: &str, max_width: f64, measure: F) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if line.is_empty() {
+            line.push_str(word);
+            continue;
+        }
+        let candidate = format!("{} {}", line, word);
+        if measure(&candidate) <= max_width {
+            line = candidate;
         } else {
-            // if more than halfway, after main diagonal, so trust south and east already
-            let y_at_east = k - self.east;
-            let x_at_south = k - self.south;
-            ([x_at_south, self.south], [self.east, y_at_east])
-        };
-        Diagonal { ll, ur }
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        }
     }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
 }
 
-impl ops::Sub<f64> for Extents {
-    type Output = Extents;
-
-    #[allow(clippy::suspicious_arithmetic_impl)]
-    fn sub(self, rhs: f64) -> Extents {
-        Extents {
-            north: self.north + rhs,
-            south: self.south - rhs,
-            east: self.east - rhs,
-            west: self.west + rhs,
-        }
-    }
+/// Moves a coordinate in CSS pixels to the middle of the device pixel it falls in, so thin lines
+/// stroked there cover whole pixels instead of blurring across two
+fn snap_to_half_pixel(coord: f64, pixel_ratio: f64) -> f64 {
+    ((coord * pixel_ratio).floor() + 0.5) / pixel_ratio
 }
 
-impl ops::Sub<[f64; 2]> for Extents {
-    type Output = Extents;
+/// Tile wall widths on each side, as percentage of tile size
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct WallWidth {
+    /// North wall width
+    pub north: f64,
+    /// South wall width
+    pub south: f64,
+    /// East wall width
+    pub east: f64,
+    /// West wall width
+    pub west: f64,
+}
 
-    fn sub(self, rhs: [f64; 2]) -> Self::Output {
-        let [x, y] = rhs;
-        Extents {
-            north: self.north - y,
-            south: self.south - y,
-            east: self.east - x,
-            west: self.west - x,
+impl WallWidth {
+    /// Gets the width of the wall on the given side
+    pub fn get(&self, dir: Direction) -> f64 {
+        match dir {
+            Direction::North => self.north,
+            Direction::South => self.south,
+            Direction::East => self.east,
+            Direction::West => self.west,
         }
     }
-}
 
-impl PartialEq<Extents> for [f64; 2] {
-    fn eq(&self, other: &Extents) -> bool {
-        self.partial_cmp(other) == Some(cmp::Ordering::Equal)
+    /// Gets the widest wall width, for anything that has to clear every wall
+    pub fn max(&self) -> f64 {
+        self.north.max(self.south).max(self.east).max(self.west)
     }
 }
 
-impl PartialOrd<Extents> for [f64; 2] {
-    fn partial_cmp(&self, other: &Extents) -> Option<cmp::Ordering> {
-        use std::cmp::Ordering::*;
-        let [x, y] = self;
-        let result = match (
-            x.partial_cmp(&other.west),
-            x.partial_cmp(&other.east),
-            y.partial_cmp(&other.north),
-            y.partial_cmp(&other.south),
-        ) {
-            // too far west
-            (Some(Less), _, _, _) => Greater,
-            // too far east
-            (_, Some(Greater), _, _) => Greater,
-            // too far north
-            (_, _, Some(Less), _) => Greater,
-            // too far south
-            (_, _, _, Some(Greater)) => Greater,
-            // entirely within
-            (Some(Greater), Some(Less), Some(Greater), Some(Less)) => Less,
-            // on west edge
-            (Some(Equal), _, _, _) => Equal,
-            // on east edge
-            (_, Some(Equal), _, _) => Equal,
-            // on north edge
-            (_, _, Some(Equal), _) => Equal,
-            // on south edge
-            (_, _, _, Some(Equal)) => Equal,
-            // this really shouldn't be possible, and the rust compiler warns about an unreachable pattern!
-            // thanks, rust!
-            // (Some(_), Some(_), Some(_), Some(_)) => panic!("Implausible bounds check for point in extents"),
-            // something is NaN or otherwise fucky
-            _ => return None,
-        };
-        Some(result)
+impl From<f64> for WallWidth {
+    fn from(width: f64) -> Self {
+        WallWidth {
+            north: width,
+            south: width,
+            east: width,
+            west: width,
+        }
     }
 }
 
 /// Stores board view settings
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BoardViewSettings {
     /// Background color
     pub background_color: Color,
+    /// Background fill pattern
+    pub background_style: BackgroundStyle,
     /// Reachable background color
     pub reachable_background_color: Color,
+    /// Whether reachable tiles are filled in or outlined as a region
+    pub reachable_style: ReachableStyle,
+    /// Color of the outline around the reachable region
+    pub reachable_outline_color: Color,
+    /// Opacity of the reachable background while tiles are still being inserted
+    pub reachable_dim_alpha: f64,
     /// Border color
     pub border_color: Color,
     /// Edge color around the whole board
@@ -181,38 +181,159 @@ pub struct BoardViewSettings {
     pub text_color: Color,
     /// Wall color
     pub wall_color: Color,
-    /// Tile wall width as percentage of tile size
-    pub wall_width: f64,
+    /// Tile wall widths as percentage of tile size
+    pub wall_width: WallWidth,
     /// Insert guide color
     pub insert_guide_color: Color,
+    /// Color of the highlight over the row or column the hovered insert guide would shift
+    pub insert_preview_color: Color,
+    /// Whether to draw a faint copy of the tile the hovered insert guide would push off the board
+    pub show_ejected_ghost: bool,
+    /// Color of the cross over the tile that would be pushed off the board
+    pub ejected_mark_color: Color,
+    /// Extra margin around each insert guide that still counts as hitting it, not drawn
+    pub insert_guide_hit_padding: f64,
+    /// Treasure color
+    pub treasure_color: Color,
     /// UI margin size, south pane
     pub ui_margin_south: f64,
     /// UI margin size, east pane
     pub ui_margin_east: f64,
     /// Font size
     pub font_size: u32,
+    /// Whether to show how many tiles the local player can reach
+    pub show_reachable_count: bool,
+    /// Whether the loose tile bobs while the local player is choosing where to insert it
+    pub loose_bob: bool,
+    /// Distance the loose tile bobs each way
+    pub loose_bob_amplitude: f64,
+    /// Seconds for the loose tile to bob down and back up
+    pub loose_bob_period: f64,
+    /// Whether to briefly draw a trail from where each token moved from
+    pub move_trails: bool,
+    /// Whether the local player's target stripes flow toward their token
+    pub target_flow: bool,
+    /// Seconds for the board to fade in when a game starts, or `None` to show it instantly
+    pub intro_duration: Option<f64>,
+    /// Ratio of canvas pixels to CSS pixels, all other sizes are in CSS pixels
+    pub device_pixel_ratio: f64,
+    /// Radius of the rounded corners of each tile, or zero for square tiles
+    pub tile_corner_radius: f64,
+    /// Smallest tile size worth drawing, below which the board is replaced by a warning
+    pub min_cell_size: f64,
+    /// How much shifting tiles squash and stretch along the way, or zero for none
+    pub insert_squash: f64,
+    /// Whether cell and board edges snap to the pixel grid so they stay sharp
+    pub crisp_lines: bool,
+    /// Radius of a token as a fraction of tile size, at most half
+    pub token_radius_ratio: f64,
+    /// Whether to briefly mark the guide the last insert came from
+    pub show_last_insert: bool,
+    /// Color of the last insert marker
+    pub last_insert_color: Color,
+    /// Whether walls get lit top-left edges and shaded bottom-right edges, so they look raised
+    pub beveled_walls: bool,
+    /// Color of the lit wall edges, or `None` to lighten the wall color
+    pub wall_highlight_color: Option<Color>,
+    /// Color of the shaded wall edges, or `None` to darken the wall color
+    pub wall_shadow_color: Option<Color>,
+    /// Most reachable tiles to shade one by one, beyond which the whole board is shaded at once
+    pub max_highlighted_tiles: Option<usize>,
+    /// Whether tile corners with no wall on either side get a wall block, rather than leaving the
+    /// passages that meet there open (corners where walls meet are covered by the walls either way)
+    pub always_draw_corners: bool,
+    /// Whether to list the keyboard shortcuts for the current turn phase
+    pub show_shortcuts: bool,
+    /// Whether to show a legend explaining guides, targets, and tokens
+    pub show_legend: bool,
+    /// Whether to draw a turning dashed ring around the token about to move on the local turn
+    pub show_focus_ring: bool,
+    /// Size of the loose tile while it rests in the east panel, as a multiple of tile size
+    pub loose_tile_scale: f64,
 }
 
 impl BoardViewSettings {
     /// Creates new board view settings
     pub fn new() -> BoardViewSettings {
+        BoardViewSettings::with_palette(PaletteKind::Default)
+    }
+
+    /// Creates new board view settings drawn in the colors of the given palette
+    pub fn with_palette(kind: PaletteKind) -> BoardViewSettings {
+        let palette = colors::palette(kind);
         BoardViewSettings {
-            background_color: colors::TEAL,
-            reachable_background_color: colors::LIGHT,
-            border_color: colors::DARK,
-            board_edge_color: colors::DARK,
-            cell_edge_color: colors::DARK,
+            background_color: palette.background,
+            background_style: BackgroundStyle::Flat,
+            reachable_background_color: palette.light,
+            reachable_style: ReachableStyle::Fill,
+            reachable_outline_color: palette.light,
+            reachable_dim_alpha: 0.35,
+            border_color: palette.dark,
+            board_edge_color: palette.dark,
+            cell_edge_color: palette.dark,
             board_edge_radius: 3.0,
             cell_edge_radius: 1.0,
-            text_color: colors::DARK,
-            wall_color: colors::BLUE,
-            wall_width: 0.3,
-            insert_guide_color: colors::PURPLE,
+            text_color: palette.dark,
+            wall_color: palette.wall,
+            wall_width: 0.3.into(),
+            insert_guide_color: palette.accent,
+            insert_preview_color: palette.accent,
+            show_ejected_ghost: true,
+            ejected_mark_color: palette.treasure,
+            insert_guide_hit_padding: 8.0,
+            treasure_color: palette.treasure,
             ui_margin_south: 100.0,
             ui_margin_east: 300.0,
             font_size: 25,
+            show_reachable_count: false,
+            loose_bob: true,
+            loose_bob_amplitude: 3.0,
+            loose_bob_period: 2.0,
+            move_trails: true,
+            target_flow: true,
+            intro_duration: Some(1.5),
+            device_pixel_ratio: 1.0,
+            tile_corner_radius: 0.0,
+            min_cell_size: 8.0,
+            insert_squash: 0.08,
+            crisp_lines: false,
+            token_radius_ratio: 0.2,
+            show_last_insert: true,
+            last_insert_color: palette.dark,
+            beveled_walls: false,
+            wall_highlight_color: None,
+            wall_shadow_color: None,
+            max_highlighted_tiles: None,
+            always_draw_corners: true,
+            show_shortcuts: false,
+            show_legend: false,
+            show_focus_ring: true,
+            loose_tile_scale: 1.0,
         }
     }
+
+    /// Gets the colors of the lit and shaded edges of beveled walls
+    pub fn wall_bevel_colors(&self) -> (Color, Color) {
+        let highlight = match self.wall_highlight_color {
+            Some(color) => color,
+            None => colors::lighten(self.wall_color, 0.35),
+        };
+        let shadow = match self.wall_shadow_color {
+            Some(color) => color,
+            None => colors::darken(self.wall_color, 0.35),
+        };
+        (highlight, shadow)
+    }
+
+    /// Saves these settings as JSON, for keeping a custom theme
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize view settings")
+    }
+
+    /// Loads settings saved by `to_json`, using defaults for anything missing
+    pub fn from_json(json: &str) -> Result<BoardViewSettings, serde_json::Error> {
+        serde_json::from_str(json)
+    }
 }
 
 impl Default for BoardViewSettings {
@@ -221,75 +342,203 @@ impl Default for BoardViewSettings {
     }
 }
 
+/// Converts a canvas size in device pixels to CSS pixels
+fn css_size((width, height): (u32, u32), pixel_ratio: f64) -> (f64, f64) {
+    (f64::from(width) / pixel_ratio, f64::from(height) / pixel_ratio)
+}
+
+/// Gets the `[dx, dy]` canvas direction target stripes flow in to lead from a target to a token
+fn flow_towards((row, col): (usize, usize), (token_row, token_col): (usize, usize)) -> [f64; 2] {
+    [
+        token_col as f64 - col as f64,
+        token_row as f64 - row as f64,
+    ]
+}
+
+/// Checks if either coordinate of a position is NaN, which comparing against extents mishandles
+fn is_nan(&[x, y]: &[f64; 2]) -> bool {
+    x.is_nan() || y.is_nan()
+}
+
+/// Gets a canvas position relative to the corner of an `[x, y, w, h]` rectangle
+fn rect_relative(&[x, y]: &[f64; 2], rect: [f64; 4]) -> [f64; 2] {
+    [x - rect[0], y - rect[1]]
+}
+
+/// Gets where the `index`th of `count` tokens on one tile goes, relative to the full token radius
+///
+/// Returns an offset from the tile center and a scale. Several tokens sit in a ring, each shrunk
+/// just enough not to overlap its neighbors or leave the full-size token's circle.
+fn token_layout(index: usize, count: usize) -> ([f64; 2], f64) {
+    if count < 2 {
+        return ([0.0, 0.0], 1.0);
+    }
+    let spread = (PI / count as f64).sin();
+    let scale = spread / (1.0 + spread);
+    let angle = 2.0 * PI * index as f64 / count as f64 - PI / 2.0;
+    let distance = 1.0 - scale;
+    ([distance * angle.cos(), distance * angle.sin()], scale)
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 enum DrawMode {
     All,
     OnlySelf,
 }
 
+/// Where everything on the canvas is, in CSS pixels, for lining overlays up with the board
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoardLayout {
+    /// Width and height of one tile
+    pub cell_size: f64,
+    /// Northwest corner of the board's tiles, as [x, y]
+    pub board_origin: [f64; 2],
+    /// Width and height of the board's tiles together, as [w, h]
+    pub board_size: [f64; 2],
+    /// South UI panel, as [x, y, w, h]
+    pub south_panel: [f64; 4],
+    /// East UI panel, as [x, y, w, h]
+    pub east_panel: [f64; 4],
+}
+
 /// Stores visual information about a board
 pub struct BoardView {
     /// Stores board view settings
     pub settings: BoardViewSettings,
+    /// Whether this view is for a spectator, who has no token and sees every target labeled
+    pub spectator: bool,
+    /// Whether this view is for broadcasting, so it hides which tiles are reachable and which
+    /// target is the local player's
+    pub clean_feed: bool,
+    /// Draws the walls of each tile
+    pub tile_renderer: Box<dyn TileRenderer>,
+    /// Part of the canvas being drawn into or hit tested, as [x, y, w, h] in CSS pixels, if not
+    /// the whole canvas
+    rect: Cell<Option<[f64; 4]>>,
 }
 
 impl BoardView {
+    /// Radius of the dot marking the local player's token, as a fraction of the token's radius
+    const TOKEN_DOT_RATIO: f64 = 0.25;
+
+    /// Smallest the resting loose tile gets on large boards, if the east panel has room
+    const MIN_LOOSE_TILE_SIZE: f64 = 48.0;
+
     /// Creates a new board view
     pub fn new(settings: BoardViewSettings) -> BoardView {
-        BoardView { settings }
+        BoardView {
+            settings,
+            spectator: false,
+            clean_feed: false,
+            tile_renderer: Box::new(DefaultTileRenderer),
+            rect: Cell::new(None),
+        }
+    }
+
+    /// Creates a new board view for a spectator
+    pub fn new_spectator(settings: BoardViewSettings) -> BoardView {
+        BoardView {
+            settings,
+            spectator: true,
+            clean_feed: false,
+            tile_renderer: Box::new(DefaultTileRenderer),
+            rect: Cell::new(None),
+        }
+    }
+
+    /// Creates a new board view for broadcasting the local player's game without giving hints away
+    pub fn new_clean_feed(settings: BoardViewSettings) -> BoardView {
+        BoardView {
+            settings,
+            spectator: false,
+            clean_feed: true,
+            tile_renderer: Box::new(DefaultTileRenderer),
+            rect: Cell::new(None),
+        }
+    }
+
+    /// Gets the width and height of the canvas (or the part being drawn into) in CSS pixels
+    fn canvas_size(&self, ctx: &Context) -> (f64, f64) {
+        if let Some([_, _, w, h]) = self.rect.get() {
+            return (w, h);
+        }
+        let canvas = ctx.canvas().unwrap_throw();
+        css_size((canvas.width(), canvas.height()), self.settings.device_pixel_ratio)
     }
 
     /// Gets the size of an individual tile and the x and y padding values
     fn tile_padding(&self, controller: &BoardController, ctx: &Context) -> (f64, f64, f64) {
         let settings = &self.settings;
-        let canvas = ctx.canvas().unwrap_throw();
-        let cell_max_height = (canvas.height() as f64 - settings.ui_margin_south)
+        let (canvas_width, canvas_height) = self.canvas_size(ctx);
+        let cell_max_height = (canvas_height - settings.ui_margin_south)
             / (controller.board.height() as f64 + 2.0);
-        let cell_max_width = (canvas.width() as f64 - settings.ui_margin_east)
+        let cell_max_width = (canvas_width - settings.ui_margin_east)
             / (controller.board.width() as f64 + 2.0);
         if cell_max_height < cell_max_width {
             let space_used_x =
                 cell_max_height * (controller.board.width() as f64 + 2.0) + settings.ui_margin_east;
-            (
-                cell_max_height,
-                (canvas.width() as f64 - space_used_x) / 2.0,
-                0.0,
-            )
+            (cell_max_height, (canvas_width - space_used_x) / 2.0, 0.0)
         } else {
             let space_used_y = cell_max_width * (controller.board.height() as f64 + 2.0)
                 + settings.ui_margin_south;
-            (
-                cell_max_width,
-                0.0,
-                (canvas.height() as f64 - space_used_y) / 2.0,
-            )
+            (cell_max_width, 0.0, (canvas_height - space_used_y) / 2.0)
+        }
+    }
+
+    /// Snaps a line coordinate to the pixel grid, if crisp lines are on
+    fn crisp(&self, coord: f64) -> f64 {
+        if self.settings.crisp_lines {
+            snap_to_half_pixel(coord, self.settings.device_pixel_ratio)
+        } else {
+            coord
         }
     }
 
+    /// Checks if tiles would come out smaller than `min_cell_size`, so the board can't be drawn
+    pub fn board_too_large(&self, controller: &BoardController, ctx: &Context) -> bool {
+        let (cell_size, _, _) = self.tile_padding(controller, ctx);
+        cell_size < self.settings.min_cell_size
+    }
+
     /// Gets the extents of the game and board
     fn game_extents(&self, controller: &BoardController, ctx: &Context) -> (Extents, Extents) {
         let settings = &self.settings;
-        let canvas = ctx.canvas().unwrap_throw();
+        let (canvas_width, canvas_height) = self.canvas_size(ctx);
         let (cell_size, x_padding, y_padding) = self.tile_padding(controller, ctx);
         let game = Extents {
             west: x_padding,
-            east: canvas.width() as f64 - x_padding - settings.ui_margin_east,
+            east: canvas_width - x_padding - settings.ui_margin_east,
             north: y_padding,
-            south: canvas.height() as f64 - y_padding - settings.ui_margin_south,
+            south: canvas_height - y_padding - settings.ui_margin_south,
         };
         let board = game.clone() - cell_size;
         (game, board)
     }
 
+    /// Gets where the board and UI panels are currently laid out on the canvas
+    pub fn layout(&self, controller: &BoardController, ctx: &Context) -> BoardLayout {
+        let (cell_size, _, _) = self.tile_padding(controller, ctx);
+        let (_, board) = self.game_extents(controller, ctx);
+        let (south_panel, east_panel) = self.ui_extents(ctx);
+        let [x, y, w, h] = board.rect();
+        BoardLayout {
+            cell_size,
+            board_origin: [x, y],
+            board_size: [w, h],
+            south_panel: south_panel.rect(),
+            east_panel: east_panel.rect(),
+        }
+    }
+
     /// Gets the extents of the south and east UI panels
     fn ui_extents(&self, ctx: &Context) -> (Extents, Extents) {
         let settings = &self.settings;
-        let canvas = ctx.canvas().unwrap_throw();
+        let (canvas_width, canvas_height) = self.canvas_size(ctx);
         let global = Extents {
             north: 0.0,
-            south: canvas.height() as f64,
+            south: canvas_height,
             west: 0.0,
-            east: canvas.width() as f64,
+            east: canvas_width,
         };
         let south = Extents {
             north: global.south - settings.ui_margin_south,
@@ -306,8 +555,66 @@ impl BoardView {
         (south, east)
     }
 
-    /// Draw board
+    /// Draw board and UI panels on one context, as the running animations currently stand
     pub fn draw(&self, controller: &BoardController, local_id: PlayerID, ctx: &Context) {
+        let (width, height) = self.canvas_size(ctx);
+        self.draw_in_rect(controller, local_id, ctx, [0.0, 0.0, width, height]);
+    }
+
+    /// Draw board and UI panels confined to the `[x, y, w, h]` rectangle (in CSS pixels) of the
+    /// canvas, as the running animations currently stand
+    ///
+    /// Everything is laid out as though the rectangle were the whole canvas, so the `_in_rect` hit
+    /// tests must be given the same rectangle.
+    pub fn draw_in_rect(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+        ctx: &Context,
+        rect: [f64; 4],
+    ) {
+        let anim_state = anim::STATE.read().unwrap();
+        let ratio = self.settings.device_pixel_ratio;
+        let [x, y, w, h] = rect;
+        ctx.save();
+        ctx.begin_path();
+        ctx.rect(x * ratio, y * ratio, w * ratio, h * ratio);
+        ctx.clip();
+        ctx.translate(x * ratio, y * ratio).unwrap_throw();
+        self.within_rect(rect, || self.draw_with_anim(controller, local_id, &anim_state, ctx));
+        ctx.restore();
+    }
+
+    /// Runs the given code with extents laid out in the given rectangle, relative to its corner
+    fn within_rect<T, F: FnOnce() -> T>(&self, rect: [f64; 4], f: F) -> T {
+        let old_rect = self.rect.replace(Some(rect));
+        let result = f();
+        self.rect.set(old_rect);
+        result
+    }
+
+    /// Draw board and UI panels on one context, as of the given animation state
+    pub fn draw_with_anim(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
+        self.draw_board(controller, local_id, anim_state, ctx);
+        self.draw_ui_panels(controller, local_id, anim_state, ctx);
+    }
+
+    /// Draw only the board, leaving the UI panels alone
+    ///
+    /// The UI panels can be drawn with `draw_ui_panels` on an overlay canvas of the same size.
+    pub fn draw_board(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
         // if a child is coming up soon, pretend we are them instead
         let local_id = controller.effective_local_id(local_id);
 
@@ -324,21 +631,37 @@ impl BoardView {
 
         ctx.save();
 
+        // extents are in CSS pixels, so draw at the device pixel ratio
+        ctx.scale(settings.device_pixel_ratio, settings.device_pixel_ratio)
+            .unwrap_throw();
+
+        // tiny tiles are unreadable, so say why there's no board instead
+        if self.board_too_large(controller, ctx) {
+            let [x, y] = game.center();
+            ctx.set_fill_style(&settings.text_color.into());
+            ctx.set_font("20px sans-serif");
+            ctx.set_text_align("center");
+            ctx.fill_text("Board too large for this canvas", x, y)
+                .unwrap_throw();
+            ctx.restore();
+            return;
+        }
+
         // draw the tiles
-        self.draw_tiles(controller, local_id, ctx);
+        self.draw_tiles(controller, local_id, anim_state, ctx);
 
         // draw tile edges
         ctx.set_line_width(settings.cell_edge_radius);
         ctx.set_stroke_style(&settings.cell_edge_color.into());
         for i in 0..board_tile_width {
-            let x = board.west + i as f64 * cell_size;
+            let x = self.crisp(board.west + i as f64 * cell_size);
             ctx.begin_path();
             ctx.move_to(x, board.north);
             ctx.line_to(x, board.south);
             ctx.stroke();
         }
         for j in 0..board_tile_height {
-            let y = game.north + (j + 1) as f64 * cell_size;
+            let y = self.crisp(game.north + (j + 1) as f64 * cell_size);
             ctx.begin_path();
             ctx.move_to(board.west, y);
             ctx.line_to(board.east, y);
@@ -348,20 +671,137 @@ impl BoardView {
         // draw board edge
         ctx.set_line_width(settings.board_edge_radius);
         ctx.set_stroke_style(&settings.board_edge_color.into());
-        ctx.stroke_rect(board.west, board.north, board_width, board_height);
+        let west = self.crisp(board.west);
+        let north = self.crisp(board.north);
+        let east = self.crisp(board.west + board_width);
+        let south = self.crisp(board.north + board_height);
+        ctx.stroke_rect(west, north, east - west, south - north);
+
+        // draw the line the hovered guide would shift
+        self.draw_insert_preview(controller, ctx);
 
         // draw insert guides
         self.draw_insert_guides(controller, local_id, ctx);
 
+        // draw the tile the hovered guide would push off the board
+        if settings.show_ejected_ghost {
+            self.draw_ejected_ghost(controller, local_id, anim_state, ctx);
+        }
+
+        // mark where the board last shifted from
+        if settings.show_last_insert {
+            self.draw_last_insert(controller, anim_state, ctx);
+        }
+
+        // draw where tokens just moved from
+        if settings.move_trails {
+            self.draw_move_trails(controller, anim_state, ctx);
+        }
+
         // draw player tokens
-        self.draw_player_tokens(DrawMode::All, controller, local_id, ctx);
+        self.draw_player_tokens(DrawMode::All, controller, local_id, anim_state, ctx);
 
         // draw own token on top of others
-        self.draw_player_tokens(DrawMode::OnlySelf, controller, local_id, ctx);
+        self.draw_player_tokens(DrawMode::OnlySelf, controller, local_id, anim_state, ctx);
+
+        ctx.restore();
+    }
+
+    /// Draw only the UI panels, the loose tile, and the turn flash
+    pub fn draw_ui_panels(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
+        // if a child is coming up soon, pretend we are them instead
+        let local_id = controller.effective_local_id(local_id);
+        let settings = &self.settings;
+
+        ctx.save();
+
+        // extents are in CSS pixels, so draw at the device pixel ratio
+        ctx.scale(settings.device_pixel_ratio, settings.device_pixel_ratio)
+            .unwrap_throw();
 
         // draw UI
-        self.draw_ui(controller, local_id, ctx);
+        self.draw_ui(controller, local_id, anim_state, ctx);
+
+        // flash the edges of the screen when our turn starts
+        self.draw_turn_flash(controller, local_id, anim_state, ctx);
+
+        ctx.restore();
+    }
+
+    fn draw_move_trails(
+        &self,
+        controller: &BoardController,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
+        let (cell_size, _, _) = self.tile_padding(controller, ctx);
+        for token in controller.board.player_tokens.values() {
+            let alpha = anim_state.move_trail.alpha(token.player_id);
+            let player = controller.players.get(&token.player_id);
+            let (from, player) = match (token.last_position, player) {
+                (Some(from), Some(player)) if alpha > 0.0 => (from, player),
+                _ => continue,
+            };
+            let [from_x, from_y] = self.tile_extents(controller, from.0, from.1, ctx).center();
+            let (row, col) = token.position;
+            let [to_x, to_y] = self.tile_extents(controller, row, col, ctx).center();
+            ctx.save();
+            ctx.set_global_alpha(alpha * 0.5);
+            ctx.set_stroke_style(&player.color.into());
+            ctx.set_fill_style(&player.color.into());
+            ctx.set_line_width(cell_size * 0.1);
+            ctx.set_line_cap("round");
+            ctx.begin_path();
+            ctx.move_to(from_x, from_y);
+            ctx.line_to(to_x, to_y);
+            ctx.stroke();
+            // ghost of the token where it used to be
+            ctx.begin_path();
+            ctx.ellipse(
+                from_x,
+                from_y,
+                cell_size * 0.2,
+                cell_size * 0.2,
+                0.0,
+                0.0,
+                ::std::f64::consts::PI * 2.0,
+            )
+                .unwrap_throw();
+            ctx.fill();
+            ctx.restore();
+        }
+    }
 
+    fn draw_turn_flash(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
+        let alpha = anim_state.turn_flash.alpha();
+        let player = match controller.players.get(&local_id) {
+            Some(player) if alpha > 0.0 => player,
+            _ => return,
+        };
+        let (width, height) = self.canvas_size(ctx);
+        let glow_width = 12.0;
+        ctx.save();
+        ctx.set_global_alpha(alpha);
+        ctx.set_stroke_style(&player.color.into());
+        ctx.set_line_width(glow_width);
+        ctx.stroke_rect(
+            glow_width / 2.0,
+            glow_width / 2.0,
+            width - glow_width,
+            height - glow_width,
+        );
         ctx.restore();
     }
 
@@ -386,67 +826,257 @@ impl BoardView {
         }
     }
 
+    /// Gets the `[x, y, w, h]` rectangle of the given tile, in CSS pixels
+    pub fn tile_rect(
+        &self,
+        controller: &BoardController,
+        row: usize,
+        col: usize,
+        ctx: &Context,
+    ) -> [f64; 4] {
+        self.tile_extents(controller, row, col, ctx).rect()
+    }
+
     /// Checks if a given position is within a tile, and returns that tile's (row, col)
+    ///
+    /// Like every hit test here, a position with a NaN coordinate never hits anything.
     pub fn in_tile(
         &self,
         pos: &[f64; 2],
         controller: &BoardController,
         ctx: &Context,
     ) -> Option<(usize, usize)> {
+        if is_nan(pos) {
+            return None;
+        }
         // TODO don't do this dumb thing
 
-        let board_tile_width = controller.board.width();
-        let board_tile_height = controller.board.height();
-
-        for j in 0..board_tile_height {
-            for i in 0..board_tile_width {
-                let cell = self.tile_extents(controller, j, i, ctx);
-                if pos < &cell {
-                    return Some((j, i));
-                }
+        for ((j, i), _) in controller.board.iter_tiles() {
+            let cell = self.tile_extents(controller, j, i, ctx);
+            if pos < &cell {
+                return Some((j, i));
             }
         }
         None
     }
 
-    fn draw_tiles(&self, controller: &BoardController, local_id: PlayerID, ctx: &Context) {
-        let board_tile_width = controller.board.width();
-        let board_tile_height = controller.board.height();
+    /// Gets how far a running insert has slid the tile at (row, col), if it is in the sliding line
+    fn insert_shift(
+        &self,
+        cell_size: f64,
+        pos: (usize, usize),
+        anim_state: &AnimGlobalState,
+    ) -> Option<[f64; 2]> {
+        let loose_insert = &anim_state.loose_insert;
+        if loose_insert.applies_to_pos(pos) {
+            Some([0.0, loose_insert.offset() * cell_size] * loose_insert.offset_dir)
+        } else {
+            None
+        }
+    }
 
+    /// Runs the given drawing code with the context centered on the given cell (shifted along with
+    /// any running insert), passing the cell's extents relative to that center
+    fn in_cell<F: FnOnce(Extents)>(
+        &self,
+        controller: &BoardController,
+        (row, col): (usize, usize),
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+        draw: F,
+    ) {
         let (cell_size, _, _) = self.tile_padding(controller, ctx);
-        let current_player_pos = controller.board.player_pos(local_id);
-        let reachable = controller.board.reachable_coords(current_player_pos);
-        let loose_insert = &anim::STATE.read().unwrap().loose_insert;
+        let loose_insert = &anim_state.loose_insert;
+        let board_intro = &anim_state.board_intro;
+        let cell = self.tile_extents(controller, row, col, ctx);
+
+        ctx.save();
+        if !board_intro.is_done() {
+            let size = (controller.board.width(), controller.board.height());
+            ctx.set_global_alpha(board_intro.tile_alpha((row, col), size));
+        }
+        let shift = self.insert_shift(cell_size, (row, col), anim_state);
+        let shifting = shift.is_some();
+        if let Some([x, y]) = shift {
+            ctx.translate(x, y).unwrap_throw();
+        }
+        let center = cell.center();
+        let [x, y] = center;
+        ctx.translate(x, y).unwrap_throw();
+        if shifting {
+            let squash = loose_insert.squash(self.settings.insert_squash);
+            match loose_insert.offset_dir {
+                Direction::North | Direction::South => ctx.scale(1.0, squash).unwrap_throw(),
+                Direction::East | Direction::West => ctx.scale(squash, 1.0).unwrap_throw(),
+            }
+        }
+        draw(cell - center);
+        ctx.restore();
+    }
 
-        let [offset_x, offset_y] =
-            [0.0, loose_insert.distance_left * cell_size] * loose_insert.offset_dir;
+    /// Gets the tiles to shade as reachable for the local player, and whether to shade them dimly
+    fn reachable_shading(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+    ) -> (HashSet<(usize, usize)>, bool) {
+        // shade where we can go fully while we are choosing where to go, and dimly while we are
+        // still inserting (previewing the hovered insert if there is one)
+        let my_turn = !self.spectator && !self.clean_feed && controller.local_turn(local_id);
+        let loose_tile_position = controller.board.loose_tile_position;
+        match (&controller.turn_state, loose_tile_position) {
+            (TurnState::MoveToken, _) if my_turn => {
+                let current_player_pos = controller.board.player_pos(local_id);
+                (controller.reachable_from(current_player_pos), false)
+            }
+            (TurnState::InsertTile, Some((dir, idx))) if my_turn => {
+                let orientation = controller.board.loose_tile.orientation;
+                // the board hasn't shifted yet, so shade each tile where it sits now
+                let reachable = controller
+                    .reachable_after_insert(dir, idx, orientation, local_id)
+                    .into_iter()
+                    .filter_map(|pos| controller.board.unshifted_pos(pos, (dir, idx)))
+                    .collect();
+                (reachable, true)
+            }
+            (TurnState::InsertTile, None) if my_turn => {
+                let current_player_pos = controller.board.player_pos(local_id);
+                (controller.reachable_from(current_player_pos), true)
+            }
+            _ => (HashSet::new(), false),
+        }
+    }
 
-        for j in 0..board_tile_height {
-            for i in 0..board_tile_width {
-                let cell = self.tile_extents(controller, j, i, ctx);
-                let color = if reachable.contains(&(j, i)) {
-                    self.settings.reachable_background_color
-                } else {
-                    self.settings.background_color
-                };
-                let is_highlighted = controller.highlighted_tile == (j, i);
-                ctx.save();
-                if loose_insert.applies_to_pos((j, i)) {
-                    ctx.translate(offset_x, offset_y).unwrap_throw();
-                };
-                self.draw_tile(
-                    controller.board.get([i, j]),
-                    cell,
-                    color,
-                    is_highlighted,
-                    false,
-                    controller,
-                    local_id,
-                    ctx,
-                );
-                ctx.restore();
+    /// Gets the opacity of reachable shading, which is dimmed while tiles are still being inserted
+    fn reachable_alpha(&self, dim: bool) -> f64 {
+        if dim {
+            self.settings.reachable_dim_alpha
+        } else {
+            1.0
+        }
+    }
+
+    /// Checks if so many tiles are fully reachable that shading the whole board at once will do
+    fn shades_whole_board(&self, reachable_count: usize, dim: bool) -> bool {
+        match self.settings.max_highlighted_tiles {
+            Some(max) => !dim && reachable_count > max,
+            None => false,
+        }
+    }
+
+    fn draw_tiles(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
+        let cells = controller
+            .board
+            .iter_tiles()
+            .map(|(pos, _)| pos)
+            .collect::<Vec<_>>();
+
+        let (reachable, dim) = self.reachable_shading(controller, local_id);
+
+        // changing styles is slow, so draw each layer of every tile with as few style changes as possible
+        ctx.save();
+
+        // fill backgrounds one color at a time
+        let style = self.settings.background_style;
+        let mut backgrounds = style
+            .fills(self.settings.background_color)
+            .into_iter()
+            .map(|color| (color, vec![]))
+            .collect::<Vec<_>>();
+        let outline = self.settings.reachable_style == ReachableStyle::Outline;
+        let mut reachable_cells = vec![];
+        for &(j, i) in &cells {
+            if reachable.contains(&(j, i)) && !outline {
+                reachable_cells.push((j, i));
+            }
+            if dim || outline || !reachable.contains(&(j, i)) {
+                backgrounds[style.fill_index((j, i))].1.push((j, i));
             }
         }
+        if self.shades_whole_board(reachable_cells.len(), dim) {
+            // nearly everything is reachable, so shading the whole board is close enough and faster
+            let (_, board) = self.game_extents(controller, ctx);
+            let [x, y, w, h] = board.rect();
+            ctx.set_fill_style(&self.settings.reachable_background_color.into());
+            ctx.fill_rect(x, y, w, h);
+        } else {
+            if !dim {
+                let reachable_cells = reachable_cells.clone();
+                backgrounds.push((self.settings.reachable_background_color, reachable_cells));
+            }
+            for (color, positions) in backgrounds {
+                ctx.set_fill_style(&color.into());
+                for pos in positions {
+                    self.in_cell(controller, pos, anim_state, ctx, |outer| {
+                        self.fill_tile_background(&outer, ctx);
+                    });
+                }
+            }
+        }
+
+        // dimly shade reachable cells over their usual background
+        let alpha = self.reachable_alpha(dim);
+        if dim {
+            ctx.set_fill_style(&self.settings.reachable_background_color.into());
+            for pos in reachable_cells {
+                self.in_cell(controller, pos, anim_state, ctx, |outer| {
+                    ctx.set_global_alpha(ctx.global_alpha() * alpha);
+                    self.fill_tile_background(&outer, ctx);
+                });
+            }
+        }
+
+        // draw targets, which pick their own colors
+        for (pos, tile) in controller.board.iter_tiles() {
+            if tile.whose_target.is_some() {
+                self.in_cell(controller, pos, anim_state, ctx, |outer| {
+                    self.clip_tile(&outer, ctx);
+                    let pos = Some(pos);
+                    self.draw_tile_target(tile, pos, &outer, controller, local_id, anim_state, ctx);
+                });
+            }
+        }
+
+        // draw all the walls in one color
+        ctx.set_fill_style(&self.settings.wall_color.into());
+        for (pos, tile) in controller.board.iter_tiles() {
+            self.in_cell(controller, pos, anim_state, ctx, |outer| {
+                self.clip_tile(&outer, ctx);
+                self.draw_tile_walls(tile, &outer, ctx);
+            });
+        }
+
+        // draw all the treasures in one color
+        ctx.set_fill_style(&self.settings.treasure_color.into());
+        for &pos in &controller.board.treasures {
+            self.in_cell(controller, pos, anim_state, ctx, |outer| {
+                self.draw_tile_treasure(&outer, ctx);
+            });
+        }
+
+        // outline the reachable region, rather than having filled it in
+        if outline {
+            ctx.set_fill_style(&self.settings.reachable_outline_color.into());
+            for (pos, dir) in reachable_boundary(&reachable) {
+                self.in_cell(controller, pos, anim_state, ctx, |outer| {
+                    ctx.set_global_alpha(ctx.global_alpha() * alpha);
+                    self.draw_tile_side(&outer, dir, ctx);
+                });
+            }
+        }
+
+        // draw the highlight
+        self.in_cell(controller, controller.highlighted_tile, anim_state, ctx, |outer| {
+            self.draw_tile_border(&outer, ctx);
+        });
+
+        ctx.restore();
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -459,14 +1089,11 @@ impl BoardView {
         is_loose: bool,
         controller: &BoardController,
         local_id: PlayerID,
+        anim_state: &AnimGlobalState,
         ctx: &Context,
     ) {
         let settings = &self.settings;
 
-        let (cell_size, _, _) = self.tile_padding(controller, ctx);
-        let wall_width = cell_size * settings.wall_width;
-        let anim_state = anim::STATE.read().unwrap();
-
         ctx.save();
 
         let [x, y] = outer.center();
@@ -479,21 +1106,60 @@ impl BoardView {
             .unwrap_throw();
 
         let outer = outer.clone() - outer.center();
-        let inner = outer.clone() - wall_width;
 
         ctx.set_fill_style(&background_color.into());
-        ctx.fill_rect(outer.west, outer.north, cell_size, cell_size);
+        self.fill_tile_background(&outer, ctx);
+        self.clip_tile(&outer, ctx);
 
-        if let Some(whose_target) = tile.whose_target {
-            let color = controller.players[&whose_target].color;
+        self.draw_tile_target(tile, None, &outer, controller, local_id, anim_state, ctx);
 
-            // TODO tilt based on something so less reliant on color
+        ctx.set_fill_style(&settings.wall_color.into());
+        self.draw_tile_walls(tile, &outer, ctx);
 
-            let anim_offset = if tile.whose_target == Some(local_id) {
-                anim_state.target_stripe.pct_offset() * cell_size / 3.0
-            } else {
-                0.0
-            };
+        if draw_border {
+            self.draw_tile_border(&outer, ctx);
+        }
+
+        ctx.restore();
+    }
+
+    /// Draws the target stripes on a tile, if it is anyone's target
+    #[allow(clippy::too_many_arguments)]
+    fn draw_tile_target(
+        &self,
+        tile: &Tile,
+        pos: Option<(usize, usize)>,
+        outer: &Extents,
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
+        if let Some(whose_target) = tile.whose_target {
+            let cell_size = outer.east - outer.west;
+            let player = &controller.players[&whose_target];
+            let color = player.color;
+
+            // the local player's target on the board points the way to their token
+            let is_local =
+                !self.spectator && !self.clean_feed && tile.whose_target == Some(local_id);
+            if self.settings.target_flow && is_local {
+                if let Some(pos) = pos {
+                    let towards = flow_towards(pos, controller.board.player_pos(whose_target));
+                    let pct = anim_state.target_stripe.pct_offset();
+                    ctx.set_fill_style(&color.into());
+                    self.draw_target_flow(outer, towards, pct, ctx);
+                    return;
+                }
+            }
+
+            // TODO tilt based on something so less reliant on color
+
+            let anim_offset = if self.spectator || is_local {
+                anim_state.target_stripe.pct_offset() * cell_size / 3.0
+            } else {
+                0.0
+            };
 
             let diagonal = outer.diagonal();
             let diagonals = (-4..4)
@@ -518,35 +1184,131 @@ impl BoardView {
                 ctx.line_to(x, y);
                 ctx.fill();
             }
+
+            // spectators can't tell targets apart by whose they are, so label them
+            if self.spectator {
+                if let Some(initial) = player.name.chars().next() {
+                    let [x, y] = outer.center();
+                    ctx.save();
+                    ctx.set_fill_style(&self.settings.text_color.into());
+                    ctx.set_font(&format!("{}px sans-serif", (cell_size / 3.0) as u32));
+                    ctx.set_text_align("center");
+                    ctx.set_text_baseline("middle");
+                    ctx.fill_text(&initial.to_string(), x, y).unwrap_throw();
+                    ctx.restore();
+                }
+            }
         }
+    }
 
-        ctx.set_fill_style(&settings.wall_color.into());
-        ctx.fill_rect(outer.west, outer.north, wall_width, wall_width);
-        ctx.fill_rect(inner.east, outer.north, wall_width, wall_width);
-        ctx.fill_rect(outer.west, inner.south, wall_width, wall_width);
-        ctx.fill_rect(inner.east, inner.south, wall_width, wall_width);
-        let walled_directions = tile.walls();
-        for d in walled_directions {
-            let (x, y, w, h) = match d {
-                Direction::North => (outer.west, outer.north, cell_size, wall_width),
-                Direction::South => (outer.west, inner.south, cell_size, wall_width),
-                Direction::East => (inner.east, outer.north, wall_width, cell_size),
-                Direction::West => (outer.west, outer.north, wall_width, cell_size),
-            };
+    /// Draws target stripes flowing along the given direction in the current fill style, or pulsing if it is zero
+    fn draw_target_flow(&self, outer: &Extents, [dx, dy]: [f64; 2], pct: f64, ctx: &Context) {
+        let cell_size = outer.east - outer.west;
+        let period = cell_size / 3.0;
+        let [x, y] = outer.center();
+        ctx.save();
+        ctx.begin_path();
+        ctx.rect(outer.west, outer.north, cell_size, outer.south - outer.north);
+        ctx.clip();
+        ctx.translate(x, y).unwrap_throw();
+        let offset = if dx == 0.0 && dy == 0.0 {
+            // the token is already here, so pulse in place
+            ctx.set_global_alpha(0.5 + 0.5 * (pct * 2.0 * PI).cos());
+            0.0
+        } else {
+            ctx.rotate(dy.atan2(dx)).unwrap_throw();
+            pct * period
+        };
+        // stripes run across the flow, which is now along +x
+        for k in -4..3 {
+            let west = f64::from(k) * period + offset;
+            ctx.fill_rect(west, -cell_size, period / 2.0, 2.0 * cell_size);
+        }
+        ctx.restore();
+    }
+
+    /// Draws the walls and corner blocks of a tile in the current fill style
+    fn draw_tile_walls(&self, tile: &Tile, outer: &Extents, ctx: &Context) {
+        self.tile_renderer.draw_tile(tile, outer, &self.settings, ctx);
+    }
+
+    /// Traces the outline of a tile, with its corners rounded by `tile_corner_radius`
+    fn trace_tile(&self, outer: &Extents, ctx: &Context) {
+        let half = (outer.east - outer.west) / 2.0;
+        let radius = self.settings.tile_corner_radius.min(half);
+        ctx.begin_path();
+        ctx.move_to(outer.west + radius, outer.north);
+        ctx.arc_to(outer.east, outer.north, outer.east, outer.south, radius)
+            .unwrap_throw();
+        ctx.arc_to(outer.east, outer.south, outer.west, outer.south, radius)
+            .unwrap_throw();
+        ctx.arc_to(outer.west, outer.south, outer.west, outer.north, radius)
+            .unwrap_throw();
+        ctx.arc_to(outer.west, outer.north, outer.east, outer.north, radius)
+            .unwrap_throw();
+        ctx.close_path();
+    }
+
+    /// Fills the background of a tile in the current fill style
+    fn fill_tile_background(&self, outer: &Extents, ctx: &Context) {
+        if let Some([x, y, w, h]) = self.square_background(outer) {
             ctx.fill_rect(x, y, w, h);
+            return;
         }
+        self.trace_tile(outer, ctx);
+        ctx.fill();
+    }
 
-        if draw_border {
-            let border_width = wall_width / 3.0;
-            let inner = outer.clone() - border_width;
-            ctx.set_fill_style(&settings.text_color.into());
-            ctx.fill_rect(outer.west, outer.north, cell_size, border_width);
-            ctx.fill_rect(outer.west, inner.south, cell_size, border_width);
-            ctx.fill_rect(inner.east, outer.north, border_width, cell_size);
-            ctx.fill_rect(outer.west, outer.north, border_width, cell_size);
+    /// Gets the `[x, y, w, h]` a tile's background fills if its corners are square
+    fn square_background(&self, outer: &Extents) -> Option<[f64; 4]> {
+        if self.settings.tile_corner_radius > 0.0 {
+            return None;
         }
+        let cell_size = outer.east - outer.west;
+        Some([outer.west, outer.north, cell_size, cell_size])
+    }
 
-        ctx.restore();
+    /// Keeps further drawing inside a tile's rounded corners, until the context is restored
+    fn clip_tile(&self, outer: &Extents, ctx: &Context) {
+        if self.settings.tile_corner_radius <= 0.0 {
+            return;
+        }
+        self.trace_tile(outer, ctx);
+        ctx.clip();
+    }
+
+    /// Draws a treasure as a small diamond in the middle of the cell, in the current fill style
+    fn draw_tile_treasure(&self, outer: &Extents, ctx: &Context) {
+        let [x, y] = outer.center();
+        let radius = (outer.east - outer.west) / 6.0;
+        ctx.begin_path();
+        ctx.move_to(x, y - radius);
+        ctx.line_to(x + radius, y);
+        ctx.line_to(x, y + radius);
+        ctx.line_to(x - radius, y);
+        ctx.close_path();
+        ctx.fill();
+    }
+
+    /// Draws the highlight border around a tile
+    fn draw_tile_border(&self, outer: &Extents, ctx: &Context) {
+        ctx.set_fill_style(&self.settings.text_color.into());
+        for &dir in Direction::all() {
+            self.draw_tile_side(outer, dir, ctx);
+        }
+    }
+
+    /// Fills a bar along one side of a tile, as thick as one side of a tile border
+    fn draw_tile_side(&self, outer: &Extents, dir: Direction, ctx: &Context) {
+        let cell_size = outer.east - outer.west;
+        let border_width = cell_size * self.settings.wall_width.max() / 3.0;
+        let inner = outer.clone() - border_width;
+        match dir {
+            Direction::North => ctx.fill_rect(outer.west, outer.north, cell_size, border_width),
+            Direction::South => ctx.fill_rect(outer.west, inner.south, cell_size, border_width),
+            Direction::East => ctx.fill_rect(inner.east, outer.north, border_width, cell_size),
+            Direction::West => ctx.fill_rect(outer.west, outer.north, border_width, cell_size),
+        }
     }
 
     fn insert_guides(
@@ -563,8 +1325,9 @@ impl BoardView {
 
         let mut north = vec![];
         let mut south = vec![];
-        for i in 0..(board_tile_width / 2) {
-            let west = board.west + (2 * i + 1) as f64 * cell_size;
+        let rule = controller.board.insert_rule;
+        for i in 0..rule.guide_count(board_tile_width) {
+            let west = board.west + rule.guide_line(i) as f64 * cell_size;
             let east = west + cell_size;
 
             let north_extents = Extents {
@@ -587,8 +1350,8 @@ impl BoardView {
         result.push((Direction::South, south));
         let mut east = vec![];
         let mut west = vec![];
-        for j in 0..(board_tile_height / 2) {
-            let north = board.north + (2 * j + 1) as f64 * cell_size;
+        for j in 0..rule.guide_count(board_tile_height) {
+            let north = board.north + rule.guide_line(j) as f64 * cell_size;
             let south = north + cell_size;
 
             let west_extents = Extents {
@@ -609,6 +1372,9 @@ impl BoardView {
         }
         result.push((Direction::East, east));
         result.push((Direction::West, west));
+        // sides that can't be inserted from get no guides at all
+        let allowed = &controller.board.allowed_insert_directions;
+        result.retain(|(dir, _)| allowed.contains(dir));
         result
     }
 
@@ -616,43 +1382,21 @@ impl BoardView {
         let settings = &self.settings;
 
         let (cell_size, _, _) = self.tile_padding(controller, ctx);
-        let wall_width = cell_size * settings.wall_width;
+        let wall_width = cell_size * settings.wall_width.max();
 
         ctx.save();
 
         ctx.set_fill_style(&settings.insert_guide_color.into());
         for (dir, guides) in self.insert_guides(controller, ctx) {
-            for guide in guides {
-                let guide = guide - wall_width;
-                let mid_x = (guide.east + guide.west) / 2.0;
-                let mid_y = (guide.north + guide.south) / 2.0;
-                let ((x0, y0), (x1, y1), (x2, y2)) = match dir {
-                    Direction::North => (
-                        (guide.west, guide.north),
-                        (mid_x, guide.south),
-                        (guide.east, guide.north),
-                    ),
-                    Direction::South => (
-                        (guide.west, guide.south),
-                        (mid_x, guide.north),
-                        (guide.east, guide.south),
-                    ),
-                    Direction::West => (
-                        (guide.west, guide.north),
-                        (guide.east, mid_y),
-                        (guide.west, guide.south),
-                    ),
-                    Direction::East => (
-                        (guide.east, guide.north),
-                        (guide.west, mid_y),
-                        (guide.east, guide.south),
-                    ),
+            for (i, guide) in guides.into_iter().enumerate() {
+                // grey out guides the rules don't allow this turn
+                let alpha = if controller.can_insert_at(dir, i) {
+                    1.0
+                } else {
+                    0.3
                 };
-                ctx.begin_path();
-                ctx.move_to(x0, y0);
-                ctx.line_to(x1, y1);
-                ctx.line_to(x2, y2);
-                ctx.close_path();
+                ctx.set_global_alpha(alpha);
+                self.trace_guide_arrow(dir, &(guide - wall_width), ctx);
                 ctx.fill();
             }
         }
@@ -660,16 +1404,229 @@ impl BoardView {
         ctx.restore();
     }
 
-    /// Checks if the given position is in an insert guide or not
+    /// Traces the arrow drawn on an insert guide, pointing into the board
+    fn trace_guide_arrow(&self, dir: Direction, guide: &Extents, ctx: &Context) {
+        let mid_x = (guide.east + guide.west) / 2.0;
+        let mid_y = (guide.north + guide.south) / 2.0;
+        let ((x0, y0), (x1, y1), (x2, y2)) = match dir {
+            Direction::North => (
+                (guide.west, guide.north),
+                (mid_x, guide.south),
+                (guide.east, guide.north),
+            ),
+            Direction::South => (
+                (guide.west, guide.south),
+                (mid_x, guide.north),
+                (guide.east, guide.south),
+            ),
+            Direction::West => (
+                (guide.west, guide.north),
+                (guide.east, mid_y),
+                (guide.west, guide.south),
+            ),
+            Direction::East => (
+                (guide.east, guide.north),
+                (guide.west, mid_y),
+                (guide.east, guide.south),
+            ),
+        };
+        ctx.begin_path();
+        ctx.move_to(x0, y0);
+        ctx.line_to(x1, y1);
+        ctx.line_to(x2, y2);
+        ctx.close_path();
+    }
+
+    /// Outlines the guide the last insert came from, fading out shortly after it happens
+    fn draw_last_insert(
+        &self,
+        controller: &BoardController,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
+        let alpha = anim_state.last_insert.alpha();
+        let (target_dir, idx) = match controller.last_insert {
+            Some(insert) if alpha > 0.0 => insert,
+            _ => return,
+        };
+        let guide = self
+            .insert_guides(controller, ctx)
+            .into_iter()
+            .filter(|(dir, _)| *dir == target_dir)
+            .flat_map(|(_, guides)| guides.into_iter().nth(idx))
+            .next();
+        let guide = match guide {
+            Some(guide) => guide,
+            None => return,
+        };
+
+        let (cell_size, _, _) = self.tile_padding(controller, ctx);
+        let wall_width = cell_size * self.settings.wall_width.max();
+
+        ctx.save();
+        ctx.set_global_alpha(alpha);
+        ctx.set_stroke_style(&self.settings.last_insert_color.into());
+        ctx.set_line_width(wall_width / 2.0);
+        self.trace_guide_arrow(target_dir, &(guide - wall_width), ctx);
+        ctx.stroke();
+        ctx.restore();
+    }
+
+    /// Draws a faded, crossed out copy of the tile the hovered insert would push off, just past
+    /// the edge it would leave by
+    fn draw_ejected_ghost(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
+        match controller.turn_state {
+            TurnState::InsertTile => {}
+            TurnState::Animating | TurnState::MoveToken => return,
+        }
+        let (dir, idx) = match controller.board.loose_tile_position {
+            Some(position) => position,
+            None => return,
+        };
+        let (row, col) = controller.board.ejected_pos(dir, idx);
+        let (cell_size, _, _) = self.tile_padding(controller, ctx);
+        let [x, y] = [0.0, cell_size] * dir;
+        let ghost = self.tile_extents(controller, row, col, ctx) - [-x, -y];
+
+        ctx.save();
+        ctx.set_global_alpha(0.35);
+        let tile = controller.board.get([col, row]);
+        let background = self.settings.background_color;
+        self.draw_tile(
+            tile, ghost.clone(), background, false, false, controller, local_id, anim_state, ctx,
+        );
+        ctx.set_global_alpha(0.8);
+        ctx.set_stroke_style(&self.settings.ejected_mark_color.into());
+        ctx.set_line_width(cell_size / 12.0);
+        let inset = cell_size / 4.0;
+        ctx.begin_path();
+        ctx.move_to(ghost.west + inset, ghost.north + inset);
+        ctx.line_to(ghost.east - inset, ghost.south - inset);
+        ctx.move_to(ghost.east - inset, ghost.north + inset);
+        ctx.line_to(ghost.west + inset, ghost.south - inset);
+        ctx.stroke();
+        ctx.restore();
+    }
+
+    fn draw_insert_preview(&self, controller: &BoardController, ctx: &Context) {
+        match controller.turn_state {
+            TurnState::InsertTile => {}
+            TurnState::Animating | TurnState::MoveToken => return,
+        }
+        let (target_dir, idx) = match controller.board.loose_tile_position {
+            Some(position) => position,
+            None => return,
+        };
+        let guide = self
+            .insert_guides(controller, ctx)
+            .into_iter()
+            .filter(|(dir, _)| *dir == target_dir)
+            .flat_map(|(_, guides)| guides.into_iter().nth(idx))
+            .next();
+        let guide = match guide {
+            Some(guide) => guide,
+            None => return,
+        };
+
+        let (cell_size, _, _) = self.tile_padding(controller, ctx);
+        let (_, board) = self.game_extents(controller, ctx);
+        let line = match target_dir {
+            Direction::North | Direction::South => Extents {
+                north: board.north,
+                south: board.south,
+                ..guide
+            },
+            Direction::East | Direction::West => Extents {
+                west: board.west,
+                east: board.east,
+                ..guide
+            },
+        };
+
+        ctx.save();
+        ctx.set_fill_style(&self.settings.insert_preview_color.into());
+        ctx.set_stroke_style(&self.settings.insert_preview_color.into());
+
+        // highlight the whole line
+        ctx.set_global_alpha(0.3);
+        ctx.fill_rect(
+            line.west,
+            line.north,
+            line.east - line.west,
+            line.south - line.north,
+        );
+
+        // draw an arrow along the middle of the line, pointing the way the tiles will move
+        ctx.set_global_alpha(0.7);
+        let [mid_x, mid_y] = line.center();
+        let inset = cell_size / 4.0;
+        let ([x0, y0], [x1, y1]) = match target_dir {
+            Direction::North => ([mid_x, line.north + inset], [mid_x, line.south - inset]),
+            Direction::South => ([mid_x, line.south - inset], [mid_x, line.north + inset]),
+            Direction::West => ([line.west + inset, mid_y], [line.east - inset, mid_y]),
+            Direction::East => ([line.east - inset, mid_y], [line.west + inset, mid_y]),
+        };
+        ctx.set_line_width(cell_size / 10.0);
+        ctx.begin_path();
+        ctx.move_to(x0, y0);
+        ctx.line_to(x1, y1);
+        ctx.stroke();
+        let head = cell_size / 4.0;
+        let angle = (y1 - y0).atan2(x1 - x0);
+        ctx.begin_path();
+        ctx.move_to(x1, y1);
+        ctx.line_to(
+            x1 - head * (angle - FRAC_PI_4).cos(),
+            y1 - head * (angle - FRAC_PI_4).sin(),
+        );
+        ctx.line_to(
+            x1 - head * (angle + FRAC_PI_4).cos(),
+            y1 - head * (angle + FRAC_PI_4).sin(),
+        );
+        ctx.close_path();
+        ctx.fill();
+
+        ctx.restore();
+    }
+
+    /// Gets the `[x, y, w, h]` rectangle of every insert guide with its side and index, in CSS pixels
+    pub fn insert_guide_rects(
+        &self,
+        controller: &BoardController,
+        ctx: &Context,
+    ) -> Vec<(Direction, usize, [f64; 4])> {
+        self.insert_guides(controller, ctx)
+            .into_iter()
+            .flat_map(|(dir, guides)| {
+                guides
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(idx, guide)| (dir, idx, guide.rect()))
+            })
+            .collect()
+    }
+
+    /// Checks if the given position is in an insert guide the rules allow this turn
     pub fn in_insert_guide(
         &self,
         pos: &[f64; 2],
         controller: &BoardController,
         ctx: &Context,
     ) -> Option<(Direction, usize)> {
+        if is_nan(pos) {
+            return None;
+        }
+        let padding = self.settings.insert_guide_hit_padding;
         for (dir, guides) in self.insert_guides(controller, ctx) {
             for (i, guide) in guides.into_iter().enumerate() {
-                if pos < &guide {
+                // subtracting a negative margin grows the guide
+                if pos < &(guide - -padding) && controller.can_insert_at(dir, i) {
                     return Some((dir, i));
                 }
             }
@@ -677,14 +1634,73 @@ impl BoardView {
         None
     }
 
-    fn loose_tile_extents(&self, controller: &BoardController, ctx: &Context) -> Extents {
-        let (target_dir, idx) = controller.board.loose_tile_position;
+    /// Gets the allowed insert guide whose center is closest to the given position, and how far away that center is
+    pub fn nearest_insert_guide(
+        &self,
+        pos: &[f64; 2],
+        controller: &BoardController,
+        ctx: &Context,
+    ) -> Option<(Direction, usize, f64)> {
+        if is_nan(pos) {
+            return None;
+        }
+        let [x, y] = *pos;
+        let mut nearest: Option<(Direction, usize, f64)> = None;
         for (dir, guides) in self.insert_guides(controller, ctx) {
-            if dir == target_dir {
-                return guides[idx].clone();
+            for (i, guide) in guides.into_iter().enumerate() {
+                if !controller.can_insert_at(dir, i) {
+                    continue;
+                }
+                let [center_x, center_y] = guide.center();
+                let distance = (center_x - x).hypot(center_y - y);
+                let is_nearer = match nearest {
+                    Some((_, _, nearest_distance)) => distance < nearest_distance,
+                    None => true,
+                };
+                if is_nearer {
+                    nearest = Some((dir, i, distance));
+                }
+            }
+        }
+        nearest
+    }
+
+    /// Gets the extents of the loose tile, either at its insert guide or resting in the east panel
+    fn loose_tile_extents(&self, controller: &BoardController, ctx: &Context) -> Extents {
+        if let Some((target_dir, idx)) = controller.board.loose_tile_position {
+            for (dir, guides) in self.insert_guides(controller, ctx) {
+                if dir == target_dir {
+                    if let Some(guide) = guides.get(idx) {
+                        return guide.clone();
+                    }
+                }
             }
         }
-        unreachable!()
+        self.loose_tile_resting_extents(controller, ctx)
+    }
+
+    /// Gets the extents of the loose tile before it has been moved to any insert guide
+    ///
+    /// The tile is scaled from the board's tile size by `loose_tile_scale`, but never gets too
+    /// small to see and click or too big to fit in the east panel.
+    fn loose_tile_resting_extents(&self, controller: &BoardController, ctx: &Context) -> Extents {
+        let (cell_size, _, _) = self.tile_padding(controller, ctx);
+        let (_, east_panel) = self.ui_extents(ctx);
+        let padding = 20.0;
+        let room = (east_panel.east - east_panel.west - padding)
+            .min(east_panel.south - east_panel.north - 2.0 * padding)
+            .max(0.0);
+        let size = (cell_size * self.settings.loose_tile_scale)
+            .max(Self::MIN_LOOSE_TILE_SIZE)
+            .min(room);
+        let south = east_panel.south - padding;
+        let west = east_panel.west;
+        Extents {
+            north: south - size,
+            south,
+            east: west + size,
+            west,
+        }
     }
 
     /// Check if the given position is within the loose tile area
@@ -694,27 +1710,167 @@ impl BoardView {
         controller: &BoardController,
         ctx: &Context,
     ) -> bool {
+        if is_nan(pos) {
+            return false;
+        }
         let cell = self.loose_tile_extents(controller, ctx);
         pos < &cell
     }
 
+    /// Checks if the given position is over a rotation control of the loose tile, and returns whether it turns clockwise
+    ///
+    /// The east half of the loose tile turns it clockwise and the west half counterclockwise.
+    pub fn in_loose_rotate_control(
+        &self,
+        pos: &[f64; 2],
+        controller: &BoardController,
+        ctx: &Context,
+    ) -> Option<bool> {
+        if is_nan(pos) {
+            return None;
+        }
+        let cell = self.loose_tile_extents(controller, ctx);
+        if pos < &cell {
+            let [x, _] = cell.center();
+            Some(pos[0] >= x)
+        } else {
+            None
+        }
+    }
+
+    /// Like `in_tile`, for a view drawn with `draw_in_rect` in the given rectangle
+    pub fn in_tile_in_rect(
+        &self,
+        pos: &[f64; 2],
+        controller: &BoardController,
+        ctx: &Context,
+        rect: [f64; 4],
+    ) -> Option<(usize, usize)> {
+        let pos = rect_relative(pos, rect);
+        self.within_rect(rect, || self.in_tile(&pos, controller, ctx))
+    }
+
+    /// Like `in_insert_guide`, for a view drawn with `draw_in_rect` in the given rectangle
+    pub fn in_insert_guide_in_rect(
+        &self,
+        pos: &[f64; 2],
+        controller: &BoardController,
+        ctx: &Context,
+        rect: [f64; 4],
+    ) -> Option<(Direction, usize)> {
+        let pos = rect_relative(pos, rect);
+        self.within_rect(rect, || self.in_insert_guide(&pos, controller, ctx))
+    }
+
+    /// Like `nearest_insert_guide`, for a view drawn with `draw_in_rect` in the given rectangle
+    pub fn nearest_insert_guide_in_rect(
+        &self,
+        pos: &[f64; 2],
+        controller: &BoardController,
+        ctx: &Context,
+        rect: [f64; 4],
+    ) -> Option<(Direction, usize, f64)> {
+        let pos = rect_relative(pos, rect);
+        self.within_rect(rect, || self.nearest_insert_guide(&pos, controller, ctx))
+    }
+
+    /// Like `in_loose_tile`, for a view drawn with `draw_in_rect` in the given rectangle
+    pub fn in_loose_tile_in_rect(
+        &self,
+        pos: &[f64; 2],
+        controller: &BoardController,
+        ctx: &Context,
+        rect: [f64; 4],
+    ) -> bool {
+        let pos = rect_relative(pos, rect);
+        self.within_rect(rect, || self.in_loose_tile(&pos, controller, ctx))
+    }
+
+    /// Like `in_loose_rotate_control`, for a view drawn with `draw_in_rect` in the given rectangle
+    pub fn in_loose_rotate_control_in_rect(
+        &self,
+        pos: &[f64; 2],
+        controller: &BoardController,
+        ctx: &Context,
+        rect: [f64; 4],
+    ) -> Option<bool> {
+        let pos = rect_relative(pos, rect);
+        self.within_rect(rect, || self.in_loose_rotate_control(&pos, controller, ctx))
+    }
+
+    /// Gets how far down the resting loose tile bobs, which it only does while waiting for the
+    /// local player to insert it
+    fn loose_bob_offset(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+    ) -> f64 {
+        let waiting = match controller.turn_state {
+            TurnState::InsertTile => !self.spectator && controller.local_turn(local_id),
+            TurnState::Animating | TurnState::MoveToken => false,
+        };
+        if !self.settings.loose_bob || !waiting {
+            return 0.0;
+        }
+        let settings = &self.settings;
+        anim_state
+            .loose_bob
+            .offset(settings.loose_bob_amplitude, settings.loose_bob_period)
+    }
+
+    /// Checks if the focus ring should be drawn, only while the local player is moving their token
+    fn focus_ring_visible(&self, controller: &BoardController, local_id: PlayerID) -> bool {
+        if !self.settings.show_focus_ring || !controller.local_turn(local_id) {
+            return false;
+        }
+        match controller.turn_state {
+            TurnState::MoveToken => true,
+            TurnState::InsertTile | TurnState::Animating => false,
+        }
+    }
+
+    /// Radius of a token on a tile of the given size, kept within the tile
+    fn token_radius(&self, cell_size: f64) -> f64 {
+        let ratio = self.settings.token_radius_ratio.max(0.0);
+        cell_size * ratio.min(0.5)
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn draw_player_tokens(
         &self,
         mode: DrawMode,
         controller: &BoardController,
         local_id: PlayerID,
+        anim_state: &AnimGlobalState,
         ctx: &Context,
     ) {
-        let settings = &self.settings;
-
         let (cell_size, _, _) = self.tile_padding(controller, ctx);
-        let wall_width = cell_size * settings.wall_width;
-        let anim_state = anim::STATE.read().unwrap();
-        let token_radius = cell_size / 2.0 - wall_width;
+        let full_radius = self.token_radius(cell_size);
+        let focus_id = if self.focus_ring_visible(controller, local_id) {
+            Some(controller.active_player_id())
+        } else {
+            None
+        };
+
+        // find who else is on each token's tile, so tokens that share a tile can make room
+        let mut sharing = BTreeMap::new();
+        for token in controller.board.player_tokens.values() {
+            sharing
+                .entry(token.position)
+                .or_insert_with(Vec::new)
+                .push(token.player_id);
+        }
 
         for token in controller.board.player_tokens.values() {
             let (row, col) = token.position;
+            let tile_mates = &sharing[&token.position];
+            let index = tile_mates
+                .iter()
+                .position(|&id| id == token.player_id)
+                .unwrap_or(0);
+            let ([offset_x, offset_y], scale) = token_layout(index, tile_mates.len());
+            let token_radius = full_radius * scale;
             let player = match controller.players.get(&token.player_id) {
                 Some(x) => x,
                 None => continue,
@@ -724,7 +1880,7 @@ impl BoardView {
             ctx.save();
 
             if anim_state.loose_insert.applies_to_pos((row, col)) {
-                let [x, y] = [0.0, anim_state.loose_insert.distance_left * cell_size]
+                let [x, y] = [0.0, anim_state.loose_insert.offset() * cell_size]
                     * anim_state.loose_insert.offset_dir;
                 ctx.translate(x, y).unwrap_throw();
             };
@@ -734,6 +1890,7 @@ impl BoardView {
                 ctx.begin_path();
                 ctx.set_fill_style(&player.color.into());
                 let [x, y] = tile.center();
+                let (x, y) = (x + offset_x * full_radius, y + offset_y * full_radius);
                 ctx.ellipse(
                     x,
                     y,
@@ -746,7 +1903,7 @@ impl BoardView {
                     .unwrap_throw();
                 ctx.fill();
                 if token.player_id == local_id {
-                    let dot_radius = token_radius - wall_width / 2.0;
+                    let dot_radius = token_radius * Self::TOKEN_DOT_RATIO;
                     ctx.begin_path();
                     ctx.set_fill_style(&JsValue::from_str("black"));
                     ctx.ellipse(
@@ -761,28 +1918,112 @@ impl BoardView {
                         .unwrap_throw();
                     ctx.fill();
                 }
+                if focus_id == Some(token.player_id) {
+                    let ring_radius = token_radius * 1.4;
+                    let dash = ring_radius * PI / 6.0;
+                    let dashes = js_sys::Array::of2(&JsValue::from(dash), &JsValue::from(dash));
+                    ctx.begin_path();
+                    ctx.set_stroke_style(&self.settings.text_color.into());
+                    ctx.set_line_width(2.0);
+                    ctx.set_line_dash(&dashes).unwrap_throw();
+                    ctx.set_line_dash_offset(-anim_state.focus_ring.pct_offset() * dash * 2.0);
+                    ctx.ellipse(x, y, ring_radius, ring_radius, 0.0, 0.0, PI * 2.0)
+                        .unwrap_throw();
+                    ctx.stroke();
+                }
             }
 
             ctx.restore();
         }
     }
 
-    fn draw_ui(&self, controller: &BoardController, local_id: PlayerID, ctx: &Context) {
+    /// Draws word-wrapped tutorial text in a box, starting below the line at the given y
+    fn draw_tutorial_text(&self, text: &str, panel: &Extents, last_y: f64, ctx: &Context) {
+        let font_size = f64::from(self.settings.font_size);
+        let line_height = font_size * 1.2;
+        let padding = font_size * 0.4;
+        let radius = padding;
+
+        ctx.save();
+        ctx.set_font(&format!("{}px sans-serif", self.settings.font_size));
+        let max_width = panel.east - panel.west - 2.0 * padding;
+        let lines = wrap_words(text, max_width, |line| {
+            ctx.measure_text(line).map(|m| m.width()).unwrap_or(0.0)
+        });
+
+        let west = panel.west;
+        let east = panel.east;
+        let north = last_y + padding;
+        let south = north + lines.len() as f64 * line_height + 2.0 * padding;
+        ctx.begin_path();
+        ctx.move_to(west + radius, north);
+        ctx.arc_to(east, north, east, south, radius).unwrap_throw();
+        ctx.arc_to(east, south, west, south, radius).unwrap_throw();
+        ctx.arc_to(west, south, west, north, radius).unwrap_throw();
+        ctx.arc_to(west, north, east, north, radius).unwrap_throw();
+        ctx.close_path();
+        ctx.set_fill_style(&self.settings.reachable_background_color.into());
+        ctx.fill();
+
+        ctx.set_fill_style(&self.settings.text_color.into());
+        ctx.set_text_baseline("top");
+        let mut y = north + padding;
+        for line in &lines {
+            ctx.fill_text(line, west + padding, y).unwrap_throw();
+            y += line_height;
+        }
+        ctx.restore();
+    }
+
+    fn draw_ui(
+        &self,
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
         let (cell_size, _, _) = self.tile_padding(controller, ctx);
-        let anim_state = anim::STATE.read().unwrap();
 
         // draw loose tile
-        {
+        if let Some([x, y]) = controller.loose_drag {
+            // being dragged, so follow the cursor
+            let half = cell_size / 2.0;
+            let cell = Extents {
+                north: y - half,
+                south: y + half,
+                west: x - half,
+                east: x + half,
+            };
+            self.draw_tile(
+                &controller.board.loose_tile,
+                cell,
+                self.settings.background_color,
+                false,
+                true,
+                controller,
+                local_id,
+                anim_state,
+                ctx,
+            );
+        } else {
             let cell = self.loose_tile_extents(controller, ctx);
             ctx.save();
-            if anim_state
-                .loose_insert
-                .applies_to_loose(controller.board.loose_tile_position)
-            {
-                let [x, y] = [0.0, anim_state.loose_insert.distance_left * cell_size]
+            let is_inserting = match controller.board.loose_tile_position {
+                Some((dir, idx)) => {
+                    let line = controller.board.insert_rule.guide_line(idx);
+                    anim_state.loose_insert.applies_to_loose(dir, line)
+                }
+                None => false,
+            };
+            if is_inserting {
+                let [x, y] = [0.0, anim_state.loose_insert.offset() * cell_size]
                     * anim_state.loose_insert.offset_dir;
                 ctx.translate(x, y).unwrap_throw();
             };
+            if !is_inserting {
+                let bob = self.loose_bob_offset(controller, local_id, anim_state);
+                ctx.translate(0.0, bob).unwrap_throw();
+            }
             self.draw_tile(
                 &controller.board.loose_tile,
                 cell,
@@ -791,6 +2032,7 @@ impl BoardView {
                 true,
                 controller,
                 local_id,
+                anim_state,
                 ctx,
             );
             ctx.restore();
@@ -799,30 +2041,67 @@ impl BoardView {
         // draw player target
         {
             let (south_panel, _) = self.ui_extents(ctx);
-            let my_turn = controller.local_turn(local_id);
+            let my_turn = !self.spectator && controller.local_turn(local_id);
             let whose_turn = controller.active_player();
             ctx.save();
 
             ctx.set_fill_style(&self.settings.text_color.into());
             ctx.set_font("20px sans-serif");
-            let text = format!("It is {}'s turn", whose_turn.name);
+            let text = if controller.sandbox {
+                "Sandbox: click any token, then where to move it, or insert at any time".to_string()
+            } else if self.spectator {
+                format!("Spectating: it is {}'s turn", whose_turn.name)
+            } else {
+                format!("It is {}'s turn", whose_turn.name)
+            };
             let x = south_panel.west;
-            let y = south_panel.north + 20.0;
+            let line_height = 25.0;
+            let mut y = south_panel.north + 20.0;
             ctx.fill_text(&text, x, y).unwrap_throw();
             if my_turn {
                 let text = match controller.turn_state {
                     TurnState::InsertTile => {
-                        "Right-click at a triangle to rotate, left-click to insert"
+                        "Right-click a side of the tile to rotate that way, left-click to insert"
                     }
+                    TurnState::Animating => "Inserting...",
                     TurnState::MoveToken => "Click on any reachable tile, or yourself to not move",
                 };
-                let y = y + 30.0;
+                y += line_height;
+                ctx.fill_text(&text, x, y).unwrap_throw();
+                if self.settings.show_shortcuts {
+                    if let Some(hints) = shortcut_hints(&controller.turn_state) {
+                        y += line_height;
+                        ctx.fill_text(hints, x, y).unwrap_throw();
+                    }
+                }
+            }
+            let show_count =
+                self.settings.show_reachable_count && !self.spectator && !self.clean_feed;
+            // an eliminated player has no token left to count from
+            let local_token = controller.board.player_tokens.get(&local_id).filter(|_| show_count);
+            if let Some(token) = local_token {
+                let reachable = controller.reachable_from(token.position);
+                let text = format!("Reachable: {}", reachable.len());
+                y += line_height;
                 ctx.fill_text(&text, x, y).unwrap_throw();
             }
             if let Some(tutorial_step) = &controller.board.tutorial_step {
-                let text = tutorial_step.text();
-                let y = y + 60.0;
-                ctx.fill_text(&text, x, y).unwrap_throw();
+                self.draw_tutorial_text(tutorial_step.text(), &south_panel, y, ctx);
+            }
+
+            // draw a countdown bar along the bottom of the panel
+            if let (Some(limit), Some(deadline)) =
+                (controller.settings.turn_time_limit, controller.turn_deadline)
+            {
+                let left = (deadline - crate::now()).max(0.0);
+                let bar_height = 6.0;
+                let width = south_panel.east - south_panel.west;
+                ctx.fill_rect(
+                    south_panel.west,
+                    south_panel.south - bar_height,
+                    width * (left / limit).min(1.0),
+                    bar_height,
+                );
             }
 
             ctx.restore();
@@ -859,12 +2138,813 @@ impl BoardView {
                     .unwrap_throw();
                 ctx.fill();
                 ctx.set_fill_style(&self.settings.text_color.into());
-                let text = format!("score: {}", token.score);
+                let score = anim_state.score_count.displayed(*player_id, token.score);
+                let text = format!("score: {}", score);
                 ctx.fill_text(&text, x + 20.0, y + 10.0).unwrap_throw();
                 y += 40.0;
             }
 
             ctx.restore();
         }
+
+        if let Some(icons) = self.legend_icons(controller, ctx) {
+            self.draw_legend(icons, controller, local_id, anim_state, ctx);
+        }
+    }
+
+    /// Gets where the guide, target, and token examples of the legend go above the loose tile, if
+    /// the legend is shown
+    fn legend_icons(&self, controller: &BoardController, ctx: &Context) -> Option<[Extents; 3]> {
+        if !self.settings.show_legend {
+            return None;
+        }
+        let (_, east_panel) = self.ui_extents(ctx);
+        let loose_tile = self.loose_tile_resting_extents(controller, ctx);
+        let row_height = 30.0;
+        let icon_size = 24.0;
+        let west = east_panel.west;
+        let north = loose_tile.north - 20.0 - 3.0 * row_height;
+        let icon = |row: f64| {
+            let north = north + row * row_height;
+            Extents {
+                north,
+                south: north + icon_size,
+                west,
+                east: west + icon_size,
+            }
+        };
+        Some([icon(0.0), icon(1.0), icon(2.0)])
+    }
+
+    /// Draws a legend of guides, targets, and tokens at the given example positions
+    fn draw_legend(
+        &self,
+        icons: [Extents; 3],
+        controller: &BoardController,
+        local_id: PlayerID,
+        anim_state: &AnimGlobalState,
+        ctx: &Context,
+    ) {
+        let [guide, target, token] = icons;
+        // the examples are in the local player's colors, or whoever's turn it is for spectators
+        let player = controller
+            .players
+            .get(&local_id)
+            .unwrap_or_else(|| controller.active_player());
+
+        ctx.save();
+        ctx.set_font("15px sans-serif");
+        ctx.set_text_baseline("middle");
+        let label = |text: &str, icon: &Extents| {
+            ctx.set_fill_style(&self.settings.text_color.into());
+            let [_, y] = icon.center();
+            ctx.fill_text(text, icon.east + 10.0, y).unwrap_throw();
+        };
+
+        ctx.set_fill_style(&self.settings.insert_guide_color.into());
+        self.trace_guide_arrow(Direction::North, &guide, ctx);
+        ctx.fill();
+        label("Insert the loose tile here", &guide);
+
+        let tile = Tile {
+            shape: Shape::I,
+            orientation: Direction::North,
+            whose_target: Some(player.id),
+        };
+        let background = self.settings.background_color;
+        self.draw_tile(
+            &tile, target.clone(), background, false, false, controller, local_id, anim_state, ctx,
+        );
+        label("Striped tile: a target", &target);
+
+        let [x, y] = token.center();
+        let radius = (token.east - token.west) / 2.0;
+        let full_circle = PI * 2.0;
+        ctx.begin_path();
+        ctx.set_fill_style(&player.color.into());
+        ctx.ellipse(x, y, radius, radius, 0.0, 0.0, full_circle).unwrap_throw();
+        ctx.fill();
+        let dot_radius = radius * Self::TOKEN_DOT_RATIO;
+        ctx.begin_path();
+        ctx.set_fill_style(&JsValue::from_str("black"));
+        ctx.ellipse(x, y, dot_radius, dot_radius, 0.0, 0.0, full_circle).unwrap_throw();
+        ctx.fill();
+        label("Dotted circle: your token", &token);
+
+        ctx.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use wasm_bindgen::JsCast;
+
+    use super::*;
+    use crate::{Board, Player};
+    use crate::board_controller::BoardSettings;
+
+    /// Stands in for the canvas in tests that only lay things out, which never touch it
+    fn no_context() -> Context {
+        JsValue::NULL.unchecked_into()
+    }
+
+    /// Makes a view laid out on an 800x600 canvas
+    fn view() -> BoardView {
+        let view = BoardView::new(BoardViewSettings::new());
+        view.rect.set(Some([0.0, 0.0, 800.0, 600.0]));
+        view
+    }
+
+    /// Gets the center of an `[x, y, w, h]` rectangle
+    fn center([x, y, w, h]: [f64; 4]) -> [f64; 2] {
+        [x + w / 2.0, y + h / 2.0]
+    }
+
+    /// Starts a game on a random 7x7 board where the given players take turns in the order given
+    fn controller(ids: &[PlayerID]) -> BoardController {
+        let players = ids
+            .iter()
+            .map(|&id| Player::new(format!("Player {}", id), Color(0.5, 0.5, 0.5), id))
+            .collect();
+        let mut result = BoardController::new(BoardSettings::default(), players, ids[0]);
+        result.turn_order = ids.to_vec();
+        result
+    }
+
+    #[test]
+    fn loose_tile_rests_in_east_panel_until_placed() {
+        let (view, ctx) = (view(), no_context());
+        let mut controller = controller(&[1]);
+        controller.board.loose_tile_position = None;
+        let cell = view.loose_tile_extents(&controller, &ctx);
+        let (_, east_panel) = view.ui_extents(&ctx);
+        assert!(cell.west >= east_panel.west && cell.east <= east_panel.east);
+        assert!(cell.north >= east_panel.north && cell.south <= east_panel.south);
+        assert!(view.in_loose_tile(&cell.center(), &controller, &ctx));
+    }
+
+    #[test]
+    fn target_flow_points_at_token() {
+        assert_eq!(flow_towards((2, 2), (2, 5)), [3.0, 0.0]);
+        assert_eq!(flow_towards((4, 1), (0, 1)), [0.0, -4.0]);
+        assert_eq!(flow_towards((1, 3), (2, 0)), [-3.0, 1.0]);
+        // standing on the target means pulsing in place
+        assert_eq!(flow_towards((3, 3), (3, 3)), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn wall_width_is_uniform_or_per_side() {
+        let uniform = WallWidth::from(0.1);
+        for &dir in Direction::all() {
+            assert_eq!(uniform.get(dir), 0.1);
+        }
+        assert_eq!(uniform.max(), 0.1);
+
+        let sides = WallWidth {
+            north: 0.05,
+            south: 0.1,
+            east: 0.2,
+            west: 0.15,
+        };
+        assert_eq!(sides.get(Direction::North), 0.05);
+        assert_eq!(sides.get(Direction::South), 0.1);
+        assert_eq!(sides.get(Direction::East), 0.2);
+        assert_eq!(sides.get(Direction::West), 0.15);
+        assert_eq!(sides.max(), 0.2);
+
+        let mut settings = BoardViewSettings::new();
+        settings.wall_width = 0.08.into();
+        assert_eq!(settings.wall_width.get(Direction::East), 0.08);
+    }
+
+    #[test]
+    fn settings_round_trip_through_json() {
+        let mut settings = BoardViewSettings::new();
+        settings.background_color = Color(0.25, 0.5, 0.75);
+        let (red, black) = (Color(1.0, 0.0, 0.0), Color(0.0, 0.0, 0.0));
+        settings.background_style = BackgroundStyle::Checkerboard(red, black);
+        settings.reachable_style = ReachableStyle::Outline;
+        settings.wall_width = WallWidth {
+            north: 0.05,
+            south: 0.1,
+            east: 0.125,
+            west: 0.2,
+        };
+        settings.board_edge_radius = 3.5;
+        settings.ui_margin_east = 123.25;
+        settings.intro_duration = None;
+        settings.wall_shadow_color = Some(Color(0.1, 0.2, 0.3));
+        settings.max_highlighted_tiles = Some(12);
+
+        let json = settings.to_json();
+        let loaded = BoardViewSettings::from_json(&json).unwrap();
+        assert_eq!(loaded.to_json(), json);
+        assert_eq!(loaded.background_color, settings.background_color);
+        assert_eq!(loaded.wall_width.get(Direction::West), 0.2);
+        assert_eq!(loaded.board_edge_radius, 3.5);
+        assert_eq!(loaded.ui_margin_east, 123.25);
+        assert_eq!(loaded.intro_duration, None);
+
+        // anything left out keeps its default
+        let partial = BoardViewSettings::from_json(r#"{"font_size": 30}"#).unwrap();
+        assert_eq!(partial.font_size, 30);
+        assert_eq!(partial.min_cell_size, BoardViewSettings::new().min_cell_size);
+        assert!(BoardViewSettings::from_json("not json").is_err());
+    }
+
+    #[test]
+    fn stacked_tokens_spread_out_without_overlapping() {
+        assert_eq!(token_layout(0, 1), ([0.0, 0.0], 1.0));
+        for &count in &[2, 3] {
+            let layouts = (0..count).map(|i| token_layout(i, count)).collect::<Vec<_>>();
+            let (first, scale) = layouts[0];
+            // the first token sits straight up, and every one stays inside the full-size token
+            assert!(first[0].abs() < 1e-9 && first[1] < 0.0);
+            assert!(scale < 1.0);
+            for &([x, y], other_scale) in &layouts {
+                assert_eq!(other_scale, scale);
+                assert!((x * x + y * y).sqrt() + scale <= 1.0 + 1e-9);
+            }
+            for (i, &([x1, y1], _)) in layouts.iter().enumerate() {
+                for &([x2, y2], _) in &layouts[i + 1..] {
+                    let distance = ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt();
+                    assert!(distance >= 2.0 * scale - 1e-9);
+                }
+            }
+        }
+        let (two, _) = token_layout(1, 2);
+        assert!(two[0].abs() < 1e-9 && two[1] > 0.0);
+        assert!(token_layout(0, 3).1 < token_layout(0, 2).1);
+    }
+
+    #[test]
+    fn outline_runs_around_a_rectangular_block() {
+        // a 3 by 2 block starting at the board's corner has a perimeter of 10 tile sides
+        let block = (0..2)
+            .flat_map(|row| (0..3).map(move |col| (row, col)))
+            .collect::<HashSet<_>>();
+        let boundary = reachable_boundary(&block);
+        assert_eq!(boundary.len(), 2 * (3 + 2));
+        assert!(boundary.contains(&((0, 0), Direction::North)));
+        assert!(boundary.contains(&((1, 2), Direction::East)));
+        assert!(!boundary.contains(&((0, 1), Direction::South)));
+
+        let single = [(4, 4)].iter().cloned().collect();
+        assert_eq!(reachable_boundary(&single).len(), 4);
+    }
+
+    #[test]
+    fn lines_snap_to_the_middle_of_a_device_pixel() {
+        assert_eq!(snap_to_half_pixel(10.0, 1.0), 10.5);
+        assert_eq!(snap_to_half_pixel(10.2, 1.0), 10.5);
+        assert_eq!(snap_to_half_pixel(10.9, 1.0), 10.5);
+        // at double density a device pixel is half a CSS pixel
+        assert_eq!(snap_to_half_pixel(10.0, 2.0), 10.25);
+        assert_eq!(snap_to_half_pixel(10.6, 2.0), 10.75);
+
+        let mut view = view();
+        view.settings.crisp_lines = false;
+        assert_eq!(view.crisp(10.2), 10.2);
+        view.settings.crisp_lines = true;
+        assert_eq!(view.crisp(10.2), 10.5);
+    }
+
+    #[test]
+    fn token_radius_follows_ratio_within_the_tile() {
+        let mut view = view();
+        view.settings.token_radius_ratio = 0.2;
+        assert!((view.token_radius(50.0) - 10.0).abs() < 1e-9);
+        view.settings.token_radius_ratio = 0.3;
+        assert!((view.token_radius(50.0) - 15.0).abs() < 1e-9);
+        // a token never spills out of its tile or turns inside out
+        view.settings.token_radius_ratio = 0.9;
+        assert_eq!(view.token_radius(50.0), 25.0);
+        view.settings.token_radius_ratio = -1.0;
+        assert_eq!(view.token_radius(50.0), 0.0);
+    }
+
+    #[test]
+    fn wall_bevels_are_lit_and_shaded_from_the_wall_color() {
+        let mut settings = BoardViewSettings::new();
+        settings.wall_color = Color(0.4, 0.2, 0.6);
+        let (highlight, shadow) = settings.wall_bevel_colors();
+        let channels = |Color(r, g, b): Color| [r, g, b];
+        for i in 0..3 {
+            let base = channels(settings.wall_color)[i];
+            assert!(channels(highlight)[i] > base);
+            assert!(channels(shadow)[i] < base);
+        }
+        assert_eq!(highlight, colors::lighten(settings.wall_color, 0.35));
+        assert_eq!(shadow, colors::darken(settings.wall_color, 0.35));
+
+        let gold = Color(0.8, 0.6, 0.2);
+        settings.wall_highlight_color = Some(gold);
+        assert_eq!(settings.wall_bevel_colors(), (gold, shadow));
+    }
+
+    #[test]
+    fn checkerboard_alternates_between_neighbors() {
+        let (even, odd) = (Color(1.0, 1.0, 1.0), Color(0.0, 0.0, 0.0));
+        let style = BackgroundStyle::Checkerboard(even, odd);
+        let fills = style.fills(Color(0.5, 0.5, 0.5));
+        let fill = |pos| fills[style.fill_index(pos)];
+        assert_eq!(fill((0, 0)), even);
+        assert_eq!(fill((0, 1)), odd);
+        assert_eq!(fill((1, 0)), odd);
+        assert_eq!(fill((3, 4)), odd);
+        assert_eq!(fill((3, 5)), even);
+        let flat = BackgroundStyle::Flat;
+        assert_eq!(flat.fills(even)[flat.fill_index((0, 1))], even);
+    }
+
+    #[test]
+    fn layout_is_in_css_pixels_at_any_pixel_ratio() {
+        assert_eq!(css_size((1600, 1200), 2.0), (800.0, 600.0));
+        assert_eq!(css_size((800, 600), 1.0), (800.0, 600.0));
+
+        let ctx = no_context();
+        let controller = controller(&[1]);
+        let standard = view();
+        let mut retina = view();
+        retina.settings.device_pixel_ratio = 2.0;
+        assert_eq!(standard.layout(&controller, &ctx), retina.layout(&controller, &ctx));
+        let tile = retina.tile_rect(&controller, 2, 3, &ctx);
+        assert_eq!(retina.in_tile(&center(tile), &controller, &ctx), Some((2, 3)));
+    }
+
+    #[test]
+    fn guide_rects_cover_every_insert_guide() {
+        let (view, ctx) = (view(), no_context());
+        let controller = controller(&[1]);
+        let board = &controller.board;
+        let rule = board.insert_rule;
+        let expected = 2 * rule.guide_count(board.width()) + 2 * rule.guide_count(board.height());
+        let guides = view.insert_guide_rects(&controller, &ctx);
+        assert_eq!(guides.len(), expected);
+        for &dir in Direction::all() {
+            let count = guides.iter().filter(|&&(d, _, _)| d == dir).count();
+            assert_eq!(count, rule.guide_count(board.width()));
+        }
+        let [x, y, w, h] = view.tile_rect(&controller, 0, 0, &ctx);
+        assert!(x >= 0.0 && y >= 0.0 && w > 0.0 && (w - h).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reachable_shading_is_only_full_on_local_move() {
+        let view = view();
+        let mut controller = controller(&[1, 2]);
+        controller.board.loose_tile_position = None;
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+        let (reachable, dim) = view.reachable_shading(&controller, 1);
+        assert!(dim && reachable.contains(&controller.board.player_pos(1)));
+        let (reachable, _) = view.reachable_shading(&controller, 2);
+        assert!(reachable.is_empty());
+
+        controller.turn_state = TurnState::MoveToken;
+        let (reachable, dim) = view.reachable_shading(&controller, 1);
+        assert!(!dim && reachable.contains(&controller.board.player_pos(1)));
+        let (reachable, _) = view.reachable_shading(&controller, 2);
+        assert!(reachable.is_empty());
+    }
+
+    #[test]
+    fn loose_tile_halves_turn_it_each_way() {
+        let (view, ctx) = (view(), no_context());
+        let mut controller = controller(&[1]);
+        controller.board.loose_tile_position = None;
+        let cell = view.loose_tile_extents(&controller, &ctx);
+        let [x, y] = cell.center();
+        let quarter = (cell.east - cell.west) / 4.0;
+        let control = |pos| view.in_loose_rotate_control(&pos, &controller, &ctx);
+        assert_eq!(control([x + quarter, y]), Some(true));
+        assert_eq!(control([x - quarter, y]), Some(false));
+        assert_eq!(control([x, y - quarter]), Some(true));
+        assert_eq!(control([cell.east + 1.0, y]), None);
+        assert_eq!(control([f64::NAN, y]), None);
+    }
+
+    #[test]
+    fn guide_hit_area_reaches_past_the_triangle() {
+        let (mut view, ctx) = (view(), no_context());
+        let controller = controller(&[1]);
+        let guides = view.insert_guide_rects(&controller, &ctx);
+        let &(_, _, [x, y, w, _]) = guides
+            .iter()
+            .find(|&&(dir, idx, _)| (dir, idx) == (Direction::North, 1))
+            .unwrap();
+        let near_miss = [x + w / 2.0, y - 3.0];
+        view.settings.insert_guide_hit_padding = 0.0;
+        assert_eq!(view.in_insert_guide(&near_miss, &controller, &ctx), None);
+        view.settings.insert_guide_hit_padding = 5.0;
+        let hit = view.in_insert_guide(&near_miss, &controller, &ctx);
+        assert_eq!(hit, Some((Direction::North, 1)));
+        let far_miss = [x + w / 2.0, y - 8.0];
+        assert_eq!(view.in_insert_guide(&far_miss, &controller, &ctx), None);
+    }
+
+    #[test]
+    fn tiny_tiles_count_as_too_large_a_board() {
+        let (mut view, ctx) = (view(), no_context());
+        let controller = controller(&[1]);
+        let (cell_size, _, _) = view.tile_padding(&controller, &ctx);
+        assert!(!view.board_too_large(&controller, &ctx));
+        view.settings.min_cell_size = cell_size;
+        assert!(!view.board_too_large(&controller, &ctx));
+        view.settings.min_cell_size = cell_size + 0.5;
+        assert!(view.board_too_large(&controller, &ctx));
+
+        // the same board squeezed onto a smaller canvas falls below the usual threshold
+        let squeezed = BoardView::new(BoardViewSettings::new());
+        let (width, height) = (squeezed.settings.ui_margin_east, squeezed.settings.ui_margin_south);
+        squeezed.rect.set(Some([0.0, 0.0, width + 20.0, height + 20.0]));
+        assert!(squeezed.board_too_large(&controller, &ctx));
+    }
+
+    #[test]
+    fn mid_insert_state_shifts_only_the_sliding_line() {
+        let view = view();
+        let mut anim_state = AnimGlobalState::new();
+        assert_eq!(view.insert_shift(50.0, (3, 3), &anim_state), None);
+
+        anim_state.apply(anim::AnimSync::Insert(Direction::South, 3));
+        anim_state.advance_by(0.125);
+        assert!(anim_state.loose_insert.distance_left > 0.0);
+        let [x, y] = view.insert_shift(50.0, (2, 3), &anim_state).unwrap();
+        assert_eq!(x, 0.0);
+        assert!((y.abs() - 25.0).abs() < 1e-9);
+        assert_eq!(view.insert_shift(50.0, (5, 3), &anim_state), Some([x, y]));
+        assert_eq!(view.insert_shift(50.0, (3, 2), &anim_state), None);
+
+        anim_state.advance_by(1.0);
+        assert_eq!(view.insert_shift(50.0, (2, 3), &anim_state), None);
+    }
+
+    #[test]
+    fn loose_tile_only_bobs_while_waiting_to_insert() {
+        let mut view = view();
+        let mut controller = controller(&[1, 2]);
+        let mut anim_state = AnimGlobalState::new();
+        anim_state.advance_by(view.settings.loose_bob_period / 4.0);
+        let amplitude = view.settings.loose_bob_amplitude;
+        let bob = view.loose_bob_offset(&controller, 1, &anim_state);
+        assert!((bob - amplitude).abs() < 1e-9);
+        assert_eq!(view.loose_bob_offset(&controller, 2, &anim_state), 0.0);
+
+        controller.turn_state = TurnState::MoveToken;
+        assert_eq!(view.loose_bob_offset(&controller, 1, &anim_state), 0.0);
+        controller.turn_state = TurnState::Animating;
+        assert_eq!(view.loose_bob_offset(&controller, 1, &anim_state), 0.0);
+
+        controller.turn_state = TurnState::InsertTile;
+        view.settings.loose_bob = false;
+        assert_eq!(view.loose_bob_offset(&controller, 1, &anim_state), 0.0);
+    }
+
+    #[test]
+    fn square_corners_fill_the_whole_tile() {
+        let mut view = view();
+        let outer = Extents {
+            north: 20.0,
+            south: 70.0,
+            west: 10.0,
+            east: 60.0,
+        };
+        view.settings.tile_corner_radius = 0.0;
+        assert_eq!(view.square_background(&outer), Some([10.0, 20.0, 50.0, 50.0]));
+        assert_eq!(view.square_background(&outer), Some(outer.rect()));
+        view.settings.tile_corner_radius = 6.0;
+        assert_eq!(view.square_background(&outer), None);
+    }
+
+    #[test]
+    fn board_layer_stays_clear_of_the_ui_panels() {
+        let (view, ctx) = (view(), no_context());
+        let controller = controller(&[1, 2]);
+        let (game, _) = view.game_extents(&controller, &ctx);
+        let (south, east) = view.ui_extents(&ctx);
+        assert!(game.south <= south.north && game.east <= east.west);
+
+        // everything draw_board puts down lands within the game extents
+        let inside = |[x, y, w, h]: [f64; 4]| {
+            x >= game.west && y >= game.north && x + w <= game.east && y + h <= game.south
+        };
+        for (row, col) in controller.board.iter_tiles().map(|(pos, _)| pos) {
+            assert!(inside(view.tile_rect(&controller, row, col, &ctx)));
+        }
+        for (_, _, rect) in view.insert_guide_rects(&controller, &ctx) {
+            assert!(inside(rect));
+        }
+    }
+
+    #[test]
+    fn guide_reversing_last_insert_misses() {
+        let (view, ctx) = (view(), no_context());
+        let mut controller = controller(&[1, 2]);
+        let guides = view.insert_guide_rects(&controller, &ctx);
+        let guide_center = |guide| {
+            let &(_, _, rect) = guides.iter().find(|&&(d, i, _)| (d, i) == guide).unwrap();
+            center(rect)
+        };
+        let reverse = guide_center((Direction::South, 1));
+        let same = guide_center((Direction::North, 1));
+        let hit = view.in_insert_guide(&reverse, &controller, &ctx);
+        assert_eq!(hit, Some((Direction::South, 1)));
+
+        controller.last_insert = Some((Direction::North, 1));
+        assert_eq!(view.in_insert_guide(&reverse, &controller, &ctx), None);
+        assert!(!controller.can_insert_at(Direction::South, 1));
+        let hit = view.in_insert_guide(&same, &controller, &ctx);
+        assert_eq!(hit, Some((Direction::North, 1)));
+    }
+
+    #[test]
+    fn nearest_guide_is_found_from_just_beside_it() {
+        let (view, ctx) = (view(), no_context());
+        let controller = controller(&[1]);
+        let guides = view.insert_guide_rects(&controller, &ctx);
+        let &(_, _, rect) = guides
+            .iter()
+            .find(|&&(dir, idx, _)| (dir, idx) == (Direction::West, 2))
+            .unwrap();
+        let [x, y] = center(rect);
+        let (dir, idx, distance) = view
+            .nearest_insert_guide(&[x - 3.0, y + 4.0], &controller, &ctx)
+            .unwrap();
+        assert_eq!((dir, idx), (Direction::West, 2));
+        assert!((distance - 5.0).abs() < 1e-9);
+        let nan = [f64::NAN, y];
+        assert_eq!(view.nearest_insert_guide(&nan, &controller, &ctx), None);
+    }
+
+    #[test]
+    fn nan_positions_never_hit() {
+        let (view, ctx) = (view(), no_context());
+        let mut controller = controller(&[1]);
+        controller.board.loose_tile_position = None;
+        let [x, y] = center(view.tile_rect(&controller, 3, 3, &ctx));
+        assert_eq!(view.in_tile(&[x, y], &controller, &ctx), Some((3, 3)));
+        for &pos in &[[f64::NAN, y], [x, f64::NAN], [f64::NAN, f64::NAN]] {
+            assert_eq!(view.in_tile(&pos, &controller, &ctx), None);
+            assert_eq!(view.in_insert_guide(&pos, &controller, &ctx), None);
+            assert!(!view.in_loose_tile(&pos, &controller, &ctx));
+        }
+        // NaN compares as neither inside nor outside, rather than as a miss
+        let cell = view.tile_extents(&controller, 3, 3, &ctx);
+        assert_eq!([f64::NAN, y].partial_cmp(&cell), None);
+    }
+
+    #[test]
+    fn reachable_shading_has_three_tiers() {
+        let view = view();
+        let mut controller = controller(&[1, 2]);
+        controller.board.loose_tile_position = None;
+        let tier = |controller: &BoardController, local_id| {
+            let (reachable, dim) = view.reachable_shading(controller, local_id);
+            (!reachable.is_empty(), view.reachable_alpha(dim))
+        };
+        let dim_alpha = view.settings.reachable_dim_alpha;
+        assert_eq!(tier(&controller, 1), (true, dim_alpha));
+        assert_eq!(tier(&controller, 2), (false, 1.0));
+        controller.turn_state = TurnState::MoveToken;
+        assert_eq!(tier(&controller, 1), (true, 1.0));
+        assert_eq!(tier(&controller, 2), (false, 1.0));
+    }
+
+    #[test]
+    fn reachable_sets_past_the_cap_shade_the_whole_board() {
+        let mut view = view();
+        let mut controller = controller(&[1, 2]);
+        controller.board.cells = Board::parse_board(&["───────"; 7].join(" "));
+        controller.turn_state = TurnState::MoveToken;
+        let (reachable, dim) = view.reachable_shading(&controller, 1);
+        assert_eq!(reachable.len(), 7);
+
+        view.settings.max_highlighted_tiles = Some(6);
+        assert!(view.shades_whole_board(reachable.len(), dim));
+        view.settings.max_highlighted_tiles = Some(7);
+        assert!(!view.shades_whole_board(reachable.len(), dim));
+        view.settings.max_highlighted_tiles = None;
+        assert!(!view.shades_whole_board(reachable.len(), dim));
+        // dim shading only covers some tiles, so it never shades the whole board
+        view.settings.max_highlighted_tiles = Some(1);
+        assert!(!view.shades_whole_board(reachable.len(), true));
+    }
+
+    #[test]
+    fn dropping_loose_tile_on_guide_inserts_it() {
+        let _lock = anim::tests::lock_state();
+        let (view, ctx) = (view(), no_context());
+        let mut controller = controller(&[1, 2]);
+        let guides = view.insert_guide_rects(&controller, &ctx);
+        let &(dir, idx, rect) = guides
+            .iter()
+            .find(|&&(dir, idx, _)| (dir, idx) == (Direction::South, 1))
+            .unwrap();
+        controller.begin_loose_drag([700.0, 500.0]);
+        controller.update_loose_drag(center(rect));
+        assert!(controller.end_loose_drag(center(rect), &view, &ctx));
+        assert_eq!(controller.loose_drag, None);
+        assert_eq!(controller.board.loose_tile_position, Some((dir, idx)));
+        assert!(matches!(controller.turn_state, TurnState::Animating));
+    }
+
+    #[test]
+    fn dropping_loose_tile_elsewhere_puts_it_back() {
+        let (view, ctx) = (view(), no_context());
+        let mut controller = controller(&[1, 2]);
+        controller.board.loose_tile_position = None;
+        controller.begin_loose_drag([700.0, 500.0]);
+        controller.update_loose_drag([5.0, 595.0]);
+        assert!(!controller.end_loose_drag([5.0, 595.0], &view, &ctx));
+        assert_eq!(controller.loose_drag, None);
+        assert_eq!(controller.board.loose_tile_position, None);
+        assert!(matches!(controller.turn_state, TurnState::InsertTile));
+        // letting go without having picked the tile up does nothing
+        assert!(!controller.end_loose_drag([5.0, 595.0], &view, &ctx));
+    }
+
+    #[test]
+    fn words_wrap_under_the_width_budget() {
+        let measure = |text: &str| text.chars().count() as f64 * 10.0;
+        let text = "Slide the loose tile in  from any arrow around the board";
+        let lines = wrap_words(text, 160.0, measure);
+        assert_eq!(
+            lines,
+            vec!["Slide the loose", "tile in from any", "arrow around the", "board"]
+        );
+        assert!(lines.iter().all(|line| measure(line) <= 160.0));
+        assert_eq!(lines.join(" "), text.split_whitespace().collect::<Vec<_>>().join(" "));
+
+        // a word that can't fit anywhere still gets shown, alone
+        let lines = wrap_words("a labyrinthine maze", 60.0, measure);
+        assert_eq!(lines, vec!["a", "labyrinthine", "maze"]);
+        assert!(wrap_words("   ", 60.0, measure).is_empty());
+    }
+
+    #[test]
+    fn clean_feed_shades_nothing_reachable() {
+        let clean = BoardView::new_clean_feed(BoardViewSettings::new());
+        clean.rect.set(Some([0.0, 0.0, 800.0, 600.0]));
+        let mut controller = controller(&[1, 2]);
+        controller.board.loose_tile_position = None;
+        assert!(clean.reachable_shading(&controller, 1).0.is_empty());
+        controller.board.loose_tile_position = Some((Direction::North, 0));
+        assert!(clean.reachable_shading(&controller, 1).0.is_empty());
+        controller.turn_state = TurnState::MoveToken;
+        assert!(clean.reachable_shading(&controller, 1).0.is_empty());
+        // the same moment on the player's own screen does shade
+        assert!(!view().reachable_shading(&controller, 1).0.is_empty());
+    }
+
+    /// Counts the tiles it is asked to draw instead of drawing them
+    struct CountingRenderer(Rc<Cell<usize>>);
+
+    impl TileRenderer for CountingRenderer {
+        fn draw_tile(&self, _: &Tile, _: &Extents, _: &BoardViewSettings, _: &Context) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn tile_walls_go_through_the_renderer() {
+        let (mut view, ctx) = (view(), no_context());
+        let count = Rc::new(Cell::new(0));
+        view.tile_renderer = Box::new(CountingRenderer(count.clone()));
+        let controller = controller(&[1, 2]);
+        let outer = Extents {
+            north: -10.0,
+            south: 10.0,
+            east: 10.0,
+            west: -10.0,
+        };
+        for (_, tile) in controller.board.iter_tiles() {
+            view.draw_tile_walls(tile, &outer, &ctx);
+        }
+        assert_eq!(count.get(), 7 * 7);
+        view.draw_tile_walls(controller.board.loose_tile(), &outer, &ctx);
+        assert_eq!(count.get(), 7 * 7 + 1);
+    }
+
+    #[test]
+    fn shortcut_hints_follow_the_turn_phase() {
+        let insert = shortcut_hints(&TurnState::InsertTile).unwrap();
+        let movement = shortcut_hints(&TurnState::MoveToken).unwrap();
+        assert_ne!(insert, movement);
+        assert!(insert.contains("rotate") && !movement.contains("rotate"));
+        // nothing to press while the tile slides
+        assert_eq!(shortcut_hints(&TurnState::Animating), None);
+    }
+
+    #[test]
+    fn layout_board_size_is_whole_cells() {
+        let (view, ctx) = (view(), no_context());
+        let settings = BoardSettings {
+            width: 9,
+            height: 5,
+            ..BoardSettings::default()
+        };
+        let players = vec![Player::new("Player 1".to_string(), Color(0.5, 0.5, 0.5), 1)];
+        let controller = BoardController::new(settings, players, 1);
+        let layout = view.layout(&controller, &ctx);
+        let [w, h] = layout.board_size;
+        assert!((w - layout.cell_size * 9.0).abs() < 1e-9);
+        assert!((h - layout.cell_size * 5.0).abs() < 1e-9);
+        let [x, y] = layout.board_origin;
+        assert!(x >= 0.0 && y >= 0.0 && x + w <= layout.east_panel[0]);
+        assert!(y + h <= layout.south_panel[1]);
+    }
+
+    #[test]
+    fn legend_only_shows_when_turned_on() {
+        let (mut view, ctx) = (view(), no_context());
+        let mut controller = controller(&[1]);
+        controller.board.loose_tile_position = None;
+        assert!(view.legend_icons(&controller, &ctx).is_none());
+
+        view.settings.show_legend = true;
+        let icons = view.legend_icons(&controller, &ctx).unwrap();
+        let (_, east_panel) = view.ui_extents(&ctx);
+        let loose_tile = view.loose_tile_extents(&controller, &ctx);
+        for pair in icons.windows(2) {
+            assert!(pair[0].south <= pair[1].north);
+        }
+        // stacked in the east panel, clear of the loose tile below
+        assert!(icons.iter().all(|icon| icon.west >= east_panel.west && icon.north >= 0.0));
+        assert!(icons[2].south <= loose_tile.north);
+    }
+
+    #[test]
+    fn focus_ring_only_while_the_local_player_moves() {
+        let mut view = view();
+        view.settings.show_focus_ring = true;
+        let mut controller = controller(&[1, 2]);
+        assert!(!view.focus_ring_visible(&controller, 1));
+        controller.turn_state = TurnState::Animating;
+        assert!(!view.focus_ring_visible(&controller, 1));
+
+        controller.turn_state = TurnState::MoveToken;
+        assert!(view.focus_ring_visible(&controller, 1));
+        assert!(!view.focus_ring_visible(&controller, 2));
+        view.settings.show_focus_ring = false;
+        assert!(!view.focus_ring_visible(&controller, 1));
+    }
+
+    #[test]
+    fn loose_tile_fits_the_east_panel_at_any_board_size() {
+        let (mut view, ctx) = (view(), no_context());
+        let (_, east_panel) = view.ui_extents(&ctx);
+        let inside = |cell: &Extents| {
+            cell.west >= east_panel.west
+                && cell.east <= east_panel.east
+                && cell.north >= east_panel.north
+                && cell.south <= east_panel.south
+        };
+        for &(size, scale) in &[(3, 1.0), (7, 1.0), (25, 1.0), (3, 4.0)] {
+            view.settings.loose_tile_scale = scale;
+            let settings = BoardSettings {
+                width: size,
+                height: size,
+                ..BoardSettings::default()
+            };
+            let players = vec![Player::new("Player 1".to_string(), Color(0.5, 0.5, 0.5), 1)];
+            let mut controller = BoardController::new(settings, players, 1);
+            controller.board.loose_tile_position = None;
+            let cell = view.loose_tile_extents(&controller, &ctx);
+            assert!(inside(&cell), "{}x{} board at scale {}", size, size, scale);
+            // even where tiles are tiny, the loose tile is big enough to grab
+            assert!(cell.east - cell.west >= BoardView::MIN_LOOSE_TILE_SIZE);
+        }
+    }
+
+    #[test]
+    fn sub_rect_extents_stay_inside_it() {
+        let (view, ctx) = (view(), no_context());
+        let mut controller = controller(&[1]);
+        controller.board.loose_tile_position = None;
+        let rect = [100.0, 50.0, 400.0, 300.0];
+        let [x, y, w, h] = rect;
+        let inside = |e: &Extents| e.west >= 0.0 && e.north >= 0.0 && e.east <= w && e.south <= h;
+        let (game, board, south_panel, east_panel, loose_tile, corner) = view.within_rect(rect, || {
+            let (game, board) = view.game_extents(&controller, &ctx);
+            let (south_panel, east_panel) = view.ui_extents(&ctx);
+            let loose_tile = view.loose_tile_extents(&controller, &ctx);
+            let corner = view.tile_extents(&controller, 6, 6, &ctx);
+            (game, board, south_panel, east_panel, loose_tile, corner)
+        });
+        for extents in &[game, board, south_panel, east_panel, loose_tile.clone()] {
+            assert!(inside(extents), "{:?} leaves the rect", extents);
+        }
+        // the view goes back to laying out the whole canvas
+        assert_eq!(view.rect.get(), Some([0.0, 0.0, 800.0, 600.0]));
+
+        // hit tests take canvas positions, and find what was drawn there
+        let [cx, cy] = corner.center();
+        let hit = view.in_tile_in_rect(&[x + cx, y + cy], &controller, &ctx, rect);
+        assert_eq!(hit, Some((6, 6)));
+        let [lx, ly] = loose_tile.center();
+        assert!(view.in_loose_tile_in_rect(&[x + lx, y + ly], &controller, &ctx, rect));
+        assert_eq!(view.in_tile_in_rect(&[x / 2.0, y / 2.0], &controller, &ctx, rect), None);
     }
 }
@@ -5,13 +5,94 @@ use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::JsValue;
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub struct Color(pub f32, pub f32, pub f32);
 
 impl Color {
     pub fn hex(&self) -> String {
         format!("#{:02x}{:02x}{:02x}", (self.0 * 255.0) as u8, (self.1 * 255.0) as u8, (self.2 * 255.0) as u8)
     }
+
+    /// Builds a color from hue in turns (0 to 1), saturation, and value
+    pub fn from_hsv(h: f32, s: f32, v: f32) -> Color {
+        let h = (h.fract() + 1.0).fract() * 6.0;
+        let c = v * s;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u8 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = v - c;
+        Color(r + m, g + m, b + m)
+    }
+
+    /// Gets the relative luminance as defined by WCAG
+    fn luminance(&self) -> f32 {
+        let channel = |c: f32| {
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * channel(self.0) + 0.7152 * channel(self.1) + 0.0722 * channel(self.2)
+    }
+}
+
+/// Most players that can be given clearly different colors
+pub const MAX_DISTINCT: usize = 8;
+
+/// Gets the WCAG contrast ratio between two colors, from 1 (identical) to 21 (black and white)
+pub fn contrast_ratio(a: Color, b: Color) -> f64 {
+    let (la, lb) = (a.luminance() as f64, b.luminance() as f64);
+    let (lighter, darker) = if la > lb { (la, lb) } else { (lb, la) };
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+/// Gets the straight-line distance between two colors in RGB, from 0 to about 1.73
+fn rgb_distance(a: Color, b: Color) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt()
+}
+
+/// Checks if two colors are too close together to tell tokens and stripes apart
+///
+/// Colors far enough apart in RGB are told apart by hue even at about the same brightness, so
+/// only colors close in both hue and brightness clash.
+pub fn indistinguishable(a: Color, b: Color) -> bool {
+    rgb_distance(a, b) < 0.25 && contrast_ratio(a, b) < 1.5
+}
+
+/// Blends from `a` (at 0) to `b` (at 1)
+pub fn lerp(a: Color, b: Color, t: f64) -> Color {
+    let t = t as f32;
+    // weighting both ends lands exactly on each of them, which stepping from `a` doesn't
+    let mix = |x: f32, y: f32| x * (1.0 - t) + y * t;
+    Color(mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+}
+
+/// Mixes a color toward white by the given fraction
+pub fn lighten(c: Color, amount: f64) -> Color {
+    lerp(c, Color(1.0, 1.0, 1.0), amount)
+}
+
+/// Mixes a color toward black by the given fraction
+pub fn darken(c: Color, amount: f64) -> Color {
+    lerp(c, Color(0.0, 0.0, 0.0), amount)
+}
+
+/// Gets up to `MAX_DISTINCT` colors with evenly spaced hues, alternating brightness for extra contrast
+pub fn assign_distinct(n: usize) -> Vec<Color> {
+    let n = n.min(MAX_DISTINCT);
+    (0..n)
+        .map(|i| {
+            let value = if i % 2 == 0 { 0.9 } else { 0.6 };
+            Color::from_hsv(i as f32 / n as f32, 0.7, value)
+        })
+        .collect()
 }
 
 impl Into<JsValue> for Color {
@@ -49,3 +130,145 @@ pub const LIGHT: Color = color!(0x82, 0xAE, 0xB1);
 pub const PURPLE: Color = color!(0x5F, 0x5A, 0xA2);
 pub const BLUE: Color = color!(0x35, 0x56, 0x91);
 pub const TEAL: Color = color!(0x66, 0x85, 0x86);
+pub const GOLD: Color = color!(0xD9, 0xA4, 0x41);
+
+/// Which set of colors to draw the game with
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteKind {
+    /// The original teal and purple look
+    Default,
+    /// Colors from the Okabe-Ito set, which stay apart for the common kinds of color blindness
+    ColorblindSafe,
+}
+
+/// Full set of named colors for drawing the game
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    /// Board background
+    pub background: Color,
+    /// Highlights such as reachable tiles
+    pub light: Color,
+    /// Text, borders, and edges
+    pub dark: Color,
+    /// Tile walls
+    pub wall: Color,
+    /// Insert guides and previews
+    pub accent: Color,
+    /// Treasures and other markers
+    pub treasure: Color,
+    /// Colors to give players, in the order they are handed out
+    pub players: Vec<Color>,
+}
+
+/// Gets colors for `n` players from the given palette, or `None` if it doesn't have that many
+/// different ones
+pub fn assign_from_palette(kind: PaletteKind, n: usize) -> Option<Vec<Color>> {
+    let colors = match kind {
+        // hues spread out better when spaced for exactly the players in the game
+        PaletteKind::Default => assign_distinct(n),
+        PaletteKind::ColorblindSafe => palette(kind).players.into_iter().take(n).collect(),
+    };
+    if colors.len() < n {
+        None
+    } else {
+        Some(colors)
+    }
+}
+
+/// Gets the full set of colors for the given palette
+pub fn palette(kind: PaletteKind) -> Palette {
+    match kind {
+        PaletteKind::Default => Palette {
+            background: TEAL,
+            light: LIGHT,
+            dark: DARK,
+            wall: BLUE,
+            accent: PURPLE,
+            treasure: GOLD,
+            players: assign_distinct(MAX_DISTINCT),
+        },
+        PaletteKind::ColorblindSafe => Palette {
+            background: color!(0x99, 0x99, 0x99),
+            light: color!(0xF0, 0xE4, 0x42),
+            dark: color!(0x1F, 0x1F, 0x1F),
+            wall: color!(0x00, 0x72, 0xB2),
+            accent: color!(0xCC, 0x79, 0xA7),
+            treasure: color!(0xE6, 0x9F, 0x00),
+            // the rest of the set is taken by the board, or looks like its gray to deuteranopes
+            players: vec![
+                color!(0x56, 0xB4, 0xE9),
+                color!(0x00, 0x9E, 0x73),
+                color!(0xD5, 0x5E, 0x00),
+            ],
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assigned_colors_are_pairwise_distinguishable() {
+        let colors = assign_distinct(4);
+        assert_eq!(colors.len(), 4);
+        // evenly spaced hues don't always differ in brightness (the bright red and the dim
+        // yellow-green are only about 1.2:1), so a pair short of the contrast threshold has to
+        // make up for it with hues far apart
+        for (i, &a) in colors.iter().enumerate() {
+            for &b in &colors[i + 1..] {
+                let (contrast, distance) = (contrast_ratio(a, b), rgb_distance(a, b));
+                let clash = format!("{:?} and {:?} clash ({}, {})", a, b, contrast, distance);
+                assert!(contrast >= 1.5 || distance >= 0.5, "{}", clash);
+            }
+        }
+        assert_eq!(assign_distinct(20).len(), MAX_DISTINCT);
+    }
+
+    #[test]
+    fn lerp_runs_between_its_ends() {
+        let a = Color(0.2, 0.4, 0.6);
+        let b = Color(0.8, 0.1, 0.0);
+        assert_eq!(lerp(a, b, 0.0), a);
+        assert_eq!(lerp(a, b, 1.0), b);
+        let middle = lerp(a, b, 0.5);
+        assert!((middle.0 - 0.5).abs() < 1e-6 && (middle.2 - 0.3).abs() < 1e-6);
+
+        assert!(lighten(a, 0.3).luminance() > a.luminance());
+        assert!(darken(a, 0.3).luminance() < a.luminance());
+        assert_eq!(lighten(a, 1.0), Color(1.0, 1.0, 1.0));
+    }
+
+    /// Approximates how a color looks with deuteranopia, by the Machado et al. matrix
+    fn deuteranopia(c: Color) -> Color {
+        let row = |r: f32, g: f32, b: f32| r * c.0 + g * c.1 + b * c.2;
+        Color(
+            row(0.367_322, 0.860_646, -0.227_968),
+            row(0.280_085, 0.672_501, 0.047_413),
+            row(-0.011_820, 0.042_940, 0.968_881),
+        )
+    }
+
+    #[test]
+    fn colorblind_safe_players_stay_apart_for_deuteranopes() {
+        // an orange and an olive anyone else tells apart run together
+        let (orange, olive) = (Color(0.8, 0.3, 0.1), Color(0.45, 0.5, 0.1));
+        assert!(rgb_distance(orange, olive) > 0.4);
+        assert!(rgb_distance(deuteranopia(orange), deuteranopia(olive)) < 0.1);
+
+        let safe = palette(PaletteKind::ColorblindSafe);
+        let board = [safe.background, safe.light, safe.dark, safe.wall, safe.accent, safe.treasure];
+        let players = safe.players;
+        for (i, &a) in players.iter().enumerate() {
+            for &b in players[i + 1..].iter().chain(&board) {
+                let seen = rgb_distance(deuteranopia(a), deuteranopia(b));
+                assert!(seen > 0.2, "{:?} and {:?} look alike ({})", a, b, seen);
+            }
+        }
+
+        let kind = PaletteKind::ColorblindSafe;
+        assert_eq!(assign_from_palette(kind, 2), Some(players[..2].to_vec()));
+        assert_eq!(assign_from_palette(kind, players.len()), Some(players.clone()));
+        assert_eq!(assign_from_palette(kind, players.len() + 1), None);
+    }
+}
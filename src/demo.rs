@@ -1,9 +1,9 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryInto;
 use std::sync::{Arc, RwLock};
 
 use crate::{Board, Direction, Player, PlayerID};
-use crate::board::PlayerToken;
+use crate::board::{InsertRule, PlayerToken, Topology};
 use crate::board_controller::{BoardController, BoardSettings};
 use crate::colors;
 use crate::menu::{ConnectedState, GameState, NetGameState};
@@ -24,6 +24,10 @@ pub fn new_controller() -> GameController {
     let player_id = 1;
     let settings = BoardSettings {
         score_limit: 3,
+        treasure_count: 0,
+        turn_time_limit: None,
+        insert_rule: InsertRule::OddLinesOnly,
+        palette: colors::PaletteKind::Default,
         width: 0,
         height: 0,
         version: 0,
@@ -53,20 +57,18 @@ pub fn new_controller() -> GameController {
             player_id,
         ),
     ];
-    let board = BoardController::new(settings, players, player_id);
-    let state = NetGameState::Active(board);
+    let board = BoardController::new_demo(settings, players, player_id);
+    let state = NetGameState::Active(Box::new(board));
     let state = Arc::new(RwLock::new(state));
     let sender = net::NetHandler::run_fake();
     let state = ConnectedState { sender, state };
     let state = GameState::InGame(state);
     let view = crate::GameView {
-        board_view: crate::BoardView {
-            settings: crate::BoardViewSettings {
-                ui_margin_south: 0.0,
-                ui_margin_east: 0.0,
-                ..Default::default()
-            }
-        }
+        board_view: crate::BoardView::new(crate::BoardViewSettings {
+            ui_margin_south: 0.0,
+            ui_margin_east: 0.0,
+            ..Default::default()
+        }),
     };
     GameController {
         state,
@@ -76,6 +78,7 @@ pub fn new_controller() -> GameController {
         sound_engine: Default::default(),
         actions: Default::default(),
         listeners: vec![],
+        last_heartbeat: 0.0,
     }
 }
 
@@ -124,8 +127,18 @@ pub fn new_board(players: &BTreeMap<PlayerID, Player>) -> Board {
     Board {
         cells,
         loose_tile,
-        loose_tile_position,
+        loose_tile_position: Some(loose_tile_position),
         player_tokens,
+        treasures: HashSet::new(),
+        insert_rule: InsertRule::OddLinesOnly,
+        fixed_tiles: (0..height)
+            .step_by(2)
+            .flat_map(|row| (0..width).step_by(2).map(move |col| (row, col)))
+            .collect(),
+        allowed_insert_directions: Direction::all().iter().cloned().collect(),
+        max_move_tiles: None,
+        topology: Topology::Bounded,
+        last_changed_cells: vec![],
         tutorial_step: None,
     }
 }
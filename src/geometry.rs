@@ -0,0 +1,227 @@
+//! Plain geometry for laying out the board, independent of any canvas
+
+use std::cmp;
+use std::ops;
+
+/// Line segment with a slope of 1, running from lower left to upper right
+#[derive(Clone, Debug)]
+pub struct Diagonal {
+    /// Lower left end (x, y)
+    pub ll: [f64; 2],
+    /// Upper right end (x, y)
+    pub ur: [f64; 2],
+}
+
+impl ops::Add<f64> for Diagonal {
+    type Output = Diagonal;
+
+    fn add(self, rhs: f64) -> Self::Output {
+        Diagonal {
+            ll: [self.ll[0] + rhs, self.ll[1] + rhs],
+            ur: [self.ur[0] + rhs, self.ur[1] + rhs],
+        }
+    }
+}
+
+impl ops::Sub<f64> for Diagonal {
+    type Output = Diagonal;
+
+    fn sub(self, rhs: f64) -> Self::Output {
+        self + (-rhs)
+    }
+}
+
+/// Axis-aligned rectangle, with y increasing to the south
+#[derive(Clone, Debug)]
+pub struct Extents {
+    /// Smallest y
+    pub north: f64,
+    /// Largest y
+    pub south: f64,
+    /// Largest x
+    pub east: f64,
+    /// Smallest x
+    pub west: f64,
+}
+
+impl Extents {
+    /// Gets the (x, y) center
+    pub fn center(&self) -> [f64; 2] {
+        [
+            (self.west + self.east) / 2.0,
+            (self.north + self.south) / 2.0,
+        ]
+    }
+
+    /// Converts to `[x, y, w, h]` for use outside the canvas
+    pub fn rect(&self) -> [f64; 4] {
+        [
+            self.west,
+            self.north,
+            self.east - self.west,
+            self.south - self.north,
+        ]
+    }
+
+    /// Gets the diagonal from the southwest corner to the northeast corner
+    pub fn diagonal(&self) -> Diagonal {
+        Diagonal {
+            ll: [self.west, self.south],
+            ur: [self.east, self.north],
+        }
+    }
+
+    /// Cuts a diagonal with the same slope down to the part inside these extents
+    ///
+    /// A line missing the extents entirely shrinks to whichever corner it passes closest to.
+    pub fn clamp_diagonal(&self, line: Diagonal) -> Diagonal {
+        // find equation of line as x + y = k (works for either point since slope assumed to be 1)
+        let ll = line.ll;
+        let k = ll[0] + ll[1];
+        // if k < west + north then too small so use northwest corner
+        let (ll, ur) = if k < self.west + self.north {
+            let point = [self.west, self.north];
+            (point, point)
+        } else if k > self.east + self.south {
+            // if k > east + south then too big so use southwest corner
+            let point = [self.east, self.south];
+            (point, point)
+        } else if k < self.north + self.east {
+            // if less than halfway, before main diagonal, so trust north and west already
+            let y_at_west = k - self.west;
+            let x_at_north = k - self.north;
+            ([self.west, y_at_west], [x_at_north, self.north])
+        } else {
+            // if more than halfway, after main diagonal, so trust south and east already
+            let y_at_east = k - self.east;
+            let x_at_south = k - self.south;
+            ([x_at_south, self.south], [self.east, y_at_east])
+        };
+        Diagonal { ll, ur }
+    }
+}
+
+impl ops::Sub<f64> for Extents {
+    type Output = Extents;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn sub(self, rhs: f64) -> Extents {
+        Extents {
+            north: self.north + rhs,
+            south: self.south - rhs,
+            east: self.east - rhs,
+            west: self.west + rhs,
+        }
+    }
+}
+
+impl ops::Sub<[f64; 2]> for Extents {
+    type Output = Extents;
+
+    fn sub(self, rhs: [f64; 2]) -> Self::Output {
+        let [x, y] = rhs;
+        Extents {
+            north: self.north - y,
+            south: self.south - y,
+            east: self.east - x,
+            west: self.west - x,
+        }
+    }
+}
+
+impl PartialEq<Extents> for [f64; 2] {
+    fn eq(&self, other: &Extents) -> bool {
+        self.partial_cmp(other) == Some(cmp::Ordering::Equal)
+    }
+}
+
+impl PartialOrd<Extents> for [f64; 2] {
+    fn partial_cmp(&self, other: &Extents) -> Option<cmp::Ordering> {
+        use std::cmp::Ordering::*;
+        let [x, y] = self;
+        let result = match (
+            x.partial_cmp(&other.west),
+            x.partial_cmp(&other.east),
+            y.partial_cmp(&other.north),
+            y.partial_cmp(&other.south),
+        ) {
+            // too far west
+            (Some(Less), _, _, _) => Greater,
+            // too far east
+            (_, Some(Greater), _, _) => Greater,
+            // too far north
+            (_, _, Some(Less), _) => Greater,
+            // too far south
+            (_, _, _, Some(Greater)) => Greater,
+            // entirely within
+            (Some(Greater), Some(Less), Some(Greater), Some(Less)) => Less,
+            // on west edge
+            (Some(Equal), _, _, _) => Equal,
+            // on east edge
+            (_, Some(Equal), _, _) => Equal,
+            // on north edge
+            (_, _, Some(Equal), _) => Equal,
+            // on south edge
+            (_, _, _, Some(Equal)) => Equal,
+            // this really shouldn't be possible, and the rust compiler warns about an unreachable pattern!
+            // thanks, rust!
+            // (Some(_), Some(_), Some(_), Some(_)) => panic!("Implausible bounds check for point in extents"),
+            // something is NaN or otherwise fucky
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets the extents from (10, 20) to (30, 40)
+    fn square() -> Extents {
+        Extents {
+            north: 20.0,
+            south: 40.0,
+            east: 30.0,
+            west: 10.0,
+        }
+    }
+
+    /// Gets a diagonal along x + y = k, far longer than the square
+    fn line(k: f64) -> Diagonal {
+        Diagonal {
+            ll: [-100.0, k + 100.0],
+            ur: [k + 100.0, -100.0],
+        }
+    }
+
+    /// Clamps `line(k)` to `square()`, as its (lower left, upper right) ends
+    fn clamped(k: f64) -> ([f64; 2], [f64; 2]) {
+        let result = square().clamp_diagonal(line(k));
+        (result.ll, result.ur)
+    }
+
+    #[test]
+    fn diagonal_before_extents_shrinks_to_northwest_corner() {
+        assert_eq!(clamped(25.0), ([10.0, 20.0], [10.0, 20.0]));
+    }
+
+    #[test]
+    fn diagonal_past_extents_shrinks_to_southeast_corner() {
+        assert_eq!(clamped(75.0), ([30.0, 40.0], [30.0, 40.0]));
+    }
+
+    #[test]
+    fn diagonal_before_main_diagonal_is_cut_by_north_and_west() {
+        assert_eq!(clamped(40.0), ([10.0, 30.0], [20.0, 20.0]));
+    }
+
+    #[test]
+    fn diagonal_after_main_diagonal_is_cut_by_south_and_east() {
+        assert_eq!(clamped(60.0), ([20.0, 40.0], [30.0, 30.0]));
+        // the main diagonal itself runs corner to corner
+        assert_eq!(clamped(50.0), ([10.0, 40.0], [30.0, 20.0]));
+        let main = square().clamp_diagonal(square().diagonal());
+        assert_eq!((main.ll, main.ur), ([10.0, 40.0], [30.0, 20.0]));
+    }
+}
@@ -13,20 +13,31 @@ use gloo::events::{EventListener, EventListenerOptions};
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
 
-pub use crate::board::Board;
-pub use crate::board_controller::{BoardController, BoardSettings};
-pub use crate::board_view::{BoardView, BoardViewSettings};
+pub use crate::ai::{AiDifficulty, best_turn};
+pub use crate::board::{
+    Board, BoardDiff, DecodeError, FairnessReport, InsertRule, LayoutError, Topology,
+};
+pub use crate::board_controller::{
+    BoardController, BoardEvent, BoardSettings, GameResult, LegalTurn,
+};
+pub use crate::board_view::{
+    BackgroundStyle, BoardLayout, BoardView, BoardViewSettings, ReachableStyle, WallWidth,
+};
+pub use crate::geometry::{Diagonal, Extents};
 pub use crate::menu_controller::GameController;
 pub use crate::menu_view::GameView;
 pub use crate::player::{Player, PlayerID};
 pub use crate::tile::{Direction, Shape, Tile};
+pub use crate::tile_renderer::{DefaultTileRenderer, TileRenderer};
 
+mod ai;
 mod anim;
 mod board;
 mod board_controller;
 mod board_view;
 mod colors;
 mod demo;
+mod geometry;
 mod menu;
 mod menu_controller;
 mod menu_view;
@@ -36,6 +47,7 @@ mod options;
 mod player;
 mod sound;
 mod tile;
+mod tile_renderer;
 mod tutorial;
 
 /// Logs some text
@@ -85,6 +97,30 @@ fn main() {
         contextmenu_listener.forget();
     }
 
+    {
+        let game_controller = game_controller.clone();
+        let main2 = main.clone();
+        let mousedown_listener = EventListener::new(&main, "mousedown", move |event| {
+            let event = event
+                .dyn_ref::<web_sys::MouseEvent>()
+                .expect_throw("bad mousedown event");
+            game_controller.lock().unwrap().on_mousedown(event, &main2);
+        });
+        mousedown_listener.forget();
+    }
+
+    {
+        let game_controller = game_controller.clone();
+        let main2 = main.clone();
+        let mouseup_listener = EventListener::new(&main, "mouseup", move |event| {
+            let event = event
+                .dyn_ref::<web_sys::MouseEvent>()
+                .expect_throw("bad mouseup event");
+            game_controller.lock().unwrap().on_mouseup(event, &main2);
+        });
+        mouseup_listener.forget();
+    }
+
     {
         let game_controller = game_controller.clone();
         let main2 = main.clone();
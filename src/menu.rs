@@ -91,7 +91,7 @@ pub enum NetGameState {
     /// Waiting for players to connect
     Lobby(LobbyInfo),
     /// In game
-    Active(BoardController),
+    Active(Box<BoardController>),
     /// After game
     GameOver(GameOverInfo),
     /// An error occurred
@@ -8,8 +8,9 @@ use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
 use web_sys::CanvasRenderingContext2d as Context;
 
-use crate::{BoardController, GameView, Player, PlayerID};
+use crate::{Board, BoardController, BoardEvent, GameView, Player, PlayerID};
 use crate::anim;
+use crate::board_controller::TurnState;
 use crate::colors::Color;
 use crate::demo;
 use crate::menu::{ConnectedState, GameOverInfo, GameState, LobbyInfo, NetGameState};
@@ -28,6 +29,35 @@ fn get_context(main: &web_sys::Element) -> Option<Context> {
     Some(ctx.clone())
 }
 
+/// Checks if the active player just changed over to the local player, which is announced once
+fn turn_began(old_active: Option<PlayerID>, active: Option<PlayerID>, local: PlayerID) -> bool {
+    old_active != active && active == Some(local)
+}
+
+/// Gets the sound for something that happened on the board, panned toward where it happened
+fn event_sound(event: &BoardEvent, board: &Board) -> Option<(sound::Sound, f32)> {
+    let (sound, col) = match *event {
+        BoardEvent::TileInserted { dir, idx } => {
+            // the tile lands where an insert from the other side would push one off
+            let (_, col) = board.ejected_pos(dir.opposite(), idx);
+            (sound::Sound::Insert, col)
+        }
+        BoardEvent::TokenMoved { to: (_, col), .. } => (sound::Sound::Move, col),
+        BoardEvent::TargetCollected(player_id) => {
+            let (_, col) = board.player_pos(player_id);
+            (sound::Sound::Pickup, col)
+        }
+        _ => return None,
+    };
+    Some((sound, sound::column_pan(col, board.width())))
+}
+
+/// Checks if a player with the given score is one target away from winning, which a game won by
+/// the first target never is
+fn one_target_from_winning(score: u8, score_limit: u8) -> bool {
+    score_limit > 1 && score >= score_limit - 1
+}
+
 type DeferredAction = Box<dyn FnOnce(&mut GameController)>;
 
 /// Handles events for DynaMaze game
@@ -46,6 +76,8 @@ pub struct GameController {
     pub actions: Arc<Mutex<Vec<DeferredAction>>>,
     /// DOM event listeners
     pub listeners: Vec<EventListener>,
+    /// Timestamp (in seconds) the host was last told this client is still connected
+    pub last_heartbeat: f64,
 }
 
 impl GameController {
@@ -57,6 +89,7 @@ impl GameController {
         let player_id = random();
         let sound_engine = SoundEngine::new();
         sound_engine.play_music(sound::Music::Menu);
+        anim::set_speed_multiplier(options::HANDLE.fetch().anim_speed_multiplier());
         GameController {
             state: GameState::MainMenu,
             player_id,
@@ -65,6 +98,7 @@ impl GameController {
             sound_engine,
             actions: Default::default(),
             listeners: vec![],
+            last_heartbeat: 0.0,
         }
     }
 
@@ -155,6 +189,40 @@ impl GameController {
         }
     }
 
+    fn set_treasure_count(&mut self, treasure_count: web_sys::HtmlInputElement) {
+        if let GameState::InGame(ref mut conn_state) = self.state {
+            let sender = &mut conn_state.sender;
+            let state = &mut conn_state.state;
+            let mut state = state.write().expect("Failed to lock state");
+            if let NetGameState::Lobby(ref mut info) = *state {
+                let settings = &mut info.settings;
+                settings.treasure_count = treasure_count.value().parse().unwrap_throw();
+                settings.version += 1;
+                treasure_count.form().unwrap_throw().dataset().set("version", &format!("{}", settings.version)).unwrap_throw();
+                let message = Message::EditSettings(settings.clone());
+                sender.send(message);
+            }
+        }
+    }
+
+    fn set_turn_time_limit(&mut self, turn_time_limit: web_sys::HtmlInputElement) {
+        if let GameState::InGame(ref mut conn_state) = self.state {
+            let sender = &mut conn_state.sender;
+            let state = &mut conn_state.state;
+            let mut state = state.write().expect("Failed to lock state");
+            if let NetGameState::Lobby(ref mut info) = *state {
+                let settings = &mut info.settings;
+                let seconds: f64 = turn_time_limit.value().parse().unwrap_or(0.0);
+                // zero means no limit
+                settings.turn_time_limit = if seconds > 0.0 { Some(seconds) } else { None };
+                settings.version += 1;
+                turn_time_limit.form().unwrap_throw().dataset().set("version", &format!("{}", settings.version)).unwrap_throw();
+                let message = Message::EditSettings(settings.clone());
+                sender.send(message);
+            }
+        }
+    }
+
     fn set_music_level(&mut self, slider: web_sys::HtmlInputElement) {
         if let GameState::Options(ref mut opts) = self.state {
             let val = slider.value();
@@ -171,6 +239,14 @@ impl GameController {
         }
     }
 
+    fn set_anim_speed(&mut self, slider: web_sys::HtmlInputElement) {
+        if let GameState::Options(ref mut opts) = self.state {
+            let val = slider.value();
+            opts.anim_speed = val.parse().unwrap_throw();
+            anim::set_speed_multiplier(opts.anim_speed_multiplier());
+        }
+    }
+
     fn save_options(&mut self) {
         if let GameState::Options(ref opts) = self.state {
             options::HANDLE.save(opts);
@@ -246,7 +322,7 @@ impl GameController {
                     let players = info.players_cloned();
                     let settings = info.settings.clone();
                     let board_controller = BoardController::new(settings, players, info.host.id);
-                    let net_state = NetGameState::Active(board_controller);
+                    let net_state = NetGameState::Active(Box::new(board_controller));
                     *state = net_state;
                     drop(state);
                     self.broadcast_state();
@@ -257,6 +333,7 @@ impl GameController {
 
     fn main_menu(&mut self) {
         self.sound_engine.fetch_volume();
+        anim::set_speed_multiplier(options::HANDLE.fetch().anim_speed_multiplier());
         self.state = GameState::MainMenu;
     }
 
@@ -279,7 +356,28 @@ impl GameController {
                 match *state {
                     NetGameState::Active(ref board) => {
                         self.last_player = Some(board.active_player_id());
-                        sound::Music::InGame
+                        let score_limit = board.settings.score_limit;
+                        let close_to_winning = board
+                            .board
+                            .player_tokens
+                            .values()
+                            .any(|token| one_target_from_winning(token.score, score_limit));
+                        let mut anim_state = anim::STATE.write().unwrap();
+                        for (&player_id, token) in &board.board.player_tokens {
+                            anim_state.score_count.set_target(player_id, token.score);
+                            if let Some(from) = token.last_position {
+                                anim_state.move_trail.observe(player_id, from, token.position);
+                            }
+                        }
+                        if close_to_winning {
+                            sound::Music::Endgame
+                        } else {
+                            sound::Music::InGame
+                        }
+                    }
+                    NetGameState::GameOver(_) => {
+                        self.last_player = None;
+                        sound::Music::Victory
                     }
                     _ => {
                         self.last_player = None;
@@ -290,8 +388,82 @@ impl GameController {
         };
         self.sound_engine.play_music(music);
 
-        if old_last_player != self.last_player && self.last_player == Some(self.player_id) {
+        // let any pending insert catch up with its animation, and run the turn timer
+        if let GameState::InGame(ref conn_state) = self.state {
+            let mut sounds = vec![];
+            let dirty = {
+                let mut state = conn_state.state.write().unwrap();
+                let is_host = state.is_host(self.player_id);
+                let mut game_over = None;
+                let dirty = match *state {
+                    NetGameState::Active(ref mut board) => {
+                        let mut dirty = false;
+                        if board.local_turn(self.player_id) {
+                            dirty = board.finish_insert();
+                        }
+                        // the host runs everyone's turn timer, so a player who stops responding
+                        // still loses their turn
+                        if is_host && board.tick(crate::now()) {
+                            dirty = true;
+                        }
+                        // the host plays for anyone the computer has taken over
+                        if is_host && board.take_over_disconnected(crate::now()) {
+                            dirty = true;
+                        }
+                        if is_host && board.step_ai().is_some() {
+                            dirty = true;
+                            if let Some(winner) = board.winner() {
+                                game_over = Some(GameOverInfo {
+                                    winner: winner.clone(),
+                                    host_id: board.host_id,
+                                });
+                            }
+                        }
+                        sounds = board
+                            .take_events()
+                            .iter()
+                            .filter_map(|event| event_sound(event, &board.board))
+                            .collect();
+                        dirty
+                    }
+                    _ => false,
+                };
+                if let Some(info) = game_over {
+                    *state = NetGameState::GameOver(info);
+                }
+                dirty
+            };
+            for (snd, pan) in sounds {
+                self.sound_engine.play_sound_panned(snd, pan);
+            }
+            if dirty {
+                self.broadcast_state();
+            }
+        }
+
+        // let the host know we're still here, so the computer doesn't take over for us
+        if let GameState::InGame(ref conn_state) = self.state {
+            let now = crate::now();
+            let is_guest = match *conn_state.state.read().unwrap() {
+                NetGameState::Active(ref board) => board.host_id != self.player_id,
+                _ => false,
+            };
+            if is_guest && now - self.last_heartbeat >= 1.0 {
+                self.last_heartbeat = now;
+                conn_state.sender.send(Message::Heartbeat(self.player_id));
+            }
+        }
+
+        // fade the board in when a game starts
+        if old_last_player.is_none() && self.last_player.is_some() {
+            if let Some(duration) = self.view.board_view.settings.intro_duration {
+                anim::STATE.write().unwrap().board_intro.start(duration);
+            }
+        }
+
+        if turn_began(old_last_player, self.last_player, self.player_id) {
             self.sound_engine.play_sound(sound::Sound::YourTurn);
+            anim::STATE.write().unwrap().turn_flash.trigger();
         }
 
         // drain one action at a time
@@ -353,6 +525,42 @@ impl GameController {
         }
     }
 
+    /// Handles mousedown event
+    pub fn on_mousedown(&mut self, event: &web_sys::MouseEvent, main: &web_sys::Element) {
+        if let GameState::InGame(ref mut conn_state) = self.state {
+            let mut state = conn_state.state.write().expect("Failed to lock state");
+            if let NetGameState::Active(ref mut board_controller) = *state {
+                board_controller.on_mousedown(
+                    event,
+                    self.player_id,
+                    &self.view.board_view,
+                    &get_context(main).unwrap_throw(),
+                );
+            }
+        }
+    }
+
+    /// Handles mouseup event
+    pub fn on_mouseup(&mut self, event: &web_sys::MouseEvent, main: &web_sys::Element) {
+        if let GameState::InGame(ref mut conn_state) = self.state {
+            let broadcast = {
+                let mut state = conn_state.state.write().expect("Failed to lock state");
+                if let NetGameState::Active(ref mut board_controller) = *state {
+                    board_controller.on_mouseup(
+                        event,
+                        &self.view.board_view,
+                        &get_context(main).unwrap_throw(),
+                    )
+                } else {
+                    false
+                }
+            };
+            if broadcast {
+                self.broadcast_state();
+            }
+        }
+    }
+
     /// Handles mousemove event
     pub fn on_mousemove(&mut self, event: &web_sys::MouseEvent, main: &web_sys::Element) {
         if let GameState::InGame(ref mut conn_state) = self.state {
@@ -360,12 +568,26 @@ impl GameController {
             let (broadcast, new_state, new_net_state) = {
                 let mut state = state.write().expect("Failed to lock state");
                 if let NetGameState::Active(ref mut board_controller) = *state {
+                    let old_highlight = board_controller.highlighted_tile;
                     let state_dirty = board_controller.on_mousemove(
                         event,
                         self.player_id,
                         &self.view.board_view,
                         &get_context(main).unwrap_throw(),
                     );
+                    // let players hear how far away a tile they could move to is
+                    let new_highlight = board_controller.highlighted_tile;
+                    let moving = match board_controller.turn_state {
+                        TurnState::MoveToken => board_controller.local_turn(self.player_id),
+                        TurnState::InsertTile | TurnState::Animating => false,
+                    };
+                    if moving && new_highlight != old_highlight {
+                        let board = &board_controller.board;
+                        let from = board.player_pos(board_controller.active_player_id());
+                        if let Some(path) = board.shortest_path(from, new_highlight) {
+                            self.sound_engine.play_hover_cue((path.len() as u32).saturating_sub(1));
+                        }
+                    }
                     if state_dirty {
                         if let Some(winner) = board_controller.winner() {
                             let info = GameOverInfo {
@@ -398,6 +620,20 @@ impl GameController {
 
     /// Handles keydown event
     pub fn on_keydown(&mut self, event: &web_sys::KeyboardEvent, _main: &web_sys::Element) {
+        // debug builds can freeze animations and step through them a frame at a time
+        if cfg!(debug_assertions) {
+            match event.code().as_str() {
+                "Pause" => {
+                    anim::set_paused(!anim::is_paused());
+                    return;
+                }
+                "Period" if anim::is_paused() => {
+                    anim::step_once(1.0 / 60.0);
+                    return;
+                }
+                _ => {}
+            }
+        }
         if let GameState::InGame(ref mut conn_state) = self.state {
             let state = &mut conn_state.state;
             let (broadcast, new_state, new_net_state) = {
@@ -447,7 +683,7 @@ impl GameController {
             let sender = &mut conn_state.sender;
             let state = &mut conn_state.state;
             let state = state.read().expect("Failed to lock state");
-            let message = Message::State(state.clone());
+            let message = Message::State(Box::new(state.clone()));
             sender.send(message);
         }
     }
@@ -611,15 +847,32 @@ impl GameController {
                             if score_limit_field.value() != score_limit {
                                 score_limit_field.set_value(&score_limit);
                             }
+
+                            let treasure_count_field: web_sys::HtmlInputElement = named_item(&elements, "treasure_count");
+                            let treasure_count = format!("{}", info.settings.treasure_count);
+                            if treasure_count_field.value() != treasure_count {
+                                treasure_count_field.set_value(&treasure_count);
+                            }
+
+                            let turn_time_limit_field: web_sys::HtmlInputElement = named_item(&elements, "turn_time_limit");
+                            let turn_time_limit = format!("{}", info.settings.turn_time_limit.unwrap_or(0.0));
+                            if turn_time_limit_field.value() != turn_time_limit {
+                                turn_time_limit_field.set_value(&turn_time_limit);
+                            }
                         }
                     }
                     NetGameState::Active(_) => {
                         let canvas: web_sys::HtmlCanvasElement = query_selector(main, "canvas");
                         let window = web_sys::window().unwrap_throw();
-                        let inner_width = window.inner_width().unwrap_throw().as_f64().unwrap_throw() as u32;
-                        let inner_height = window.inner_height().unwrap_throw().as_f64().unwrap_throw() as u32;
-                        canvas.set_width(inner_width);
-                        canvas.set_height(inner_height);
+                        let ratio = window.device_pixel_ratio();
+                        let inner_width = window.inner_width().unwrap_throw().as_f64().unwrap_throw();
+                        let inner_height = window.inner_height().unwrap_throw().as_f64().unwrap_throw();
+                        canvas.set_width((inner_width * ratio) as u32);
+                        canvas.set_height((inner_height * ratio) as u32);
+                        let style = canvas.style();
+                        style.set_property("width", &format!("{}px", inner_width)).unwrap_throw();
+                        style.set_property("height", &format!("{}px", inner_height)).unwrap_throw();
+                        self.view.board_view.settings.device_pixel_ratio = ratio;
                     }
                     _ => {}
                 }
@@ -759,6 +1012,30 @@ impl GameController {
                         listen!(&score_limit, "input", self.set_score_limit(score_limit));
                         score_limit_label.append_with_node_1(&score_limit).unwrap_throw();
 
+                        let treasure_count_label: web_sys::HtmlElement = create_element_with_text(&document, "label", "Treasures");
+                        settings_form.append_with_node_1(&treasure_count_label).unwrap_throw();
+                        let treasure_count: web_sys::HtmlInputElement = create_element(&document, "input");
+                        treasure_count.set_name("treasure_count");
+                        treasure_count.set_type("number");
+                        treasure_count.set_min("0");
+                        treasure_count.set_max("20");
+                        treasure_count.set_step("1");
+                        treasure_count.set_value(&format!("{}", info.settings.treasure_count));
+                        listen!(&treasure_count, "input", self.set_treasure_count(treasure_count));
+                        treasure_count_label.append_with_node_1(&treasure_count).unwrap_throw();
+
+                        let turn_time_limit_label: web_sys::HtmlElement = create_element_with_text(&document, "label", "Turn Time Limit (seconds, 0 for none)");
+                        settings_form.append_with_node_1(&turn_time_limit_label).unwrap_throw();
+                        let turn_time_limit: web_sys::HtmlInputElement = create_element(&document, "input");
+                        turn_time_limit.set_name("turn_time_limit");
+                        turn_time_limit.set_type("number");
+                        turn_time_limit.set_min("0");
+                        turn_time_limit.set_max("300");
+                        turn_time_limit.set_step("5");
+                        turn_time_limit.set_value(&format!("{}", info.settings.turn_time_limit.unwrap_or(0.0)));
+                        listen!(&turn_time_limit, "input", self.set_turn_time_limit(turn_time_limit));
+                        turn_time_limit_label.append_with_node_1(&turn_time_limit).unwrap_throw();
+
                         if is_host {
                             let start: web_sys::HtmlElement = create_element_with_text(&document, "button", "Begin Game");
                             main.append_with_node_1(&start).unwrap_throw();
@@ -826,6 +1103,19 @@ impl GameController {
                 sound.append_with_node_1(&sound_slider).unwrap_throw();
                 main.append_with_node_1(&sound).unwrap_throw();
 
+                let anim_speed: web_sys::Element = create_element(&document, "label");
+                let anim_speed_label = document.create_text_node("Animation Speed");
+                anim_speed.append_with_node_1(&anim_speed_label).unwrap_throw();
+                let anim_speed_slider: web_sys::HtmlInputElement = create_element(&document, "input");
+                anim_speed_slider.set_type("range");
+                anim_speed_slider.set_min("25");
+                anim_speed_slider.set_max("400");
+                anim_speed_slider.set_step("25");
+                anim_speed_slider.set_value(&format!("{}", curr_options.anim_speed));
+                listen!(&anim_speed_slider, "input", self.set_anim_speed(anim_speed_slider));
+                anim_speed.append_with_node_1(&anim_speed_slider).unwrap_throw();
+                main.append_with_node_1(&anim_speed).unwrap_throw();
+
                 let save_button: web_sys::HtmlElement = create_element_with_text(&document, "button", "Save");
                 main.append_with_node_1(&save_button).unwrap_throw();
                 listen!(&save_button, "click", self.save_options());
@@ -843,3 +1133,51 @@ impl Default for GameController {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Direction;
+
+    #[test]
+    fn turn_flash_fires_once_per_turn() {
+        // ticking along with the turn already ours or someone else's stays quiet
+        assert!(turn_began(Some(2), Some(1), 1));
+        assert!(!turn_began(Some(1), Some(1), 1));
+        assert!(!turn_began(Some(1), Some(2), 1));
+        assert!(!turn_began(Some(2), Some(3), 1));
+        assert!(turn_began(None, Some(1), 1));
+        assert!(!turn_began(Some(1), None, 1));
+    }
+
+    #[test]
+    fn endgame_starts_one_target_from_winning() {
+        assert!(!one_target_from_winning(8, 10));
+        assert!(one_target_from_winning(9, 10));
+        assert!(one_target_from_winning(10, 10));
+        // with a single target to win, like the tutorial, nobody starts out close
+        assert!(!one_target_from_winning(0, 1));
+        assert!(one_target_from_winning(1, 2));
+    }
+
+    #[test]
+    fn board_sounds_pan_toward_where_they_happen() {
+        let players = vec![Player::new("A".to_string(), Color(0.5, 0.5, 0.5), 1)];
+        let mut controller = BoardController::new(Default::default(), players, 1);
+        controller.board.move_player(1, (3, 6));
+        let board = &controller.board;
+        let width = board.width();
+
+        let inserted = BoardEvent::TileInserted { dir: Direction::West, idx: 0 };
+        assert_eq!(event_sound(&inserted, board), Some((sound::Sound::Insert, -1.0)));
+        let inserted = BoardEvent::TileInserted { dir: Direction::North, idx: 0 };
+        let pan = sound::column_pan(1, width);
+        assert_eq!(event_sound(&inserted, board), Some((sound::Sound::Insert, pan)));
+
+        let moved = BoardEvent::TokenMoved { player: 1, from: (3, 6), to: (3, 0) };
+        assert_eq!(event_sound(&moved, board), Some((sound::Sound::Move, -1.0)));
+        let collected = BoardEvent::TargetCollected(1);
+        assert_eq!(event_sound(&collected, board), Some((sound::Sound::Pickup, 1.0)));
+        assert_eq!(event_sound(&BoardEvent::TurnStarted(1), board), None);
+    }
+}
@@ -19,13 +19,15 @@ pub enum Message {
     /// Join a lobby
     JoinLobby(Player),
     /// Entire game state
-    State(NetGameState),
+    State(Box<NetGameState>),
     /// Edit player info
     EditPlayer(PlayerID, Player),
     /// Edit game settings
     EditSettings(BoardSettings),
     /// Synchronize animation state
     Anim(anim::AnimSync),
+    /// Let the host know the given player's client is still connected
+    Heartbeat(PlayerID),
 }
 
 impl Into<MetaMessage> for Message {
@@ -47,7 +49,7 @@ fn handle_incoming(
             if let NetGameState::Lobby(ref mut lobby_info) = *state {
                 lobby_info.guests.push(player);
                 if is_host {
-                    return Some(Message::State(state.clone()));
+                    return Some(Message::State(Box::new(state.clone())));
                 }
             }
         }
@@ -63,11 +65,26 @@ fn handle_incoming(
             }
         }
         Message::State(new_state) => {
+            let mut new_state = *new_state;
+            if is_host {
+                if let (NetGameState::Active(old), NetGameState::Active(new)) =
+                    (&*state, &mut new_state)
+                {
+                    new.carry_host_state(old, crate::now());
+                }
+            }
             *state = new_state;
         }
         Message::Anim(sync) => {
             anim::STATE.write().unwrap().apply(sync);
         }
+        Message::Heartbeat(id) => {
+            if let NetGameState::Active(ref mut board) = *state {
+                if is_host && board.note_heard_from(id, crate::now()) {
+                    return Some(Message::State(Box::new(state.clone())));
+                }
+            }
+        }
     }
     None
 }
@@ -10,6 +10,13 @@ use wasm_bindgen::prelude::*;
 pub struct GameOptions {
     pub music_level: u8,
     pub sound_level: u8,
+    pub anim_speed: u16,
+}
+
+impl GameOptions {
+    pub fn anim_speed_multiplier(&self) -> f64 {
+        self.anim_speed as f64 / 100.0
+    }
 }
 
 impl Default for GameOptions {
@@ -17,6 +24,7 @@ impl Default for GameOptions {
         GameOptions {
             music_level: 50,
             sound_level: 50,
+            anim_speed: 100,
         }
     }
 }
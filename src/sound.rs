@@ -2,52 +2,105 @@ use std::collections::HashMap;
 use std::sync::Mutex;
 
 use wasm_bindgen::prelude::*;
-use web_sys::{AudioContext, GainNode, HtmlAudioElement};
+use web_sys::{AudioContext, GainNode, HtmlAudioElement, StereoPannerNode};
 
 use crate::options;
 
 const MUSIC_VOLUME: f32 = 0.6;
 const SOUND_VOLUME: f32 = 0.4;
+/// Shortest time in seconds between hover cues, so sweeping the mouse doesn't spam them
+const HOVER_CUE_INTERVAL: f64 = 0.1;
+/// Where audio lives relative to the page, unless told otherwise
+const DEFAULT_ASSET_BASE: &str = "assets/";
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Music {
     Menu,
     InGame,
+    /// Someone is one target away from winning, which has no track of its own yet
+    Endgame,
+    /// The results screen, which has no track of its own yet
+    Victory,
 }
 
 fn calc_gain(global_scale: f32, options_level: u8) -> f32 {
     global_scale * (f32::from(options_level)) / 100.0
 }
 
+/// Keeps a pan within -1.0 (left) to 1.0 (right)
+fn clamp_pan(pan: f32) -> f32 {
+    let pan = pan.max(-1.0);
+    pan.min(1.0)
+}
+
+/// Pan for a sound from the given column of a board with the given number of columns, from
+/// -1.0 for the leftmost to 1.0 for the rightmost
+pub fn column_pan(col: usize, width: usize) -> f32 {
+    if width < 2 {
+        return 0.0;
+    }
+    clamp_pan(2.0 * col as f32 / (width - 1) as f32 - 1.0)
+}
+
+/// Playback rate of the hover cue for a tile the given number of steps away, higher when farther
+pub fn hover_cue_rate(distance: u32) -> f64 {
+    let rate = 1.0 + 0.1 * f64::from(distance);
+    rate.min(2.0)
+}
+
+fn asset_path(base: &str, file: &str) -> String {
+    if base.is_empty() || base.ends_with('/') {
+        format!("{}{}", base, file)
+    } else {
+        format!("{}/{}", base, file)
+    }
+}
+
 fn ramp_gain(gain: web_sys::AudioParam, value: f32) {
     gain.exponential_ramp_to_value_at_time(value, 0.01).unwrap_throw();
 }
 
 impl Music {
-    fn load(self) -> HtmlAudioElement {
-        let path = match self {
-            Music::Menu => "assets/BlueEther.mp3",
-            Music::InGame => "assets/ElectricSweater.mp3",
+    fn load(self, base: &str) -> HtmlAudioElement {
+        // no endgame or victory tracks have been added, so those replay the in-game and menu
+        // tracks, with the endgame sped up to build tension
+        let (file, rate) = match self {
+            Music::Menu => ("BlueEther.mp3", 1.0),
+            Music::InGame => ("ElectricSweater.mp3", 1.0),
+            Music::Endgame => ("ElectricSweater.mp3", 1.15),
+            Music::Victory => ("BlueEther.mp3", 1.0),
         };
 
-        let result = HtmlAudioElement::new_with_src(path).unwrap_throw();
+        let result = HtmlAudioElement::new_with_src(&asset_path(base, file)).unwrap_throw();
         result.set_loop(true);
+        result.set_playback_rate(rate);
         result
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub enum Sound {
     YourTurn,
+    /// Pitched by distance when hovering a reachable tile
+    HoverCue,
+    /// The loose tile went in
+    Insert,
+    /// A token moved
+    Move,
+    /// A token picked up its target
+    Pickup,
 }
 
 impl Sound {
-    fn load(self) -> HtmlAudioElement {
-        let path = match self {
-            Sound::YourTurn => "assets/TurnPing.wav",
+    fn load(self, base: &str) -> HtmlAudioElement {
+        // there are no dedicated effects for the board yet, so they all reuse the turn ping
+        let file = match self {
+            Sound::YourTurn | Sound::HoverCue | Sound::Insert | Sound::Move | Sound::Pickup => {
+                "TurnPing.wav"
+            }
         };
 
-        HtmlAudioElement::new_with_src(path).unwrap_throw()
+        HtmlAudioElement::new_with_src(&asset_path(base, file)).unwrap_throw()
     }
 }
 
@@ -57,11 +110,19 @@ pub struct SoundEngine {
     sound_sources: Mutex<HashMap<Sound, HtmlAudioElement>>,
     music_gain: GainNode,
     sound_gain: GainNode,
+    sound_panner: StereoPannerNode,
     current_music: Mutex<Option<Music>>,
+    last_hover_cue: Mutex<f64>,
+    asset_base: String,
 }
 
 impl SoundEngine {
     pub fn new() -> SoundEngine {
+        SoundEngine::with_asset_base(DEFAULT_ASSET_BASE)
+    }
+
+    /// Creates a sound engine that loads audio from under the given path
+    pub fn with_asset_base(base: &str) -> SoundEngine {
         let context = AudioContext::new().unwrap_throw();
         let music_gain = context
             .create_gain()
@@ -81,13 +142,23 @@ impl SoundEngine {
         sound_gain
             .connect_with_audio_node(&context.destination())
             .unwrap_throw();
+        // every sound shares one panner, which gets set each time a sound plays
+        let sound_panner = context
+            .create_stereo_panner()
+            .expect_throw("Failed to create sound panner node");
+        sound_panner
+            .connect_with_audio_node(&sound_gain)
+            .unwrap_throw();
         SoundEngine {
             context,
             music_sources: Mutex::new(HashMap::new()),
             sound_sources: Mutex::new(HashMap::new()),
             music_gain,
             sound_gain,
+            sound_panner,
             current_music: Mutex::new(None),
+            last_hover_cue: Mutex::new(0.0),
+            asset_base: base.to_string(),
         }
     }
 
@@ -116,7 +187,7 @@ impl SoundEngine {
             }
         }
         let source = music_sources.entry(music).or_insert_with(|| {
-            let source = music.load();
+            let source = music.load(&self.asset_base);
             let source_node = self
                 .context
                 .create_media_element_source(&source)
@@ -131,22 +202,46 @@ impl SoundEngine {
     }
 
     pub fn play_sound(&self, snd: Sound) {
+        self.play_sound_panned(snd, 0.0);
+    }
+
+    /// Plays a sound from -1.0 (left) to 1.0 (right)
+    pub fn play_sound_panned(&self, snd: Sound, pan: f32) {
+        self.play_sound_with(snd, pan, 1.0);
+    }
+
+    fn play_sound_with(&self, snd: Sound, pan: f32, rate: f64) {
         let _ = self.context.resume();
+        self.sound_panner.pan().set_value(clamp_pan(pan));
         let mut sound_sources = self.sound_sources.lock().unwrap();
         let source = sound_sources.entry(snd).or_insert_with(|| {
-            let source = snd.load();
+            let source = snd.load(&self.asset_base);
             let source_node = self
                 .context
                 .create_media_element_source(&source)
                 .unwrap_throw();
             source_node
-                .connect_with_audio_node(&self.sound_gain)
+                .connect_with_audio_node(&self.sound_panner)
                 .unwrap_throw();
             source
         });
+        source.set_playback_rate(rate);
         let _ = source.play().unwrap_throw();
     }
 
+    /// Plays a cue pitched by how many steps away a hovered tile is, unless one just played
+    pub fn play_hover_cue(&self, distance: u32) {
+        let now = crate::now();
+        {
+            let mut last_hover_cue = self.last_hover_cue.lock().unwrap();
+            if now - *last_hover_cue < HOVER_CUE_INTERVAL {
+                return;
+            }
+            *last_hover_cue = now;
+        }
+        self.play_sound_with(Sound::HoverCue, 0.0, hover_cue_rate(distance));
+    }
+
     pub fn fetch_volume(&self) {
         self.poke_options(&*options::HANDLE.fetch());
     }
@@ -162,3 +257,46 @@ impl Default for SoundEngine {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_is_clamped_to_speakers() {
+        assert_eq!(clamp_pan(0.25), 0.25);
+        assert_eq!(clamp_pan(-1.0), -1.0);
+        assert_eq!(clamp_pan(3.0), 1.0);
+        assert_eq!(clamp_pan(-7.5), -1.0);
+    }
+
+    #[test]
+    fn columns_pan_from_left_to_right() {
+        assert_eq!(column_pan(0, 7), -1.0);
+        assert_eq!(column_pan(3, 7), 0.0);
+        assert_eq!(column_pan(6, 7), 1.0);
+        assert!(column_pan(1, 7) < column_pan(2, 7));
+        // past the edge or on a board one column wide stays within the speakers
+        assert_eq!(column_pan(9, 7), 1.0);
+        assert_eq!(column_pan(0, 1), 0.0);
+    }
+
+    #[test]
+    fn asset_paths_sit_under_the_base() {
+        assert_eq!(asset_path(DEFAULT_ASSET_BASE, "YourTurn.wav"), "assets/YourTurn.wav");
+        let custom = "/games/dynamaze/assets";
+        assert_eq!(asset_path(custom, "x.mp3"), "/games/dynamaze/assets/x.mp3");
+        assert_eq!(asset_path("/games/dynamaze/", "x.mp3"), "/games/dynamaze/x.mp3");
+        assert_eq!(asset_path("", "x.mp3"), "x.mp3");
+    }
+
+    #[test]
+    fn farther_hovers_play_higher() {
+        assert_eq!(hover_cue_rate(0), 1.0);
+        assert!((hover_cue_rate(3) - 1.3).abs() < 1e-9);
+        assert!(hover_cue_rate(4) > hover_cue_rate(3));
+        // far enough away and the pitch stops climbing
+        assert_eq!(hover_cue_rate(10), 2.0);
+        assert_eq!(hover_cue_rate(u32::MAX), 2.0);
+    }
+}
@@ -1,5 +1,6 @@
 //! Tile logic
 
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::f64::consts;
 use std::ops;
@@ -11,7 +12,7 @@ use serde::{Deserialize, Serialize};
 use crate::PlayerID;
 
 /// Cardinal directions
-#[derive(Eq, PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Direction {
     /// Up
     North,
@@ -43,6 +44,31 @@ impl Direction {
             Direction::West,
         ]
     }
+
+    /// Gets the direction facing the other way
+    pub fn opposite(self) -> Direction {
+        self * Direction::South
+    }
+
+    /// Gets the next direction clockwise
+    pub fn rotate_cw(self) -> Direction {
+        self * Direction::East
+    }
+
+    /// Gets the next direction counterclockwise
+    pub fn rotate_ccw(self) -> Direction {
+        self * Direction::West
+    }
+
+    /// Gets the (row, col) change from stepping one tile this way
+    pub fn offset(self) -> (isize, isize) {
+        match self {
+            Direction::North => (-1, 0),
+            Direction::South => (1, 0),
+            Direction::East => (0, 1),
+            Direction::West => (0, -1),
+        }
+    }
 }
 
 impl ops::Add<Direction> for (usize, usize) {
@@ -116,7 +142,7 @@ impl Distribution<Direction> for Standard {
 }
 
 /// Tile shapes
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Shape {
     /// Two connections, 90 degree angle (canonically North / East)
     L,
@@ -141,6 +167,34 @@ impl Shape {
             Shape::T => vec![Direction::West],
         }
     }
+
+    /// Get the orientations that give this shape different paths, as a prefix of `Direction::all()`
+    pub fn distinct_orientations(&self) -> Vec<Direction> {
+        let count = match *self {
+            Shape::L | Shape::T => 4,
+            // turning a straight tile halfway around leaves it unchanged
+            Shape::I => 2,
+        };
+        Direction::all()[..count].to_vec()
+    }
+}
+
+lazy_static! {
+    /// Walls of every shape in every orientation, since drawing needs them for every tile every frame
+    static ref WALLS: HashMap<(Shape, Direction), Vec<Direction>> = {
+        let mut walls = HashMap::new();
+        for shape in &[Shape::L, Shape::I, Shape::T] {
+            for &orientation in Direction::all() {
+                let tile = Tile {
+                    shape: shape.clone(),
+                    orientation,
+                    whose_target: None,
+                };
+                walls.insert((shape.clone(), orientation), tile.walls());
+            }
+        }
+        walls
+    };
 }
 
 impl Distribution<Shape> for Standard {
@@ -155,7 +209,7 @@ impl Distribution<Shape> for Standard {
 }
 
 /// Contents of a tile
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Tile {
     /// Shape of the tile
     pub shape: Shape,
@@ -183,10 +237,25 @@ impl Tile {
             .collect()
     }
 
+    /// Get the directions which are blocked on this tile, without recomputing them
+    pub fn cached_walls(&self) -> &'static [Direction] {
+        &WALLS[&(self.shape.clone(), self.orientation)]
+    }
+
     /// Rotate this tile clockwise
     pub fn rotate(&mut self, direction: Direction) {
         self.orientation = direction * self.orientation;
     }
+
+    /// Swaps the orientation for the equivalent one in `Shape::distinct_orientations`
+    pub fn normalize_orientation(&mut self) {
+        let orientations = self.shape.distinct_orientations();
+        let index = Direction::all()
+            .iter()
+            .position(|&dir| dir == self.orientation)
+            .unwrap_or(0);
+        self.orientation = orientations[index % orientations.len()];
+    }
 }
 
 impl Distribution<Tile> for Standard {
@@ -227,3 +296,76 @@ impl TryFrom<char> for Tile {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets the paths of a tile of the given shape and orientation, in a consistent order
+    fn sorted_paths(shape: &Shape, orientation: Direction) -> Vec<Direction> {
+        let tile = Tile {
+            shape: shape.clone(),
+            orientation,
+            whose_target: None,
+        };
+        let mut paths = tile.paths();
+        paths.sort_by_key(|&dir| dir as u8);
+        paths
+    }
+
+    #[test]
+    fn distinct_orientations_skip_symmetric_turns() {
+        assert_eq!(Shape::I.distinct_orientations().len(), 2);
+        assert_eq!(Shape::L.distinct_orientations().len(), 4);
+        assert_eq!(Shape::T.distinct_orientations().len(), 4);
+        for shape in &[Shape::L, Shape::I, Shape::T] {
+            let distinct = shape
+                .distinct_orientations()
+                .into_iter()
+                .map(|dir| sorted_paths(shape, dir))
+                .collect::<Vec<_>>();
+            // each distinct orientation differs from the others, and together they cover every turn
+            for (i, paths) in distinct.iter().enumerate() {
+                assert!(!distinct[i + 1..].contains(paths));
+            }
+            for &dir in Direction::all() {
+                assert!(distinct.contains(&sorted_paths(shape, dir)));
+            }
+        }
+    }
+
+    #[test]
+    fn direction_helpers_agree_with_the_compass() {
+        use Direction::*;
+        assert_eq!(North.opposite(), South);
+        assert_eq!(East.opposite(), West);
+        assert_eq!(North.rotate_cw(), East);
+        assert_eq!(North.rotate_ccw(), West);
+        for &dir in Direction::all() {
+            assert_eq!(dir.opposite().opposite(), dir);
+            assert_eq!(dir.rotate_cw().rotate_ccw(), dir);
+            let turned = dir.rotate_cw().rotate_cw().rotate_cw().rotate_cw();
+            assert_eq!(turned, dir);
+            let (row, col) = dir.offset();
+            let (back_row, back_col) = dir.opposite().offset();
+            assert_eq!((row + back_row, col + back_col), (0, 0));
+            assert_eq!((1, 1) + dir, ((1 + row) as usize, (1 + col) as usize));
+        }
+        assert_eq!(North.offset(), (-1, 0));
+        assert_eq!(East.offset(), (0, 1));
+    }
+
+    #[test]
+    fn cached_walls_match_computed_walls() {
+        for shape in &[Shape::L, Shape::I, Shape::T] {
+            for &orientation in Direction::all() {
+                let tile = Tile {
+                    shape: shape.clone(),
+                    orientation,
+                    whose_target: None,
+                };
+                assert_eq!(tile.cached_walls(), &tile.walls()[..]);
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Pluggable drawing of tile walls, so tiles can be skinned
+
+use web_sys::CanvasRenderingContext2d as Context;
+
+use crate::{BoardViewSettings, Direction, Tile, WallWidth};
+use crate::geometry::Extents;
+
+/// Draws the walls of a single tile, on top of its background and target
+///
+/// Renderers are handed the tile's extents already centered on the origin, and the fill style
+/// already set to the wall color. A renderer may change any other canvas state it needs, but
+/// should restore it before returning.
+pub trait TileRenderer {
+    /// Draws the given tile's walls within the given extents
+    fn draw_tile(
+        &self,
+        tile: &Tile,
+        outer: &Extents,
+        settings: &BoardViewSettings,
+        ctx: &Context,
+    );
+}
+
+/// Gets the corners, as (north or south, east or west), with none of the given walls on either side
+///
+/// Only these need a block of their own, since each wall already runs the full length of its side.
+pub fn open_corners(walls: &[Direction]) -> Vec<(Direction, Direction)> {
+    let mut result = vec![];
+    for &vertical in &[Direction::North, Direction::South] {
+        for &horizontal in &[Direction::East, Direction::West] {
+            if !walls.contains(&vertical) && !walls.contains(&horizontal) {
+                result.push((vertical, horizontal));
+            }
+        }
+    }
+    result
+}
+
+/// Gets the (x, y, width, height) blocks making up the given walls within the given extents,
+/// with blocks in the open corners too if `always_draw_corners` is set
+fn wall_blocks(
+    walls: &[Direction],
+    outer: &Extents,
+    widths: &WallWidth,
+    always_draw_corners: bool,
+) -> Vec<(f64, f64, f64, f64)> {
+    let cell_size = outer.east - outer.west;
+    let north = cell_size * widths.north;
+    let south = cell_size * widths.south;
+    let east = cell_size * widths.east;
+    let west = cell_size * widths.west;
+    let inner = Extents {
+        north: outer.north + north,
+        south: outer.south - south,
+        east: outer.east - east,
+        west: outer.west + west,
+    };
+
+    let corners = if always_draw_corners {
+        open_corners(walls)
+    } else {
+        vec![]
+    };
+    let mut rects = vec![];
+    for corner in corners {
+        rects.push(match corner {
+            (Direction::North, Direction::West) => (outer.west, outer.north, west, north),
+            (Direction::North, _) => (inner.east, outer.north, east, north),
+            (_, Direction::West) => (outer.west, inner.south, west, south),
+            _ => (inner.east, inner.south, east, south),
+        });
+    }
+    for &d in walls {
+        rects.push(match d {
+            Direction::North => (outer.west, outer.north, cell_size, north),
+            Direction::South => (outer.west, inner.south, cell_size, south),
+            Direction::East => (inner.east, outer.north, east, cell_size),
+            Direction::West => (outer.west, outer.north, west, cell_size),
+        });
+    }
+    rects
+}
+
+/// Draws walls as solid blocks sized by `wall_width`, optionally beveled
+pub struct DefaultTileRenderer;
+
+impl TileRenderer for DefaultTileRenderer {
+    fn draw_tile(
+        &self,
+        tile: &Tile,
+        outer: &Extents,
+        settings: &BoardViewSettings,
+        ctx: &Context,
+    ) {
+        let walls = tile.cached_walls();
+        let rects = wall_blocks(walls, outer, &settings.wall_width, settings.always_draw_corners);
+        for &(x, y, w, h) in &rects {
+            ctx.fill_rect(x, y, w, h);
+        }
+
+        if !settings.beveled_walls {
+            return;
+        }
+        // light comes from the northwest, so blocks are lit on top and left, shaded below and right
+        let (highlight, shadow) = settings.wall_bevel_colors();
+        ctx.save();
+        ctx.set_fill_style(&highlight.into());
+        for &(x, y, w, h) in &rects {
+            let bevel = w.min(h) * 0.2;
+            ctx.fill_rect(x, y, w, bevel);
+            ctx.fill_rect(x, y, bevel, h);
+        }
+        ctx.set_fill_style(&shadow.into());
+        for &(x, y, w, h) in &rects {
+            let bevel = w.min(h) * 0.2;
+            ctx.fill_rect(x, y + h - bevel, w, bevel);
+            ctx.fill_rect(x + w - bevel, y, bevel, h);
+        }
+        ctx.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    /// Gets the walls of the tile drawn as the given box-drawing character
+    fn walls(tile: char) -> Vec<Direction> {
+        Tile::try_from(tile).unwrap().cached_walls().to_vec()
+    }
+
+    #[test]
+    fn open_corners_only_where_no_wall_runs() {
+        // a straight tile's walls run along both sides, past every corner
+        assert_eq!(open_corners(&walls('│')), vec![]);
+        assert_eq!(open_corners(&walls('─')), vec![]);
+        // the ┌ passage turns east to south, leaving walls to the north and west
+        assert_eq!(open_corners(&walls('┌')), vec![(Direction::South, Direction::East)]);
+        let open = vec![(Direction::South, Direction::East), (Direction::South, Direction::West)];
+        assert_eq!(open_corners(&walls('┬')), open);
+        assert_eq!(open_corners(&[]).len(), 4);
+    }
+
+    #[test]
+    fn corner_blocks_are_only_painted_when_asked() {
+        let outer = Extents {
+            north: 0.0,
+            south: 10.0,
+            east: 10.0,
+            west: 0.0,
+        };
+        let widths = WallWidth {
+            north: 0.1,
+            south: 0.1,
+            east: 0.1,
+            west: 0.1,
+        };
+        // the ┬ has a wall to the north, and posts either side of its south passage
+        let north_wall = (0.0, 0.0, 10.0, 1.0);
+        assert_eq!(wall_blocks(&walls('┬'), &outer, &widths, false), vec![north_wall]);
+        let posts = vec![(9.0, 9.0, 1.0, 1.0), (0.0, 9.0, 1.0, 1.0), north_wall];
+        assert_eq!(wall_blocks(&walls('┬'), &outer, &widths, true), posts);
+        // a straight tile has no open corner, so looks the same either way
+        let straight = wall_blocks(&walls('│'), &outer, &widths, true);
+        assert_eq!(straight, wall_blocks(&walls('│'), &outer, &widths, false));
+        assert_eq!(straight.len(), 2);
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::{Arc, RwLock};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{Board, Direction, Player, PlayerID};
+use crate::{Board, Direction, InsertRule, Player, PlayerID};
 use crate::board_controller::{BoardController, BoardSettings};
 use crate::colors;
 use crate::menu::{ConnectedState, NetGameState};
@@ -12,6 +12,10 @@ use crate::net;
 pub fn new_conn_state(player_id: PlayerID) -> ConnectedState {
     let settings = BoardSettings {
         score_limit: 1,
+        treasure_count: 0,
+        turn_time_limit: None,
+        insert_rule: InsertRule::OddLinesOnly,
+        palette: colors::PaletteKind::Default,
         width: 3,
         height: 3,
         version: 0,
@@ -23,7 +27,7 @@ pub fn new_conn_state(player_id: PlayerID) -> ConnectedState {
     )];
     let mut board = BoardController::new(settings, players, player_id);
     TutorialStep::First.apply(&mut board.board);
-    let state = NetGameState::Active(board);
+    let state = NetGameState::Active(Box::new(board));
     let state = Arc::new(RwLock::new(state));
     let sender = net::NetHandler::run_fake();
     ConnectedState { sender, state }
@@ -50,7 +54,7 @@ impl TutorialStep {
                 ",
                 );
                 board.loose_tile = '│'.try_into().unwrap();
-                board.loose_tile_position = (Direction::North, 1);
+                board.loose_tile_position = Some((Direction::North, 1));
                 let players = board.player_tokens.keys().collect::<Vec<_>>();
                 let my_id = *players[0];
                 board.cells[0][6].whose_target = Some(my_id);
@@ -73,7 +77,7 @@ impl TutorialStep {
                 ",
                 );
                 board.loose_tile = '┌'.try_into().unwrap();
-                board.loose_tile_position = (Direction::East, 2);
+                board.loose_tile_position = Some((Direction::East, 2));
                 let players = board.player_tokens.keys().collect::<Vec<_>>();
                 let my_id = *players[0];
                 board.loose_tile.whose_target = Some(my_id);
@@ -96,7 +100,7 @@ impl TutorialStep {
                 ",
                 );
                 board.loose_tile = '─'.try_into().unwrap();
-                board.loose_tile_position = (Direction::North, 2);
+                board.loose_tile_position = Some((Direction::North, 2));
                 let players = board.player_tokens.keys().collect::<Vec<_>>();
                 let my_id = *players[0];
                 board.cells[2][2].whose_target = Some(my_id);